@@ -51,12 +51,18 @@ if [ $? -eq 0 ]; then
 
   rsync -avzr config/mysteriousbot.yml ${SERVICE_USER_NAME}@${SERVICE_HOST}:${SERVICE_USER_DIR}/config/mysteriousbot.yml
 
+  # files replies attach, if there are any
+  if [ -d media ]; then
+    rsync -avzr media/ ${SERVICE_USER_NAME}@${SERVICE_HOST}:${SERVICE_USER_DIR}/media
+  fi
+
   if [[ $fast == false ]]; then
     rsync -avzr target/release/mysteriousbot ${SERVICE_USER_NAME}@${SERVICE_HOST}:${SERVICE_USER_DIR}/mysteriousbot
     ssh ${SERVICE_USER_NAME}@${SERVICE_HOST} chmod +x ${SERVICE_USER_DIR}/mysteriousbot
     ssh ${SERVICE_SU_USER}@${SERVICE_HOST} sudo systemctl start ${SERVICE_NAME}
   else
-    ssh ${SERVICE_SU_USER}@${SERVICE_HOST} sudo systemctl restart ${SERVICE_NAME}
+    # the bot reloads its config on SIGHUP, no need to drop the gateway
+    ssh ${SERVICE_SU_USER}@${SERVICE_HOST} sudo systemctl kill -s HUP ${SERVICE_NAME}
   fi
 else
   echo "Fix your broken build, man."
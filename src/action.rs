@@ -0,0 +1,860 @@
+use std::{collections::HashMap, fmt, sync::OnceLock};
+
+use serde::{de::Error as DeError, Deserialize};
+use serde_with::{formats::PreferOne, serde_as, OneOrMany};
+use serde_yaml::Value;
+use serenity::{
+    all::CreateAllowedMentions,
+    async_trait,
+    model::{
+        id::{ChannelId, GuildId, RoleId, UserId},
+        mention::Mentionable,
+    },
+};
+
+use crate::{
+    config::GuildConfig,
+    counter::EventSource,
+    discord::Discord,
+    handler::Services,
+    leaderboard::{LeaderboardPage, DEFAULT_PAGE_SIZE, MAX_COUNTER_CHARS, MAX_PAGE_SIZE},
+    options::Arguments,
+    reply::{self, RenderedReply, Reply, Selection},
+    template::{guild_name, Placeholder, TemplateValues},
+};
+
+/// Every key actions are written with, in the order the actions they
+/// make are run.
+pub const FIELDS: &[&str] = &[
+    "counter",
+    "decrement_counter",
+    "add_roles",
+    "remove_roles",
+    "reply_messages",
+    "selection",
+    "counter_leaderboard",
+    "leaderboard_page_size",
+    "send_to_channel",
+    "dm_user",
+    "twemojis",
+];
+
+/// Something a command or autoresponder does when it runs.
+#[async_trait]
+pub trait Action: fmt::Debug + Send + Sync {
+    /// Does this action's part, adding anything it replies with or
+    /// reacts with to the output rather than sending it, as only the
+    /// command or autoresponder knows how to.
+    async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput);
+}
+
+/// What actions run against: who they're for and where.
+pub struct ActionContext<'a> {
+    pub discord: &'a dyn Discord,
+    pub services: &'a Services,
+    pub guild_config: &'a GuildConfig,
+    pub guild_id: GuildId,
+    /// Who the actions are for, such as whoever used a command.
+    pub subject: UserId,
+    pub subject_name: String,
+    pub channel_id: ChannelId,
+    /// Where counts come from.
+    pub source: EventSource,
+    /// Identifies the command or autoresponder, for keeping where it is
+    /// in a rotation of replies.
+    pub rotation_key: String,
+    /// Filled into `{1}`, `{name}` and so on in replies.
+    pub indexed: Vec<String>,
+    pub named: HashMap<String, String>,
+    /// The command's arguments, which leaderboards take their period,
+    /// user and limit from. Empty for autoresponders.
+    pub arguments: &'a Arguments,
+}
+
+/// What actions reply and react with, for the command or autoresponder
+/// to send.
+#[derive(Default)]
+pub struct ActionOutput {
+    /// Combined into as few messages as they fit in.
+    pub replies: Vec<RenderedReply>,
+    pub twemojis: Vec<String>,
+    /// Whether any action failed to do what it was meant to, which has
+    /// already been logged.
+    pub failed: bool,
+}
+
+/// Every action of a command or autoresponder. Each is written as a
+/// key or two of its definition, and they run in the order of
+/// [`FIELDS`].
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(try_from = "ActionDefinition")]
+pub struct Actions {
+    pub increment: Option<Count>,
+    pub decrement: Option<Count>,
+    pub add_roles: Option<ChangeRoles>,
+    pub remove_roles: Option<ChangeRoles>,
+    pub reply: Option<Respond>,
+    pub leaderboard: Option<ShowLeaderboard>,
+    pub send_to_channel: Option<SendToChannel>,
+    pub dm_user: Option<DirectMessage>,
+    pub react: Option<React>,
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+struct ActionDefinition {
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    counter: Vec<String>,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    decrement_counter: Vec<String>,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    add_roles: Vec<u64>,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    remove_roles: Vec<u64>,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    reply_messages: Vec<Reply>,
+    #[serde(default)]
+    selection: Selection,
+    counter_leaderboard: Option<String>,
+    leaderboard_page_size: Option<u32>,
+    send_to_channel: Option<SendToChannel>,
+    dm_user: Option<DirectMessage>,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    twemojis: Vec<String>,
+}
+
+impl TryFrom<ActionDefinition> for Actions {
+    type Error = String;
+
+    fn try_from(definition: ActionDefinition) -> Result<Self, String> {
+        if definition.counter_leaderboard.is_none() && definition.leaderboard_page_size.is_some() {
+            return Err("leaderboard_page_size needs a counter_leaderboard".to_owned());
+        }
+
+        let count = |counters: Vec<String>, delta| {
+            (!counters.is_empty()).then_some(Count { counters, delta })
+        };
+        let change_roles =
+            |roles: Vec<u64>, add| (!roles.is_empty()).then_some(ChangeRoles { roles, add });
+
+        Ok(Self {
+            increment: count(definition.counter, 1),
+            decrement: count(definition.decrement_counter, -1),
+            add_roles: change_roles(definition.add_roles, true),
+            remove_roles: change_roles(definition.remove_roles, false),
+            reply: (!definition.reply_messages.is_empty()).then_some(Respond {
+                reply_messages: definition.reply_messages,
+                selection: definition.selection,
+            }),
+            leaderboard: definition
+                .counter_leaderboard
+                .map(|counter| ShowLeaderboard {
+                    counter,
+                    page_size: definition.leaderboard_page_size,
+                }),
+            send_to_channel: definition.send_to_channel,
+            dm_user: definition.dm_user,
+            react: (!definition.twemojis.is_empty()).then_some(React {
+                twemojis: definition.twemojis,
+            }),
+        })
+    }
+}
+
+impl Actions {
+    /// Reads actions written on their own, where any key which isn't
+    /// an action's is an error.
+    pub fn from_value<E: DeError>(value: Value) -> Result<Self, E> {
+        check_fields(&value, FIELDS)?;
+        Actions::deserialize(value).map_err(E::custom)
+    }
+
+    /// Every action, in the order they run.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Action> {
+        let increment = self.increment.iter().map(|action| action as &dyn Action);
+        let decrement = self.decrement.iter().map(|action| action as &dyn Action);
+        let add_roles = self.add_roles.iter().map(|action| action as &dyn Action);
+        let remove_roles = self.remove_roles.iter().map(|action| action as &dyn Action);
+        let reply = self.reply.iter().map(|action| action as &dyn Action);
+        let leaderboard = self.leaderboard.iter().map(|action| action as &dyn Action);
+        let send_to_channel = self
+            .send_to_channel
+            .iter()
+            .map(|action| action as &dyn Action);
+        let dm_user = self.dm_user.iter().map(|action| action as &dyn Action);
+        let react = self.react.iter().map(|action| action as &dyn Action);
+
+        increment
+            .chain(decrement)
+            .chain(add_roles)
+            .chain(remove_roles)
+            .chain(reply)
+            .chain(leaderboard)
+            .chain(send_to_channel)
+            .chain(dm_user)
+            .chain(react)
+    }
+
+    pub async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput) {
+        for action in self.iter() {
+            action.run(context, output).await;
+        }
+    }
+
+    /// Whether there are no actions at all.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// The counters these actions increment.
+    pub fn incremented(&self) -> impl Iterator<Item = &str> {
+        self.increment
+            .iter()
+            .flat_map(|count| count.counters.iter().map(String::as_str))
+    }
+
+    /// Every counter these actions name.
+    pub fn counters(&self) -> impl Iterator<Item = &str> {
+        self.increment
+            .iter()
+            .chain(&self.decrement)
+            .flat_map(|count| count.counters.iter().map(String::as_str))
+            .chain(
+                self.leaderboard
+                    .iter()
+                    .map(|leaderboard| leaderboard.counter.as_str()),
+            )
+    }
+
+    /// Every set of replies, with the key it was written under.
+    pub fn reply_sets(&self) -> impl Iterator<Item = (&'static str, &[Reply], Selection)> {
+        self.reply
+            .iter()
+            .map(|reply| ("", reply.reply_messages.as_slice(), reply.selection))
+            .chain(self.send_to_channel.iter().map(|send| {
+                (
+                    "send_to_channel: ",
+                    send.reply_messages.as_slice(),
+                    send.selection,
+                )
+            }))
+            .chain(
+                self.dm_user
+                    .iter()
+                    .map(|dm| ("dm_user: ", dm.reply_messages.as_slice(), dm.selection)),
+            )
+    }
+
+    /// Checks these actions would do what they're meant to, returning a
+    /// description of each problem found.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if let Some(leaderboard) = &self.leaderboard {
+            if let Some(page_size) = leaderboard.page_size {
+                if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+                    problems.push(format!("leaderboard_page_size must be 1-{}", MAX_PAGE_SIZE));
+                }
+            }
+
+            // the counter goes in the custom id of the page buttons
+            if leaderboard.counter.chars().count() > MAX_COUNTER_CHARS {
+                problems.push(format!(
+                    "counter_leaderboard {} must be at most {} characters",
+                    leaderboard.counter, MAX_COUNTER_CHARS
+                ));
+            }
+        }
+
+        for (label, replies, selection) in self.reply_sets() {
+            if replies.is_empty() {
+                problems.push(format!("{}reply_messages can't be empty", label));
+            }
+
+            for problem in reply::selection_problems(replies, selection)
+                .into_iter()
+                .chain(reply::content_problems(replies))
+            {
+                problems.push(format!("{}{}", label, problem));
+            }
+        }
+
+        problems
+    }
+
+    /// Checks the counters their replies show counts from are among the
+    /// guild's `counters`, returning a description of each problem found.
+    pub fn counter_problems(&self, counters: &[&str]) -> Vec<String> {
+        let mut problems = vec![];
+
+        for (label, replies, _) in self.reply_sets() {
+            for placeholder in replies.iter().flat_map(Reply::placeholders) {
+                if let Placeholder::Counter { counter, .. } = placeholder {
+                    if !counters.contains(&counter.as_str()) {
+                        problems.push(format!(
+                            "{}reply_messages: {{counter:{}}} is not a counter",
+                            label, counter
+                        ));
+                    }
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// The keys of something with keys of its own besides its actions',
+/// such as a command.
+pub struct Fields {
+    own: &'static [&'static str],
+    all: OnceLock<Vec<&'static str>>,
+}
+
+impl Fields {
+    pub const fn with_actions(own: &'static [&'static str]) -> Self {
+        Self {
+            own,
+            all: OnceLock::new(),
+        }
+    }
+
+    /// Its own keys followed by the actions' [`FIELDS`].
+    pub fn all(&'static self) -> &'static [&'static str] {
+        self.all.get_or_init(|| [self.own, FIELDS].concat())
+    }
+}
+
+/// Checks a mapping only has the given keys. Anything with actions
+/// flattens them into its own definition, and serde can't deny unknown
+/// fields through `flatten`, so we check for them ourselves.
+pub fn check_fields<E: DeError>(value: &Value, fields: &'static [&'static str]) -> Result<(), E> {
+    let mapping = match value {
+        Value::Mapping(mapping) => mapping,
+        _ => return Ok(()),
+    };
+
+    for key in mapping.keys() {
+        match key.as_str() {
+            Some(key) if fields.contains(&key) => {}
+            Some(key) => return Err(E::unknown_field(key, fields)),
+            None => return Err(E::custom(format!("unexpected key {:?}", key))),
+        }
+    }
+
+    Ok(())
+}
+
+impl ActionContext<'_> {
+    /// Fills in a reply for the subject.
+    pub async fn render(&self, reply: &Reply) -> RenderedReply {
+        let values = TemplateValues {
+            author: self.subject_name.clone(),
+            author_mention: self.subject.mention().to_string(),
+            channel: self.channel_id.mention().to_string(),
+            guild: match reply.uses_guild() {
+                true => guild_name(self.discord, self.guild_id).await,
+                false => None,
+            },
+            indexed: self.indexed.clone(),
+            named: self.named.clone(),
+            counter: &|counter| {
+                self.services
+                    .counter_factory
+                    .make_counter(
+                        self.guild_config.counter_guild(self.guild_id, counter),
+                        counter,
+                    )
+                    .get(self.subject)
+                    .unwrap_or_default()
+            },
+        };
+
+        reply.render(&values, &self.services.media_dir).await
+    }
+
+    /// Picks one of some replies and fills it in, keeping its place in
+    /// the rotation under the rotation key and a suffix.
+    async fn pick(
+        &self,
+        suffix: &str,
+        replies: &[Reply],
+        selection: Selection,
+    ) -> Option<RenderedReply> {
+        let reply = self.services.rotation_store.pick(
+            self.guild_id,
+            &format!("{}{}", self.rotation_key, suffix),
+            replies,
+            selection,
+            self.services.random.as_ref(),
+        )?;
+
+        Some(self.render(reply).await)
+    }
+}
+
+impl ActionOutput {
+    /// Adds a reply to the last one if they fit in one message, or after
+    /// it to be sent on its own if they don't.
+    pub fn reply(&mut self, reply: RenderedReply) {
+        let reply = match self.replies.last_mut() {
+            Some(last) => last.combine(reply),
+            None => Some(reply),
+        };
+
+        self.replies.extend(reply);
+    }
+}
+
+/// Adds to or takes one from counters for the subject, written as
+/// `counter` or `decrement_counter`. Counts never go below zero.
+#[derive(Debug, PartialEq)]
+pub struct Count {
+    pub counters: Vec<String>,
+    pub delta: i64,
+}
+
+impl Count {
+    /// Takes back what the context's source counted for the subject,
+    /// for each counter it incremented on balance.
+    pub fn take_back(&self, context: &ActionContext<'_>) {
+        for counter in &self.counters {
+            let counter = context.services.counter_factory.make_counter(
+                context
+                    .guild_config
+                    .counter_guild(context.guild_id, counter),
+                counter,
+            );
+            let decremented = counter
+                .delta_from(context.subject, &context.source)
+                .and_then(|delta| match delta > 0 {
+                    true => counter
+                        .decrement(context.subject, &context.source)
+                        .map(drop),
+                    false => Ok(()),
+                });
+
+            if let Err(e) = decremented {
+                log::error!(
+                    "Failed to decrement counter {:?} for user {} with error {:#?}",
+                    counter,
+                    context.subject,
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Action for Count {
+    async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput) {
+        for counter in &self.counters {
+            let counter = context.services.counter_factory.make_counter(
+                context
+                    .guild_config
+                    .counter_guild(context.guild_id, counter),
+                counter,
+            );
+
+            // anything but a single increment goes through add, which
+            // stops at zero
+            let counted = match self.delta {
+                1 => counter.increment(context.subject, &context.source),
+                delta => counter.add(context.subject, delta, &context.source),
+            };
+
+            if let Err(e) = counted {
+                log::error!(
+                    "Failed to add {} to counter {:?} for user {} with error {:#?}",
+                    self.delta,
+                    counter,
+                    context.subject,
+                    e
+                );
+                output.failed = true;
+            }
+        }
+    }
+}
+
+/// Gives the subject roles or takes them away, written as `add_roles`
+/// or `remove_roles`.
+#[derive(Debug, PartialEq)]
+pub struct ChangeRoles {
+    pub roles: Vec<u64>,
+    pub add: bool,
+}
+
+#[async_trait]
+impl Action for ChangeRoles {
+    async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput) {
+        let discord = context.discord;
+
+        for role in &self.roles {
+            let role_id = RoleId::new(*role);
+            let changed = match self.add {
+                true => {
+                    discord
+                        .add_member_role(context.guild_id, context.subject, role_id)
+                        .await
+                }
+                false => {
+                    discord
+                        .remove_member_role(context.guild_id, context.subject, role_id)
+                        .await
+                }
+            };
+
+            if let Err(e) = changed {
+                log::error!(
+                    "Failed to change role {} of user {} with error {:#?}",
+                    role,
+                    context.subject,
+                    e
+                );
+                output.failed = true;
+            }
+        }
+    }
+}
+
+/// Replies with one of `reply_messages`, picked by `selection`.
+#[derive(Debug, PartialEq)]
+pub struct Respond {
+    pub reply_messages: Vec<Reply>,
+    pub selection: Selection,
+}
+
+#[async_trait]
+impl Action for Respond {
+    async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput) {
+        match context.pick("", &self.reply_messages, self.selection).await {
+            Some(reply) => output.reply(reply),
+            None => output.failed = true,
+        }
+    }
+}
+
+/// Replies with a page of a counter's leaderboard, written as
+/// `counter_leaderboard` and `leaderboard_page_size`.
+#[derive(Debug, PartialEq)]
+pub struct ShowLeaderboard {
+    pub counter: String,
+    /// How many rows each page shows, unless the command has a `limit`
+    /// option and it's given.
+    pub page_size: Option<u32>,
+}
+
+#[async_trait]
+impl Action for ShowLeaderboard {
+    async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput) {
+        let arguments = context.arguments;
+        let counter = context.services.counter_factory.make_counter(
+            context
+                .guild_config
+                .counter_guild(context.guild_id, &self.counter),
+            &self.counter,
+        );
+        let page = LeaderboardPage {
+            counter: self.counter.clone(),
+            period: arguments
+                .string("period")
+                .and_then(|period| period.parse().ok())
+                .unwrap_or_default(),
+            subject: arguments.user("user").unwrap_or(context.subject),
+            page_size: arguments
+                .integer("limit")
+                .map(|limit| limit.clamp(1, MAX_PAGE_SIZE as i64) as u32)
+                .or(self.page_size)
+                .unwrap_or(DEFAULT_PAGE_SIZE),
+            page: 0,
+        };
+
+        match page
+            .render_logged(
+                context.discord,
+                context.guild_id,
+                &counter,
+                &context.services.member_cache,
+            )
+            .await
+        {
+            Some(reply) => output.reply(reply),
+            None => output.failed = true,
+        }
+    }
+}
+
+/// Posts one of its `reply_messages` in another channel.
+#[serde_as]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SendToChannel {
+    pub channel: u64,
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    pub reply_messages: Vec<Reply>,
+    #[serde(default)]
+    pub selection: Selection,
+}
+
+#[async_trait]
+impl Action for SendToChannel {
+    async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput) {
+        let reply = match context
+            .pick("/send_to_channel", &self.reply_messages, self.selection)
+            .await
+        {
+            Some(reply) => reply,
+            None => {
+                output.failed = true;
+                return;
+            }
+        };
+        let message = reply
+            .message()
+            .allowed_mentions(CreateAllowedMentions::new().all_users(true));
+
+        if let Err(e) = context
+            .discord
+            .send_message(ChannelId::new(self.channel), message)
+            .await
+        {
+            log::error!(
+                "Failed to send to channel {} with error {:#?}",
+                self.channel,
+                e
+            );
+            output.failed = true;
+        }
+    }
+}
+
+/// Sends the subject one of its `reply_messages` directly.
+#[serde_as]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DirectMessage {
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    pub reply_messages: Vec<Reply>,
+    #[serde(default)]
+    pub selection: Selection,
+}
+
+#[async_trait]
+impl Action for DirectMessage {
+    async fn run(&self, context: &ActionContext<'_>, output: &mut ActionOutput) {
+        let reply = match context
+            .pick("/dm_user", &self.reply_messages, self.selection)
+            .await
+        {
+            Some(reply) => reply,
+            None => {
+                output.failed = true;
+                return;
+            }
+        };
+
+        if let Err(e) = context
+            .discord
+            .direct_message(context.subject, reply.message())
+            .await
+        {
+            log::error!(
+                "Failed to message user {} with error {:#?}",
+                context.subject,
+                e
+            );
+            output.failed = true;
+        }
+    }
+}
+
+/// Reacts with `twemojis`, to the message that set off an autoresponder
+/// or to a command's response.
+#[derive(Debug, PartialEq)]
+pub struct React {
+    pub twemojis: Vec<String>,
+}
+
+#[async_trait]
+impl Action for React {
+    async fn run(&self, _context: &ActionContext<'_>, output: &mut ActionOutput) {
+        output.twemojis.extend(self.twemojis.iter().cloned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use serde_yaml::Value;
+    use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+
+    use super::{ActionContext, ActionOutput, Actions, Count};
+    use crate::{
+        counter::{tests::memory_pool, EventSource},
+        discord::tests::Call,
+        handler::tests::{config, discord, services},
+        options::Arguments,
+    };
+
+    #[test]
+    fn actions_deserialization() {
+        let yaml = r#"---
+        twemojis: clarus
+        counter: clarus
+        decrement_counter:
+          - heresy
+          - doubt
+        add_roles: 1
+        dm_user:
+          reply_messages: welcome to the faith"#;
+        let actions: Actions = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            Some(Count {
+                counters: vec!["heresy".to_owned(), "doubt".to_owned()],
+                delta: -1
+            }),
+            actions.decrement
+        );
+        assert_eq!(vec!["clarus"], actions.incremented().collect::<Vec<_>>());
+        assert_eq!(
+            vec!["clarus", "heresy", "doubt"],
+            actions.counters().collect::<Vec<_>>()
+        );
+        assert!(actions.problems().is_empty());
+
+        assert!(Actions::default().is_empty());
+        assert!(serde_yaml::from_str::<Actions>("leaderboard_page_size: 5").is_err());
+
+        let value: Value = serde_yaml::from_str("reply_message: oops").unwrap();
+        let e = Actions::from_value::<serde_yaml::Error>(value).unwrap_err();
+        assert!(e.to_string().contains("unknown field `reply_message`"));
+    }
+
+    #[tokio::test]
+    async fn actions_run_in_order() {
+        // written in the reverse of the order they run in
+        let yaml = r#"---
+        twemojis: clarus
+        dm_user:
+          reply_messages: welcome to the faith
+        send_to_channel:
+          channel: 100
+          reply_messages: "{author} has joined the faith"
+        remove_roles: 2
+        add_roles: 1
+        decrement_counter: heresy
+        counter: [clarus, heresy]"#;
+        let actions: Actions = serde_yaml::from_str(yaml).unwrap();
+        let config = config("    shared_counters: {}");
+        let services = services(memory_pool());
+        let discord = discord();
+        let arguments = Arguments::default();
+        let (guild_id, subject) = (GuildId::new(1), UserId::new(10));
+        let context = ActionContext {
+            discord: &discord,
+            services: &services,
+            guild_config: &config.guilds[&1],
+            guild_id,
+            subject,
+            subject_name: "Skorpion Medion".to_owned(),
+            channel_id: ChannelId::new(100),
+            source: EventSource {
+                channel_id: ChannelId::new(100),
+                message_id: None,
+                origin: None,
+            },
+            rotation_key: "/faith".to_owned(),
+            indexed: vec![],
+            named: Default::default(),
+            arguments: &arguments,
+        };
+        let mut output = ActionOutput::default();
+
+        for action in actions.iter() {
+            action.run(&context, &mut output).await;
+        }
+
+        let count = |counter| {
+            services
+                .counter_factory
+                .make_counter(guild_id, counter)
+                .get(subject)
+                .unwrap()
+        };
+        // heresy is counted before it's taken back, so there's one to take
+        assert_eq!((1, 0), (count("clarus"), count("heresy")));
+        assert_eq!(vec!["clarus"], output.twemojis);
+        let calls = discord.take_calls();
+        assert_eq!(4, calls.len(), "{:?}", calls);
+        assert_eq!(
+            Call::AddMemberRole {
+                guild_id,
+                user_id: subject,
+                role_id: RoleId::new(1),
+            },
+            calls[0]
+        );
+        assert_eq!(
+            Call::RemoveMemberRole {
+                guild_id,
+                user_id: subject,
+                role_id: RoleId::new(2),
+            },
+            calls[1]
+        );
+        match &calls[2] {
+            Call::SendMessage {
+                channel_id,
+                message,
+            } => {
+                assert_eq!(ChannelId::new(100), *channel_id);
+                assert_eq!(
+                    json!("Skorpion Medion has joined the faith"),
+                    message["content"]
+                );
+            }
+            call => panic!("expected a post, got {:?}", call),
+        }
+        match &calls[3] {
+            Call::DirectMessage { user_id, message } => {
+                assert_eq!(subject, *user_id);
+                assert_eq!(json!("welcome to the faith"), message["content"]);
+            }
+            call => panic!("expected a DM, got {:?}", call),
+        }
+    }
+
+    #[test]
+    fn action_problems() {
+        let yaml = r#"---
+        counter_leaderboard: a_counter_with_a_name_well_past_forty_characters
+        leaderboard_page_size: 0
+        send_to_channel:
+          channel: 1
+          reply_messages: []
+        dm_user:
+          reply_messages:
+            - text: hello
+              weight: 2
+          selection: sequential"#;
+        let actions: Actions = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            vec![
+                "leaderboard_page_size must be 1-25",
+                "counter_leaderboard a_counter_with_a_name_well_past_forty_characters must be \
+                    at most 40 characters",
+                "send_to_channel: reply_messages can't be empty",
+                "dm_user: reply_messages: weights only matter when selection is random",
+            ],
+            actions.problems()
+        );
+    }
+}
@@ -1,47 +1,593 @@
 use std::{
+    collections::HashMap,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
-use rand::prelude::SliceRandom;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{de::Error as DeError, Deserialize, Deserializer};
 use serde_with::{formats::PreferOne, serde_as, DisplayFromStr, DurationSeconds, OneOrMany};
+use serde_yaml::Value;
 use serenity::{
-    client::Context,
-    model::{channel::Message, id::GuildId},
+    all::CreateAllowedMentions,
+    model::{
+        channel::{Message, Reaction, ReactionType},
+        id::{GuildId, UserId},
+    },
 };
-use tokio::sync::Mutex;
 
-use crate::{counter::CounterFactory, emojicache::EmojiCache};
+use crate::{
+    action::{Action, ActionContext, ActionOutput, Actions, Fields},
+    config::GuildConfig,
+    cooldown::{CooldownLimit, CooldownOwner, CooldownScope, CooldownStore, Cooldowns},
+    counter::EventSource,
+    discord::Discord,
+    emojicache::EmojiCache,
+    handler::Services,
+    options::Arguments,
+    random::Random,
+    reply::Reply,
+    template::Placeholder,
+};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 pub struct Autoresponder {
+    /// The definition this autoresponder was parsed from, kept so that
+    /// a reloaded config can tell which autoresponders are unchanged.
+    definition: Value,
+    /// Identifies this autoresponder across restarts, for keeping its
+    /// cooldowns: its `id` if it has one, or a hash of its definition.
+    id: String,
+    trigger: AutoresponderTrigger,
+    filter: AutoresponderFilter,
+    action: Actions,
+}
+
+#[derive(Deserialize)]
+struct AutoresponderParts {
+    #[serde(default)]
+    id: Option<String>,
     #[serde(flatten)]
     trigger: AutoresponderTrigger,
     #[serde(flatten)]
     filter: AutoresponderFilter,
     #[serde(flatten)]
-    action: AutoresponderAction,
+    action: Actions,
+}
+
+/// Every key an autoresponder understands.
+static FIELDS: Fields = Fields::with_actions(&[
+    "id",
+    // triggers, see TRIGGERS
+    "message_matches",
+    "user_message",
+    "user_mentioned",
+    "in_channels",
+    "reaction_added",
+    "all",
+    "any",
+    "not",
+    // filters
+    "only_in_channels",
+    "chance",
+    "cooldown",
+    "cooldown_scope",
+    "on_cooldown",
+]);
+
+/// Every key a trigger understands, at the top of an autoresponder or
+/// nested inside `all`, `any` or `not`.
+const TRIGGERS: &[&str] = &[
+    "message_matches",
+    "user_message",
+    "user_mentioned",
+    "in_channels",
+    "reaction_added",
+    "all",
+    "any",
+    "not",
+];
+
+impl<'de> Deserialize<'de> for Autoresponder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let definition = Value::deserialize(deserializer)?;
+
+        check_keys(&definition, FIELDS.all())?;
+
+        let parts =
+            AutoresponderParts::deserialize(definition.clone()).map_err(D::Error::custom)?;
+
+        let id = match parts.id {
+            Some(id) => id,
+            None => {
+                let canonical = serde_yaml::to_string(&definition).map_err(D::Error::custom)?;
+                format!("{:016x}", stable_hash(&canonical))
+            }
+        };
+
+        Ok(Self {
+            definition,
+            id,
+            trigger: parts.trigger,
+            filter: parts.filter,
+            action: parts.action,
+        })
+    }
+}
+
+/// FNV-1a, which unlike the standard library's hasher is guaranteed to
+/// give the same hash from one build to the next.
+fn stable_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Checks a mapping only has the given keys and at most one trigger,
+/// recursing into nested triggers. The untagged trigger enum would
+/// otherwise quietly pick one trigger and ignore the rest.
+fn check_keys<E: DeError>(value: &Value, fields: &'static [&'static str]) -> Result<(), E> {
+    let mapping = match value {
+        Value::Mapping(mapping) => mapping,
+        _ => return Ok(()),
+    };
+    let mut triggers = 0;
+
+    for (key, value) in mapping {
+        let key = match key.as_str() {
+            Some(key) if fields.contains(&key) => key,
+            Some(key) => return Err(E::unknown_field(key, fields)),
+            None => return Err(E::custom(format!("unexpected key {:?}", key))),
+        };
+
+        if !TRIGGERS.contains(&key) {
+            continue;
+        }
+
+        triggers += 1;
+
+        match (key, value) {
+            ("all" | "any", Value::Sequence(triggers)) => {
+                for trigger in triggers {
+                    check_keys(trigger, TRIGGERS)?;
+                }
+            }
+            ("not", trigger) => check_keys(trigger, TRIGGERS)?,
+            _ => {}
+        }
+    }
+
+    if triggers > 1 {
+        return Err(E::custom(
+            "more than one trigger, combine them with all or any",
+        ));
+    }
+
+    Ok(())
 }
 
 impl Autoresponder {
+    /// Whether this autoresponder was parsed from the same definition
+    /// as another one.
+    pub fn same_definition(&self, other: &Autoresponder) -> bool {
+        self.definition == other.definition
+    }
+
+    /// Identifies this autoresponder across restarts.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The counters this autoresponder increments.
+    pub fn incremented(&self) -> impl Iterator<Item = &str> {
+        self.action.incremented()
+    }
+
+    /// Every counter this autoresponder names.
+    pub fn counters(&self) -> impl Iterator<Item = &str> {
+        self.action.counters()
+    }
+
+    /// Whether this autoresponder does nothing when it triggers.
+    pub fn is_noop(&self) -> bool {
+        self.action.is_empty()
+    }
+
+    /// Whether this autoresponder runs on reactions rather than on
+    /// messages.
+    pub fn on_reactions(&self) -> bool {
+        !self.trigger.reactions().is_empty()
+    }
+
+    /// Shares the cooldown state of a previous incarnation of this
+    /// autoresponder, so a config reload doesn't re-arm it.
+    pub fn adopt_state(&mut self, previous: &Autoresponder) {
+        self.filter.cooldowns = previous.filter.cooldowns.clone();
+    }
+
     pub async fn handle(
         &self,
-        emojicache: &EmojiCache,
-        counter_factory: &CounterFactory,
-        context: &Context,
+        services: &Services,
+        discord: &dyn Discord,
+        message: &Message,
+        guild_id: &GuildId,
+        guild_config: &GuildConfig,
+    ) {
+        if self.on_reactions() {
+            return;
+        }
+
+        let event = Event {
+            message,
+            subject: message.author.id,
+            subject_name: message_author_name(message),
+            reaction: None,
+        };
+
+        self.handle_event(services, discord, &event, guild_id, guild_config)
+            .await;
+    }
+
+    /// Handles a reaction being added to a message, which runs this
+    /// autoresponder if one of its `reaction_added` triggers matches.
+    pub async fn handle_reaction(
+        &self,
+        services: &Services,
+        discord: &dyn Discord,
+        reaction: &Reaction,
+        message: &Message,
+        guild_id: &GuildId,
+        guild_config: &GuildConfig,
+    ) {
+        let event = match self.reaction_event(discord, reaction, message).await {
+            Some(event) => event,
+            None => return,
+        };
+
+        self.handle_event(services, discord, &event, guild_id, guild_config)
+            .await;
+    }
+
+    /// Handles a reaction being removed from a message, which takes back
+    /// what it counted if its trigger says to. Chance and cooldowns
+    /// aren't considered, but only a count the message actually got is
+    /// ever taken back.
+    pub async fn handle_reaction_removed(
+        &self,
+        services: &Services,
+        discord: &dyn Discord,
+        reaction: &Reaction,
         message: &Message,
         guild_id: &GuildId,
+        guild_config: &GuildConfig,
     ) {
-        if self.trigger.should_run(context, message) && self.filter.should_run(message).await {
-            self.action
-                .run(emojicache, counter_factory, context, guild_id, message)
-                .await;
+        let decrement_on_remove = self
+            .trigger
+            .reaction(&reaction.emoji)
+            .is_some_and(|trigger| trigger.decrement_on_remove);
+
+        if !decrement_on_remove {
+            return;
+        }
+
+        let event = match self.reaction_event(discord, reaction, message).await {
+            Some(event) => event,
+            None => return,
+        };
+        let content = discord.content_safe(message);
+
+        if self.trigger.should_run(&event.input(&content))
+            && self.filter.allows_channel(message.channel_id.get())
+        {
+            if let Some(increment) = &self.action.increment {
+                let arguments = Arguments::default();
+                let action_context = self.action_context(
+                    services,
+                    discord,
+                    guild_id,
+                    guild_config,
+                    &event,
+                    Captures::default(),
+                    &arguments,
+                );
+
+                increment.take_back(&action_context);
+            }
         }
     }
+
+    /// The event for a reaction to a message, if one of this
+    /// autoresponder's `reaction_added` triggers is for its emoji.
+    async fn reaction_event<'a>(
+        &self,
+        discord: &dyn Discord,
+        reaction: &'a Reaction,
+        message: &'a Message,
+    ) -> Option<Event<'a>> {
+        let trigger = self.trigger.reaction(&reaction.emoji)?;
+        let (subject, subject_name) = match trigger.target {
+            ReactionTarget::Author => (message.author.id, message_author_name(message)),
+            ReactionTarget::Reactor => {
+                let subject_name = match &reaction.member {
+                    Some(member) => member.display_name().to_owned(),
+                    None => match discord.user(reaction.user_id?).await {
+                        Ok(user) => user.display_name().to_owned(),
+                        Err(e) => {
+                            log::error!(
+                                "Failed to get user info for reaction {:?} with error {:#?}",
+                                reaction,
+                                e
+                            );
+                            return None;
+                        }
+                    },
+                };
+
+                (reaction.user_id?, subject_name)
+            }
+        };
+
+        Some(Event {
+            message,
+            subject,
+            subject_name,
+            reaction: Some(&reaction.emoji),
+        })
+    }
+
+    async fn handle_event(
+        &self,
+        services: &Services,
+        discord: &dyn Discord,
+        event: &Event<'_>,
+        guild_id: &GuildId,
+        guild_config: &GuildConfig,
+    ) {
+        let content = discord.content_safe(event.message);
+
+        if !self.trigger.should_run(&event.input(&content)) {
+            return;
+        }
+
+        let owner = CooldownOwner {
+            guild_id: *guild_id,
+            id: &self.id,
+        };
+
+        match self
+            .filter
+            .check(
+                &services.cooldown_store,
+                owner,
+                services.random.as_ref(),
+                event,
+            )
+            .await
+        {
+            FilterResult::Pass => {
+                let captures = self.trigger.captures(&content);
+
+                self.run(services, discord, guild_id, guild_config, event, captures)
+                    .await;
+            }
+            FilterResult::OnCooldown => match &self.filter.on_cooldown {
+                OnCooldown::Ignore => {}
+                OnCooldown::React(twemoji) => {
+                    react(
+                        &services.emoji_cache,
+                        discord,
+                        guild_id,
+                        event.message,
+                        twemoji,
+                    )
+                    .await
+                }
+                OnCooldown::Count => {
+                    if let Some(increment) = &self.action.increment {
+                        let arguments = Arguments::default();
+                        let action_context = self.action_context(
+                            services,
+                            discord,
+                            guild_id,
+                            guild_config,
+                            event,
+                            Captures::default(),
+                            &arguments,
+                        );
+
+                        increment
+                            .run(&action_context, &mut ActionOutput::default())
+                            .await;
+                    }
+                }
+            },
+            FilterResult::Filtered => {}
+        }
+    }
+
+    /// What this autoresponder's actions run against for an event.
+    #[allow(clippy::too_many_arguments)]
+    fn action_context<'a>(
+        &self,
+        services: &'a Services,
+        discord: &'a dyn Discord,
+        guild_id: &GuildId,
+        guild_config: &'a GuildConfig,
+        event: &Event<'_>,
+        captures: Captures,
+        arguments: &'a Arguments,
+    ) -> ActionContext<'a> {
+        ActionContext {
+            discord,
+            services,
+            guild_config,
+            guild_id: *guild_id,
+            subject: event.subject,
+            subject_name: event.subject_name.clone(),
+            channel_id: event.message.channel_id,
+            source: event.source(&self.id),
+            rotation_key: self.id.clone(),
+            indexed: captures.indexed,
+            named: captures.named,
+            arguments,
+        }
+    }
+
+    /// Runs this autoresponder's actions in response to an event,
+    /// replying to and reacting to its message.
+    async fn run(
+        &self,
+        services: &Services,
+        discord: &dyn Discord,
+        guild_id: &GuildId,
+        guild_config: &GuildConfig,
+        event: &Event<'_>,
+        captures: Captures,
+    ) {
+        let message = event.message;
+        let arguments = Arguments::default();
+        let action_context = self.action_context(
+            services,
+            discord,
+            guild_id,
+            guild_config,
+            event,
+            captures,
+            &arguments,
+        );
+        let mut output = ActionOutput::default();
+
+        self.action.run(&action_context, &mut output).await;
+
+        for twemoji in &output.twemojis {
+            react(&services.emoji_cache, discord, guild_id, message, twemoji).await;
+        }
+
+        for reply in output.replies {
+            // the same as message.reply, which only takes plain content
+            let reply_message = reply.message().reference_message(message).allowed_mentions(
+                CreateAllowedMentions::new()
+                    .replied_user(false)
+                    .everyone(true)
+                    .all_users(true)
+                    .all_roles(true),
+            );
+
+            if let Err(why) = discord
+                .send_message(message.channel_id, reply_message)
+                .await
+            {
+                log::error!("Failed to autoreply to message with reason {:?}", why);
+            }
+        }
+    }
+
+    /// Checks this autoresponder's chance and cooldown settings make
+    /// sense, returning a description of each problem found.
+    pub fn filter_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if let Some(chance) = self.filter.chance {
+            if !(chance > 0.0 && chance <= 1.0) {
+                problems.push(format!("chance {} must be above 0 and at most 1", chance));
+            }
+        }
+
+        if self.filter.cooldown.is_zero() {
+            if self.filter.cooldown_scope != CooldownScope::Global {
+                problems.push("cooldown_scope does nothing without a cooldown".to_owned());
+            }
+
+            if self.filter.on_cooldown != OnCooldown::Ignore {
+                problems.push("on_cooldown does nothing without a cooldown".to_owned());
+            }
+        }
+
+        if self.filter.on_cooldown == OnCooldown::Count && self.action.increment.is_none() {
+            problems.push("on_cooldown is count but there is no counter".to_owned());
+        }
+
+        problems
+    }
+
+    /// Checks this autoresponder's triggers make sense, returning a
+    /// description of each problem found.
+    pub fn trigger_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        // an empty all always runs and an empty any never does, neither
+        // of which anyone means to write
+        for key in self.trigger.empty_combinations() {
+            problems.push(format!("{} needs at least one trigger", key));
+        }
+
+        if self.trigger.negates_reaction() {
+            problems.push("reaction_added can't be under not".to_owned());
+        }
+
+        if self
+            .trigger
+            .reactions()
+            .iter()
+            .any(|trigger| trigger.decrement_on_remove)
+            && self.action.increment.is_none()
+        {
+            problems.push("reaction_added decrements on remove but there is no counter".to_owned());
+        }
+
+        problems
+    }
+
+    /// Checks this autoresponder's replies only use captures its regexes
+    /// have, returning a description of each problem found.
+    pub fn reply_problems(&self) -> Vec<String> {
+        let regexes = self.trigger.regexes();
+        let mut problems = vec![];
+
+        for (label, replies, _) in self.action.reply_sets() {
+            for placeholder in replies.iter().flat_map(Reply::placeholders) {
+                match placeholder {
+                    Placeholder::Indexed(i)
+                        if !regexes.iter().any(|regex| regex.captures_len() > *i) =>
+                    {
+                        problems.push(format!(
+                            "{}reply_messages: {{{}}} is not a capture group of message_matches",
+                            label, i
+                        ));
+                    }
+                    Placeholder::Named(name)
+                        if !regexes
+                            .iter()
+                            .any(|regex| regex.capture_names().any(|n| n == Some(name))) =>
+                    {
+                        problems.push(format!(
+                            "{}reply_messages: {{{}}} is not a capture group of message_matches",
+                            label, name
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Checks this autoresponder's actions would do what they're meant
+    /// to, returning a description of each problem found.
+    pub fn action_problems(&self) -> Vec<String> {
+        self.action.problems()
+    }
+
+    /// Checks the counters this autoresponder's replies show are among
+    /// the guild's `counters`.
+    pub fn counter_problems(&self, counters: &[&str]) -> Vec<String> {
+        self.action.counter_problems(counters)
+    }
 }
 
+/// What an autoresponder triggers on, either one of the leaf triggers
+/// or a combination of other triggers with `all`, `any` or `not`.
 #[serde_as]
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
@@ -58,132 +604,397 @@ pub enum AutoresponderTrigger {
         #[serde_as(as = "OneOrMany<_, PreferOne>")]
         user_mentioned: Vec<u64>,
     },
+    InChannels {
+        #[serde_as(as = "OneOrMany<_, PreferOne>")]
+        in_channels: Vec<u64>,
+    },
+    ReactionAdded {
+        reaction_added: ReactionTrigger,
+    },
+    All {
+        all: Vec<AutoresponderTrigger>,
+    },
+    Any {
+        any: Vec<AutoresponderTrigger>,
+    },
+    Not {
+        not: Box<AutoresponderTrigger>,
+    },
+}
+
+/// Triggers on reactions with one of the given emojis. An autoresponder
+/// with one runs on reactions instead of messages, and its other
+/// triggers look at the message which was reacted to.
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReactionTrigger {
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    emoji: Vec<ReactionEmoji>,
+    #[serde(default)]
+    target: ReactionTarget,
+    /// Whether removing the reaction takes back what adding it counted.
+    #[serde(default)]
+    decrement_on_remove: bool,
+}
+
+/// A unicode emoji or a custom emoji's name, or a custom emoji's id.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ReactionEmoji {
+    Id(u64),
+    Name(String),
+}
+
+/// Who a reaction autoresponder acts on behalf of: who is counted, who
+/// `{author}` names and whose cooldown it is.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ReactionTarget {
+    /// Whoever wrote the message which was reacted to.
+    #[default]
+    Author,
+    /// Whoever reacted.
+    Reactor,
+}
+
+impl ReactionTrigger {
+    fn matches(&self, emoji: &ReactionType) -> bool {
+        self.emoji
+            .iter()
+            .any(|trigger_emoji| match (trigger_emoji, emoji) {
+                (ReactionEmoji::Id(id), ReactionType::Custom { id: emoji_id, .. }) => {
+                    emoji_id.get() == *id
+                }
+                (
+                    ReactionEmoji::Name(name),
+                    ReactionType::Custom {
+                        name: Some(emoji_name),
+                        ..
+                    },
+                )
+                | (ReactionEmoji::Name(name), ReactionType::Unicode(emoji_name)) => {
+                    emoji_name == name
+                }
+                _ => false,
+            })
+    }
+}
+
+/// What set an autoresponder off: a message, or a reaction to one.
+struct Event<'a> {
+    message: &'a Message,
+    /// Who the autoresponder acts on behalf of.
+    subject: UserId,
+    subject_name: String,
+    reaction: Option<&'a ReactionType>,
+}
+
+impl Event<'_> {
+    fn input<'a>(&'a self, content: &'a str) -> TriggerInput<'a> {
+        TriggerInput {
+            content,
+            author_id: self.message.author.id.get(),
+            channel_id: self.message.channel_id.get(),
+            mentions: self
+                .message
+                .mentions
+                .iter()
+                .map(|user| user.id.get())
+                .collect(),
+            reaction: self.reaction,
+        }
+    }
+
+    /// Where the counts an autoresponder makes for an event come from.
+    fn source(&self, autoresponder_id: &str) -> EventSource {
+        EventSource {
+            channel_id: self.message.channel_id,
+            message_id: Some(self.message.id),
+            origin: Some(autoresponder_id.to_owned()),
+        }
+    }
+}
+
+/// The name `{author}` gives the author of a message.
+fn message_author_name(message: &Message) -> String {
+    message
+        .member
+        .as_ref()
+        .and_then(|member| member.nick.as_deref())
+        .unwrap_or(message.author.display_name())
+        .to_owned()
+}
+
+/// The parts of a message triggers look at.
+struct TriggerInput<'a> {
+    content: &'a str,
+    author_id: u64,
+    channel_id: u64,
+    mentions: Vec<u64>,
+    /// The emoji of the reaction to the message, if that's what
+    /// happened to it.
+    reaction: Option<&'a ReactionType>,
 }
 
 impl AutoresponderTrigger {
-    fn should_run(&self, context: &Context, message: &Message) -> bool {
+    fn should_run(&self, input: &TriggerInput) -> bool {
         match self {
-            Self::MessageMatches { message_matches } => {
-                let message_content = message.content_safe(context);
-                message_matches
-                    .iter()
-                    .any(|regex| regex.is_match(&message_content))
-            }
-            Self::UserMessage { user_message } => user_message
+            Self::MessageMatches { message_matches } => message_matches
                 .iter()
-                .any(|user_id| user_id == &message.author.id.get()),
+                .any(|regex| regex.is_match(input.content)),
+            Self::UserMessage { user_message } => user_message.contains(&input.author_id),
             Self::UserMentioned { user_mentioned } => user_mentioned
                 .iter()
-                .any(|user_id| message.mentions_user_id(*user_id)),
+                .any(|user_id| input.mentions.contains(user_id)),
+            Self::InChannels { in_channels } => in_channels.contains(&input.channel_id),
+            Self::ReactionAdded { reaction_added } => input
+                .reaction
+                .is_some_and(|emoji| reaction_added.matches(emoji)),
+            Self::All { all } => all.iter().all(|trigger| trigger.should_run(input)),
+            Self::Any { any } => any.iter().any(|trigger| trigger.should_run(input)),
+            Self::Not { not } => !not.should_run(input),
         }
     }
-}
 
-#[serde_as]
-#[derive(Debug, Deserialize)]
-pub struct AutoresponderFilter {
-    #[serde(default)]
-    #[serde_as(as = "OneOrMany<_, PreferOne>")]
-    only_in_channels: Vec<u64>,
-    #[serde(default = "default_cooldown")]
-    #[serde_as(as = "DurationSeconds<u64>")]
-    cooldown: Duration,
-    #[serde(skip, default = "default_system_time")]
-    last_triggered: Arc<Mutex<SystemTime>>,
-}
-
-impl AutoresponderFilter {
-    async fn should_run(&self, message: &Message) -> bool {
-        // basic channel filter
-        if self.only_in_channels.len() > 0
-            && !self.only_in_channels.contains(&message.channel_id.get())
-        {
-            return false;
+    /// Every regex whose captures can fill in a template, which leaves
+    /// out those under a `not` as they never match when it triggers.
+    fn regexes(&self) -> Vec<&Regex> {
+        match self {
+            Self::MessageMatches { message_matches } => message_matches.iter().collect(),
+            Self::All { all: triggers } | Self::Any { any: triggers } => triggers
+                .iter()
+                .flat_map(|trigger| trigger.regexes())
+                .collect(),
+            _ => vec![],
         }
+    }
 
-        let now = SystemTime::now();
+    /// Every reaction trigger, which leaves out those under a `not` as
+    /// they don't make it run on reactions.
+    fn reactions(&self) -> Vec<&ReactionTrigger> {
+        match self {
+            Self::ReactionAdded { reaction_added } => vec![reaction_added],
+            Self::All { all: triggers } | Self::Any { any: triggers } => triggers
+                .iter()
+                .flat_map(|trigger| trigger.reactions())
+                .collect(),
+            _ => vec![],
+        }
+    }
 
-        let mut last_triggered = self.last_triggered.lock().await;
-        let t = now.duration_since(*last_triggered).unwrap_or_default();
+    /// The first reaction trigger for the given emoji.
+    fn reaction(&self, emoji: &ReactionType) -> Option<&ReactionTrigger> {
+        self.reactions()
+            .into_iter()
+            .find(|trigger| trigger.matches(emoji))
+    }
 
-        // reject if now is within cooldown of the last triggering
-        if t < self.cooldown {
-            return false;
+    /// The keys of every `all` and `any` with nothing in it.
+    fn empty_combinations(&self) -> Vec<&'static str> {
+        match self {
+            Self::All { all } if all.is_empty() => vec!["all"],
+            Self::Any { any } if any.is_empty() => vec!["any"],
+            Self::All { all: triggers } | Self::Any { any: triggers } => triggers
+                .iter()
+                .flat_map(|trigger| trigger.empty_combinations())
+                .collect(),
+            Self::Not { not } => not.empty_combinations(),
+            _ => vec![],
         }
+    }
 
-        *last_triggered = now;
-        true
+    /// Whether there's a reaction trigger under a `not`.
+    fn negates_reaction(&self) -> bool {
+        match self {
+            Self::Not { not } => !not.reactions().is_empty() || not.negates_reaction(),
+            Self::All { all: triggers } | Self::Any { any: triggers } => {
+                triggers.iter().any(|trigger| trigger.negates_reaction())
+            }
+            _ => false,
+        }
     }
-}
 
-const fn default_cooldown() -> Duration {
-    Duration::ZERO
+    /// The capture groups of the first regex matching the content, by
+    /// index and by name.
+    fn captures(&self, content: &str) -> Captures {
+        let regex = match self
+            .regexes()
+            .into_iter()
+            .find(|regex| regex.is_match(content))
+        {
+            Some(regex) => regex,
+            None => return Default::default(),
+        };
+        let captures = match regex.captures(content) {
+            Some(captures) => captures,
+            None => return Default::default(),
+        };
+        let text = |m: Option<regex::Match>| m.map_or(String::new(), |m| m.as_str().to_owned());
+
+        Captures {
+            indexed: captures.iter().map(text).collect(),
+            named: regex
+                .capture_names()
+                .flatten()
+                .map(|name| (name.to_owned(), text(captures.name(name))))
+                .collect(),
+        }
+    }
 }
 
-fn default_system_time() -> Arc<Mutex<SystemTime>> {
-    Arc::new(Mutex::new(SystemTime::UNIX_EPOCH))
+/// What a message trigger's regex captured, for filling in templates.
+#[derive(Debug, Default)]
+struct Captures {
+    indexed: Vec<String>,
+    named: HashMap<String, String>,
 }
 
 #[serde_as]
 #[derive(Debug, Deserialize)]
-pub struct AutoresponderAction {
+pub struct AutoresponderFilter {
     #[serde(default)]
     #[serde_as(as = "OneOrMany<_, PreferOne>")]
-    twemojis: Vec<String>,
+    only_in_channels: Vec<u64>,
+    /// How likely the autoresponder is to fire when it triggers, from
+    /// 0 to 1. It always fires if there's no chance given.
     #[serde(default)]
-    #[serde_as(as = "OneOrMany<_, PreferOne>")]
-    reply_messages: Vec<String>,
+    chance: Option<f64>,
+    #[serde(default = "default_cooldown")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    cooldown: Duration,
     #[serde(default)]
-    #[serde_as(as = "OneOrMany<_, PreferOne>")]
-    counter: Vec<String>,
+    cooldown_scope: CooldownScope,
+    #[serde(default)]
+    on_cooldown: OnCooldown,
+    #[serde(skip)]
+    cooldowns: Arc<Cooldowns>,
 }
 
-impl AutoresponderAction {
-    async fn run(
+/// What to do with a message which triggers an autoresponder that is
+/// on cooldown for it, written `ignore`, `count` or `!react twemoji`.
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnCooldown {
+    /// Do nothing, as if the autoresponder hadn't triggered.
+    #[default]
+    Ignore,
+    /// React with the given twemoji instead.
+    React(String),
+    /// Increment the counters without reacting or replying.
+    Count,
+}
+
+enum FilterResult {
+    Pass,
+    Filtered,
+    OnCooldown,
+}
+
+impl AutoresponderFilter {
+    async fn check(
         &self,
-        emojicache: &EmojiCache,
-        counter_factory: &CounterFactory,
-        context: &Context,
-        guild_id: &GuildId,
-        message: &Message,
-    ) {
-        for counter in &self.counter {
-            let counter = counter_factory.make_counter(&counter);
-            if let Err(e) = counter.increment(message.author.id) {
-                log::error!(
-                    "Failed to increment counter {:?} for user {} with error {:#?}",
-                    counter,
-                    message.author.id.get(),
-                    e
-                );
-            }
+        cooldown_store: &CooldownStore,
+        owner: CooldownOwner<'_>,
+        random: &dyn Random,
+        event: &Event<'_>,
+    ) -> FilterResult {
+        if !self.allows_channel(event.message.channel_id.get()) {
+            return FilterResult::Filtered;
         }
 
-        for twemoji in &self.twemojis {
-            if let Ok(Some(emoji)) = emojicache.get_emoji(&context, guild_id, &twemoji).await {
-                if let Err(why) = message.react(context, emoji).await {
-                    log::error!("Failed to react to message with reason {:?}", why);
-                } else {
-                    log::error!("Unknown twemoji {} for guild {}", twemoji, guild_id);
-                }
+        // rolled before the cooldown, so that a miss doesn't start it
+        if let Some(chance) = self.chance {
+            if !random.chance(chance) {
+                return FilterResult::Filtered;
             }
         }
 
-        let content = { self.reply_messages.choose(&mut rand::thread_rng()) };
+        let key = self
+            .cooldown_scope
+            .key(event.subject.get(), event.message.channel_id.get());
 
-        if let Some(content) = content {
-            if let Err(why) = message.reply(context, content).await {
-                log::error!("Failed to autoreply to message with reason {:?}", why);
+        match self
+            .cooldowns
+            .try_trigger(
+                cooldown_store,
+                owner,
+                key,
+                CooldownLimit::new(self.cooldown),
+                SystemTime::now(),
+            )
+            .await
+        {
+            Ok(()) => FilterResult::Pass,
+            Err(_) => FilterResult::OnCooldown,
+        }
+    }
+
+    // basic channel filter
+    fn allows_channel(&self, channel_id: u64) -> bool {
+        self.only_in_channels.is_empty() || self.only_in_channels.contains(&channel_id)
+    }
+}
+
+const fn default_cooldown() -> Duration {
+    Duration::ZERO
+}
+
+/// Reacts to a message with one of the guild's twemojis.
+pub async fn react(
+    emojicache: &EmojiCache,
+    discord: &dyn Discord,
+    guild_id: &GuildId,
+    message: &Message,
+    twemoji: &str,
+) {
+    match emojicache.get_emoji(discord, guild_id, twemoji).await {
+        Ok(Some(emoji)) => {
+            if let Err(why) = discord
+                .react(message.channel_id, message.id, emoji.into())
+                .await
+            {
+                log::error!("Failed to react to message with reason {:?}", why);
             }
         }
+        Ok(None) => log::error!("Unknown twemoji {} for guild {}", twemoji, guild_id),
+        Err(e) => log::error!(
+            "Failed to get twemojis for guild {} with error {}",
+            guild_id,
+            e
+        ),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{sync::Arc, time::Duration};
 
-    use super::{Autoresponder, AutoresponderAction, AutoresponderFilter, AutoresponderTrigger};
+    use serenity::model::{channel::ReactionType, id::EmojiId};
+
+    use super::{
+        Autoresponder, AutoresponderFilter, AutoresponderTrigger, OnCooldown, ReactionTarget,
+        TriggerInput, FIELDS,
+    };
+    use crate::{
+        action::{self, Actions},
+        cooldown::CooldownScope,
+    };
+
+    fn input<'a>(
+        content: &'a str,
+        author_id: u64,
+        channel_id: u64,
+        mentions: &[u64],
+    ) -> TriggerInput<'a> {
+        TriggerInput {
+            content,
+            author_id,
+            channel_id,
+            mentions: mentions.to_vec(),
+            reaction: None,
+        }
+    }
 
     #[test]
     fn autorespondertrigger_single_messagematches() {
@@ -293,13 +1104,88 @@ mod tests {
         }
     }
 
+    #[test]
+    fn autorespondertrigger_all() {
+        let yaml = r#"---
+        all:
+          - user_message: 139425197118849025 # Skorpion Medion
+          - message_matches: fish"#;
+        let autorespondertrigger: AutoresponderTrigger = serde_yaml::from_str(yaml).unwrap();
+        assert!(autorespondertrigger.should_run(&input("fish!", 139425197118849025, 1, &[])));
+        assert!(!autorespondertrigger.should_run(&input("fish!", 2, 1, &[])));
+        assert!(!autorespondertrigger.should_run(&input("chips", 139425197118849025, 1, &[])));
+    }
+
+    #[test]
+    fn autorespondertrigger_any() {
+        let yaml = r#"---
+        any:
+          - user_mentioned: 1
+          - message_matches: peeky"#;
+        let autorespondertrigger: AutoresponderTrigger = serde_yaml::from_str(yaml).unwrap();
+        assert!(autorespondertrigger.should_run(&input("hi", 2, 3, &[1])));
+        assert!(autorespondertrigger.should_run(&input("hi peeky", 2, 3, &[])));
+        assert!(!autorespondertrigger.should_run(&input("hi", 2, 3, &[4])));
+    }
+
+    #[test]
+    fn autorespondertrigger_nested_not() {
+        let yaml = r#"---
+        all:
+          - user_mentioned: 261559920485335040 # P33ky
+          - not:
+              in_channels: 499363309070319616 # nsfw"#;
+        let autorespondertrigger: AutoresponderTrigger = serde_yaml::from_str(yaml).unwrap();
+        assert!(autorespondertrigger.should_run(&input("", 1, 2, &[261559920485335040])));
+        assert!(!autorespondertrigger.should_run(&input(
+            "",
+            1,
+            499363309070319616,
+            &[261559920485335040]
+        )));
+        assert!(!autorespondertrigger.should_run(&input("", 1, 2, &[])));
+    }
+
+    #[test]
+    fn autorespondertrigger_regexes_skip_not() {
+        let yaml = r#"---
+        any:
+          - message_matches: (foo)
+          - not:
+              message_matches: (bar)"#;
+        let autorespondertrigger: AutoresponderTrigger = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(1, autorespondertrigger.regexes().len());
+    }
+
+    #[test]
+    fn autoresponder_nested_keys_are_checked() {
+        let yaml = r#"---
+        all:
+          - user_message: 1
+          - message_match: fish
+        twemojis: swedishfish"#;
+        let e = serde_yaml::from_str::<Autoresponder>(yaml).unwrap_err();
+        assert!(e.to_string().contains("unknown field `message_match`"));
+
+        let yaml = r#"---
+        not:
+          user_message: 1
+          message_matches: fish
+        twemojis: swedishfish"#;
+        let e = serde_yaml::from_str::<Autoresponder>(yaml).unwrap_err();
+        assert!(e.to_string().contains("more than one trigger"));
+    }
+
     #[test]
     fn autoresponderaction_single_twemoji() {
         let yaml = r#"---
         twemojis: PingBad"#;
-        let autoresponderaction: AutoresponderAction = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(1, autoresponderaction.twemojis.len());
-        assert_eq!(0, autoresponderaction.reply_messages.len());
+        let autoresponderaction: Actions = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            1,
+            autoresponderaction.react.as_ref().unwrap().twemojis.len()
+        );
+        assert!(autoresponderaction.reply.is_none());
     }
 
     #[test]
@@ -308,18 +1194,29 @@ mod tests {
         twemojis:
           - PingBad
           - PES_Ping"#;
-        let autoresponderaction: AutoresponderAction = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(2, autoresponderaction.twemojis.len());
-        assert_eq!(0, autoresponderaction.reply_messages.len());
+        let autoresponderaction: Actions = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            2,
+            autoresponderaction.react.as_ref().unwrap().twemojis.len()
+        );
+        assert!(autoresponderaction.reply.is_none());
     }
 
     #[test]
     fn autoresponderaction_single_replymessage() {
         let yaml = r#"---
         reply_messages: foo"#;
-        let autoresponderaction: AutoresponderAction = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(0, autoresponderaction.twemojis.len());
-        assert_eq!(1, autoresponderaction.reply_messages.len());
+        let autoresponderaction: Actions = serde_yaml::from_str(yaml).unwrap();
+        assert!(autoresponderaction.react.is_none());
+        assert_eq!(
+            1,
+            autoresponderaction
+                .reply
+                .as_ref()
+                .unwrap()
+                .reply_messages
+                .len()
+        );
     }
 
     #[test]
@@ -328,9 +1225,17 @@ mod tests {
         reply_messages:
           - foo
           - bar"#;
-        let autoresponderaction: AutoresponderAction = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(0, autoresponderaction.twemojis.len());
-        assert_eq!(2, autoresponderaction.reply_messages.len());
+        let autoresponderaction: Actions = serde_yaml::from_str(yaml).unwrap();
+        assert!(autoresponderaction.react.is_none());
+        assert_eq!(
+            2,
+            autoresponderaction
+                .reply
+                .as_ref()
+                .unwrap()
+                .reply_messages
+                .len()
+        );
     }
 
     #[test]
@@ -338,9 +1243,27 @@ mod tests {
         let yaml = r#"---
         twemojis: PingBad
         reply_messages: Grr"#;
-        let autoresponderaction: AutoresponderAction = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(1, autoresponderaction.twemojis.len());
-        assert_eq!(1, autoresponderaction.reply_messages.len());
+        let autoresponderaction: Actions = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            1,
+            autoresponderaction.react.as_ref().unwrap().twemojis.len()
+        );
+        assert_eq!(
+            1,
+            autoresponderaction
+                .reply
+                .as_ref()
+                .unwrap()
+                .reply_messages
+                .len()
+        );
+    }
+
+    #[test]
+    fn autoresponder_fields_include_actions() {
+        for field in action::FIELDS {
+            assert!(FIELDS.all().contains(field), "{} is missing", field);
+        }
     }
 
     #[test]
@@ -352,6 +1275,32 @@ mod tests {
         assert_eq!(Duration::ZERO, autoresponderfilter.cooldown);
     }
 
+    #[test]
+    fn autoresponderfilter_cooldown_scope() {
+        let yaml = r#"---
+        cooldown: 1800
+        cooldown_scope: per_user
+        on_cooldown: !react PES_Cooldown"#;
+        let autoresponderfilter: AutoresponderFilter = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(CooldownScope::PerUser, autoresponderfilter.cooldown_scope);
+        assert_eq!(
+            OnCooldown::React("PES_Cooldown".to_owned()),
+            autoresponderfilter.on_cooldown
+        );
+    }
+
+    #[test]
+    fn autoresponder_filter_problems() {
+        let yaml = r#"---
+        message_matches: foo
+        reply_messages: bar
+        chance: 1.5
+        cooldown_scope: per_channel
+        on_cooldown: count"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(4, autoresponder.filter_problems().len());
+    }
+
     #[test]
     fn autoresponder_basic_definition1() {
         let yaml = r#"---
@@ -373,4 +1322,165 @@ mod tests {
           - 499363309070319616 # nsfw"#;
         let _: Autoresponder = serde_yaml::from_str(yaml).unwrap();
     }
+
+    #[test]
+    fn autoresponder_adopt_state() {
+        let yaml = r#"---
+        message_matches: foo
+        reply_messages: bar
+        cooldown: 300"#;
+        let previous: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        let mut next: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        let changed: Autoresponder = serde_yaml::from_str(
+            r#"---
+        message_matches: foo
+        reply_messages: baz
+        cooldown: 300"#,
+        )
+        .unwrap();
+        assert!(next.same_definition(&previous));
+        assert!(!changed.same_definition(&previous));
+        next.adopt_state(&previous);
+        assert!(Arc::ptr_eq(
+            &next.filter.cooldowns,
+            &previous.filter.cooldowns
+        ));
+    }
+
+    #[test]
+    fn autoresponder_ids_are_stable() {
+        let yaml = r#"---
+        message_matches: foo
+        reply_messages: bar"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!("0151e965680de050", autoresponder.id());
+
+        let yaml = r#"---
+        id: foobar
+        message_matches: foo
+        reply_messages: bar"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!("foobar", autoresponder.id());
+    }
+
+    #[test]
+    fn autoresponder_unknown_field() {
+        let yaml = r#"---
+        message_matches: foo
+        reply_message: bar"#;
+        let e = serde_yaml::from_str::<Autoresponder>(yaml).unwrap_err();
+        assert!(e.to_string().contains("unknown field `reply_message`"));
+    }
+
+    #[test]
+    fn autoresponder_template_captures() {
+        let yaml = r#"---
+        message_matches: "(?i)\\b(?P<vessel>boats?|ships?)\\b"
+        reply_messages:
+          - "I like {vessel}"
+          - "I like {1}"
+          - "I like {2}"
+          - "I like {cars}""#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(2, autoresponder.reply_problems().len());
+
+        let captures = autoresponder.trigger.captures("I saw some Boats today");
+        assert_eq!(vec!["Boats", "Boats"], captures.indexed);
+        assert_eq!(Some(&"Boats".to_owned()), captures.named.get("vessel"));
+    }
+
+    #[test]
+    fn autorespondertrigger_reaction_added() {
+        let yaml = r#"---
+        all:
+          - reaction_added:
+              emoji: [clarus, 123, "🐟"]
+              target: reactor
+              decrement_on_remove: true
+          - in_channels: 1
+        counter: clarus"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert!(autoresponder.on_reactions());
+        assert!(autoresponder.trigger_problems().is_empty());
+
+        let custom = |id, name: &str| ReactionType::Custom {
+            animated: false,
+            id: EmojiId::new(id),
+            name: Some(name.to_owned()),
+        };
+        let clarus = custom(456, "clarus");
+        let renamed = custom(123, "clarus_v2");
+        let fish = ReactionType::Unicode("🐟".to_owned());
+        let thumbs = ReactionType::Unicode("👍".to_owned());
+
+        for (emoji, channel_id, expected) in [
+            (&clarus, 1, true),
+            (&renamed, 1, true),
+            (&fish, 1, true),
+            (&thumbs, 1, false),
+            (&clarus, 2, false),
+        ] {
+            let input = TriggerInput {
+                reaction: Some(emoji),
+                ..input("", 1, channel_id, &[])
+            };
+            assert_eq!(expected, autoresponder.trigger.should_run(&input));
+        }
+
+        // messages never set off a reaction trigger
+        assert!(!autoresponder.trigger.should_run(&input("", 1, 1, &[])));
+        assert_eq!(
+            ReactionTarget::Reactor,
+            autoresponder.trigger.reaction(&fish).unwrap().target
+        );
+        assert!(autoresponder.trigger.reaction(&thumbs).is_none());
+    }
+
+    #[test]
+    fn autoresponder_reaction_problems() {
+        let yaml = r#"---
+        not:
+          reaction_added:
+            emoji: clarus
+        twemojis: clarus"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert!(!autoresponder.on_reactions());
+        assert_eq!(1, autoresponder.trigger_problems().len());
+
+        let yaml = r#"---
+        reaction_added:
+          emoji: clarus
+          decrement_on_remove: true
+        twemojis: clarus"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            ReactionTarget::Author,
+            autoresponder.trigger.reactions()[0].target
+        );
+        assert_eq!(1, autoresponder.trigger_problems().len());
+    }
+
+    #[test]
+    fn autoresponder_empty_combination_problems() {
+        let yaml = r#"---
+        all:
+          - message_matches: fish
+          - not:
+              any: []
+        reply_messages: blub"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            vec!["any needs at least one trigger"],
+            autoresponder.trigger_problems()
+        );
+
+        let yaml = r#"---
+        all: []
+        reply_messages: blub"#;
+        let autoresponder: Autoresponder = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            vec!["all needs at least one trigger"],
+            autoresponder.trigger_problems()
+        );
+    }
 }
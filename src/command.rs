@@ -1,142 +1,631 @@
-use rand::{prelude::SliceRandom, thread_rng};
-use serde::Deserialize;
-use serde_with::{formats::PreferOne, serde_as, OneOrMany};
+use std::{
+    collections::HashSet,
+    num::NonZeroU32,
+    sync::{Arc, LazyLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+use serde::{de::Error as DeError, Deserialize, Deserializer};
+use serde_with::{formats::PreferOne, serde_as, DurationSeconds, OneOrMany};
+use serde_yaml::Value;
 use serenity::{
-    all::{CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage},
-    client::Context,
-    model::application::CommandInteraction,
+    all::{
+        CommandOptionType, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    model::{
+        application::CommandInteraction,
+        id::{ChannelId, GuildId, RoleId},
+        permissions::Permissions,
+    },
 };
 
-use crate::counter::{Counter, CounterFactory};
+use crate::{
+    action::{check_fields, ActionContext, ActionOutput, Actions, Fields, ShowLeaderboard},
+    autoresponder::react,
+    config::GuildConfig,
+    cooldown::{CooldownLimit, CooldownOwner, CooldownScope, Cooldowns},
+    counter::{EventSource, Period},
+    discord::Discord,
+    handler::Services,
+    options::{Arguments, CommandOption, OptionKind},
+    reply::Reply,
+    responder::{Outcome, Responder, Responding},
+    template::Placeholder,
+};
 
-#[serde_as]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq)]
 pub struct Command {
     pub alias: String,
     pub description: String,
+    /// Whether only the person using the command sees its responses.
+    pub ephemeral: bool,
+    /// Roles by id, one of which someone needs to use the command, if
+    /// there are any.
+    pub allowed_roles: Vec<u64>,
+    /// Roles by id, any of which stops someone using the command.
+    pub denied_roles: Vec<u64>,
+    /// Channels by id the command can be used in, or threads in them,
+    /// if there are any.
+    pub allowed_channels: Vec<u64>,
+    /// The permissions someone needs to use the command. Discord hides
+    /// it from everyone else, though admins can change who sees it.
+    pub default_member_permissions: Option<Permissions>,
+    /// How long someone has to wait between uses, or zero for no
+    /// cooldown.
+    pub cooldown: Duration,
+    pub cooldown_scope: CooldownScope,
+    /// How many uses can be made in quick succession before the
+    /// cooldown has to be waited out, one by default.
+    pub cooldown_burst: Option<NonZeroU32>,
+    cooldowns: Arc<Cooldowns>,
+    /// The actions written in the command's own fields, which run
+    /// before any of its `actions`.
+    pub action: Actions,
+    pub actions: Vec<Actions>,
+    pub options: Vec<CommandOption>,
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+struct CommandParts {
+    alias: String,
+    description: String,
+    #[serde(default)]
+    ephemeral: bool,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    allowed_roles: Vec<u64>,
     #[serde(default)]
     #[serde_as(as = "OneOrMany<_, PreferOne>")]
-    pub reply_messages: Vec<String>,
-    pub counter_leaderboard: Option<String>,
+    denied_roles: Vec<u64>,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    allowed_channels: Vec<u64>,
+    /// Permission names, such as `manage_messages`.
+    #[serde(default)]
+    #[serde_as(as = "Option<OneOrMany<_, PreferOne>>")]
+    default_member_permissions: Option<Vec<String>>,
+    #[serde(default)]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    cooldown: Duration,
+    #[serde(default)]
+    cooldown_scope: CooldownScope,
+    cooldown_burst: Option<NonZeroU32>,
+    #[serde(default)]
+    actions: Vec<Value>,
+    #[serde(default)]
+    options: Vec<CommandOption>,
+    #[serde(flatten)]
+    action: Actions,
+}
+
+/// Every key a command understands.
+static FIELDS: Fields = Fields::with_actions(&[
+    "alias",
+    "description",
+    "ephemeral",
+    "allowed_roles",
+    "denied_roles",
+    "allowed_channels",
+    "default_member_permissions",
+    "cooldown",
+    "cooldown_scope",
+    "cooldown_burst",
+    "actions",
+    "options",
+]);
+
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let definition = Value::deserialize(deserializer)?;
+
+        check_fields(&definition, FIELDS.all())?;
+
+        let parts = CommandParts::deserialize(definition).map_err(D::Error::custom)?;
+        let actions = parts
+            .actions
+            .into_iter()
+            .map(Actions::from_value)
+            .collect::<Result<_, _>>()?;
+        let default_member_permissions = parts
+            .default_member_permissions
+            .map(|names| parse_permissions(&names).map_err(D::Error::custom))
+            .transpose()?;
+
+        Ok(Self {
+            alias: parts.alias,
+            description: parts.description,
+            ephemeral: parts.ephemeral,
+            allowed_roles: parts.allowed_roles,
+            denied_roles: parts.denied_roles,
+            allowed_channels: parts.allowed_channels,
+            default_member_permissions,
+            cooldown: parts.cooldown,
+            cooldown_scope: parts.cooldown_scope,
+            cooldown_burst: parts.cooldown_burst,
+            cooldowns: Arc::default(),
+            action: parts.action,
+            actions,
+            options: parts.options,
+        })
+    }
+}
+
+/// Reads permissions written by name, in any case, such as
+/// `manage_messages`.
+fn parse_permissions(names: &[String]) -> Result<Permissions, String> {
+    names.iter().try_fold(
+        Permissions::empty(),
+        |permissions, name| match Permissions::from_name(&name.to_uppercase()) {
+            Some(permission) => Ok(permissions | permission),
+            None => Err(format!("unknown permission {}", name)),
+        },
+    )
+}
+
+/// Whether Discord would accept a name for a command or an option.
+pub fn is_valid_name(name: &str) -> bool {
+    static NAME_FORMAT: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^[-_\p{L}\p{N}]{1,32}$").unwrap());
+
+    NAME_FORMAT.is_match(name) && name.to_lowercase() == name
 }
 
 impl Command {
-    pub async fn handle(
+    /// The application command to register for this command.
+    pub fn create_command(&self) -> CreateCommand {
+        let mut create_command = CreateCommand::new(&self.alias).description(&self.description);
+
+        if let Some(permissions) = self.default_member_permissions {
+            create_command = create_command.default_member_permissions(permissions);
+        }
+
+        if self.leaderboard().is_some() {
+            let period = Period::ALL.iter().fold(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "period",
+                    "how far back to count, all of time if unset",
+                ),
+                |option, (name, _)| option.add_string_choice(*name, *name),
+            );
+
+            create_command = create_command.add_option(period);
+        }
+
+        self.options
+            .iter()
+            .fold(create_command, |create_command, option| {
+                create_command.add_option(option.create_option())
+            })
+    }
+
+    /// Whether someone with the given roles and permissions may use this
+    /// command in a channel, given with the channel it's a thread of if
+    /// it is one.
+    fn allows(
+        &self,
+        roles: &[RoleId],
+        permissions: Option<Permissions>,
+        channel_id: ChannelId,
+        parent_id: Option<ChannelId>,
+    ) -> bool {
+        let has_role = |allowed: &[u64]| roles.iter().any(|role| allowed.contains(&role.get()));
+        let in_channel = std::iter::once(channel_id)
+            .chain(parent_id)
+            .any(|channel_id| self.allowed_channels.contains(&channel_id.get()));
+
+        (self.allowed_roles.is_empty() || has_role(&self.allowed_roles))
+            && !has_role(&self.denied_roles)
+            && (self.allowed_channels.is_empty() || in_channel)
+            // Discord hides the command from anyone without these, but
+            // only once it has been registered with them
+            && self
+                .default_member_permissions
+                .is_none_or(|needed| {
+                    permissions.is_some_and(|permissions| permissions.contains(needed))
+                })
+    }
+
+    /// Checks whoever used this command may use it where they did,
+    /// telling them they can't if not.
+    pub async fn permits(&self, interaction: &CommandInteraction, discord: &dyn Discord) -> bool {
+        let (roles, permissions) = match &interaction.member {
+            Some(member) => (member.roles.as_slice(), member.permissions),
+            None => (&[][..], None),
+        };
+        let parent_id = interaction
+            .channel
+            .as_ref()
+            .and_then(|channel| channel.parent_id);
+
+        if self.allows(roles, permissions, interaction.channel_id, parent_id) {
+            return true;
+        }
+
+        refuse(
+            interaction,
+            discord,
+            "You're not allowed to use this command here.",
+        )
+        .await;
+
+        false
+    }
+
+    /// Shares the cooldown state of a previous incarnation of this
+    /// command, so a config reload doesn't re-arm it.
+    pub fn adopt_state(&mut self, previous: &Command) {
+        self.cooldowns = previous.cooldowns.clone();
+    }
+
+    /// Triggers this command's cooldown for whoever used it, returning
+    /// how long until they can use it again if it's still running.
+    async fn try_trigger_cooldown(
         &self,
         interaction: &CommandInteraction,
-        ctx: Context,
-        counter_factory: &CounterFactory,
-    ) {
-        if !self.reply_messages.is_empty() {
-            handle_reply_message(&ctx, interaction, &self.reply_messages).await;
+        services: &Services,
+        guild_id: GuildId,
+    ) -> Result<(), Duration> {
+        if self.cooldown.is_zero() {
+            return Ok(());
         }
 
-        if let Some(counter_name) = &self.counter_leaderboard {
-            let counter = counter_factory.make_counter(&counter_name);
+        let owner_id = format!("/{}", self.alias);
+        let owner = CooldownOwner {
+            guild_id,
+            id: &owner_id,
+        };
+        let key = self
+            .cooldown_scope
+            .key(interaction.user.id.get(), interaction.channel_id.get());
+        let limit = CooldownLimit {
+            cooldown: self.cooldown,
+            burst: self.cooldown_burst.unwrap_or(NonZeroU32::MIN),
+        };
 
-            handle_counter_leaderboard(&ctx, interaction, &counter).await;
+        self.cooldowns
+            .try_trigger(
+                &services.cooldown_store,
+                owner,
+                key,
+                limit,
+                SystemTime::now(),
+            )
+            .await
+    }
+
+    /// Every set of actions, with a prefix naming where it was written
+    /// for problems found with it: its own fields, and then its
+    /// `actions` in order.
+    fn labelled_actions(&self) -> impl Iterator<Item = (String, &Actions)> {
+        std::iter::once((String::new(), &self.action)).chain(
+            self.actions
+                .iter()
+                .enumerate()
+                .map(|(i, action)| (format!("actions[{}]: ", i), action)),
+        )
+    }
+
+    /// The counters this command increments.
+    pub fn incremented(&self) -> impl Iterator<Item = &str> {
+        self.labelled_actions()
+            .flat_map(|(_, action)| action.incremented())
+    }
+
+    /// Every counter this command names.
+    pub fn counters(&self) -> impl Iterator<Item = &str> {
+        self.labelled_actions()
+            .flat_map(|(_, action)| action.counters())
+    }
+
+    /// This command's leaderboard, if it has one.
+    pub fn leaderboard(&self) -> Option<&ShowLeaderboard> {
+        self.labelled_actions()
+            .find_map(|(_, action)| action.leaderboard.as_ref())
+    }
+
+    /// Checks this command's actions would do what they're meant to and
+    /// make sense together, returning a description of each problem
+    /// found.
+    pub fn action_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        for (label, action) in self.labelled_actions() {
+            for problem in action.problems() {
+                problems.push(format!("{}{}", label, problem));
+            }
+        }
+
+        for (i, action) in self.actions.iter().enumerate() {
+            if action.is_empty() {
+                problems.push(format!("actions[{}]: does nothing", i));
+            }
+        }
+
+        // the period, user and limit options belong to the leaderboard
+        if self
+            .labelled_actions()
+            .filter(|(_, action)| action.leaderboard.is_some())
+            .count()
+            > 1
+        {
+            problems.push("can only have one counter_leaderboard".to_owned());
+        }
+
+        let reacts = self
+            .labelled_actions()
+            .any(|(_, action)| action.react.is_some());
+        let responds = self
+            .labelled_actions()
+            .any(|(_, action)| action.reply.is_some() || action.leaderboard.is_some());
+
+        if reacts && !responds {
+            problems.push(
+                "twemojis need reply_messages or a counter_leaderboard to react to".to_owned(),
+            );
         }
+
+        problems
     }
-}
 
-async fn handle_reply_message(
-    ctx: &Context,
-    interaction: &CommandInteraction,
-    messages: &Vec<String>,
-) {
-    let content = match messages.choose(&mut thread_rng()) {
-        Some(content) => content,
-        None => {
-            log::error!("No responses configured");
-            return;
+    /// Checks this command's roles don't contradict each other,
+    /// returning a description of each problem found.
+    pub fn permission_problems(&self) -> Vec<String> {
+        self.allowed_roles
+            .iter()
+            .filter(|role| self.denied_roles.contains(role))
+            .map(|role| format!("role {} is both allowed and denied", role))
+            .collect()
+    }
+
+    /// Checks this command's cooldown settings make sense, returning a
+    /// description of each problem found.
+    pub fn cooldown_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.cooldown.is_zero() {
+            if self.cooldown_scope != CooldownScope::Global {
+                problems.push("cooldown_scope does nothing without a cooldown".to_owned());
+            }
+
+            if self.cooldown_burst.is_some() {
+                problems.push("cooldown_burst does nothing without a cooldown".to_owned());
+            }
         }
-    };
 
-    let interaction_response = CreateInteractionResponse::Message(
-        CreateInteractionResponseMessage::new().content(content),
-    );
+        problems
+    }
 
-    let r = interaction
-        .create_response(&ctx.http, interaction_response)
-        .await
-        .err();
+    /// Checks this command's replies only use options it has, returning
+    /// a description of each problem found.
+    pub fn reply_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        for (label, action) in self.labelled_actions() {
+            for (reply_label, replies, _) in action.reply_sets() {
+                let mut problem = |problem: String| {
+                    problems.push(format!(
+                        "{}{}reply_messages: {}",
+                        label, reply_label, problem
+                    ))
+                };
+
+                for placeholder in replies.iter().flat_map(Reply::placeholders) {
+                    match placeholder {
+                        Placeholder::Indexed(i) => {
+                            problem(format!("{{{}}} can only be used by autoresponders", i))
+                        }
+                        Placeholder::Named(name)
+                            if !self.options.iter().any(|option| option.name == *name) =>
+                        {
+                            problem(format!("{{{}}} is not an option", name))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
 
-    if let Some(e) = r {
-        log::error!("Failed to respond to interaction with error {:?}", e);
+        problems
     }
-}
 
-async fn handle_counter_leaderboard(
-    ctx: &Context,
-    interaction: &CommandInteraction,
-    counter: &Counter,
-) {
-    let guild_id = match interaction.guild_id {
-        Some(guild_id) => guild_id,
-        None => {
-            log::warn!("Interaction occurred without guild_id, aborting.");
-            return;
+    /// Checks the counters this command's replies show are among the
+    /// guild's `counters`, see [`Actions::counter_problems`].
+    pub fn counter_problems(&self, counters: &[&str]) -> Vec<String> {
+        self.labelled_actions()
+            .flat_map(|(label, action)| {
+                action
+                    .counter_problems(counters)
+                    .into_iter()
+                    .map(move |problem| format!("{}{}", label, problem))
+            })
+            .collect()
+    }
+
+    /// Checks this command's options for anything Discord would refuse
+    /// or which would confuse the command, returning a description of
+    /// each problem found.
+    pub fn option_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mut names = HashSet::new();
+        // leaderboards lead with their optional period
+        let leaderboard = self.leaderboard().is_some();
+        let mut seen_optional = leaderboard;
+
+        if self.options.len() + usize::from(leaderboard) > 25 {
+            problems.push("can have at most 25 options".to_owned());
         }
-    };
 
-    let top_counts = match counter.top_counts(interaction.user.id) {
-        Ok(top_counts) => top_counts,
-        Err(e) => {
-            log::error!(
-                "Failed to retrieve top counts for counter {:?} with error {:#?}",
-                counter,
-                e
-            );
-            return;
+        for (i, option) in self.options.iter().enumerate() {
+            let mut problem =
+                |problem: String| problems.push(format!("options[{}]: {}", i, problem));
+
+            for option_problem in option.problems() {
+                problem(option_problem);
+            }
+
+            if !names.insert(option.name.as_str()) {
+                problem(format!("name {} is used more than once", option.name));
+            }
+
+            if option.required && seen_optional {
+                problem("required options must come before optional ones".to_owned());
+            }
+
+            seen_optional |= !option.required;
+
+            if leaderboard {
+                let expected_kind = match option.name.as_str() {
+                    "period" => {
+                        problem("period is already an option of every leaderboard".to_owned());
+                        continue;
+                    }
+                    "user" => OptionKind::User,
+                    "limit" => OptionKind::Integer,
+                    _ => continue,
+                };
+
+                if option.kind != expected_kind {
+                    problem(format!(
+                        "{} must be a {:?} option to be used by the leaderboard",
+                        option.name, expected_kind
+                    ));
+                }
+            }
         }
-    };
 
-    let mut named_counts = vec![];
-
-    for (user_id, count) in top_counts {
-        let user = match user_id.to_user(ctx).await {
-            Ok(user) => user,
-            Err(e) => {
-                log::error!(
-                    "Failed to get user info for user {} with error {:#?}",
-                    user_id,
-                    e
-                );
-                continue;
+        problems
+    }
+
+    /// Runs this command's actions in order, combining what they reply
+    /// with into as few messages as they fit in.
+    pub async fn handle(
+        &self,
+        interaction: &CommandInteraction,
+        discord: &dyn Discord,
+        services: &Services,
+        guild_config: &GuildConfig,
+    ) {
+        let guild_id = match interaction.guild_id {
+            Some(guild_id) => guild_id,
+            None => {
+                log::warn!("Interaction occurred without guild_id, aborting.");
+                return;
             }
         };
 
-        let nick = match user.nick_in(ctx, guild_id).await {
-            Some(nick) => nick,
-            None => user.name,
+        if let Err(wait) = self
+            .try_trigger_cooldown(interaction, services, guild_id)
+            .await
+        {
+            let ready_at = (SystemTime::now() + wait)
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            // rounded up, so it never says it's ready before it is
+            let content = format!(
+                "Slow down! You can use this command again <t:{}:R>.",
+                ready_at.as_secs() + u64::from(ready_at.subsec_nanos() > 0)
+            );
+
+            refuse(interaction, discord, &content).await;
+            return;
+        }
+
+        let mut responder =
+            Responder::new(discord, Responding::Command(interaction), self.ephemeral);
+        let arguments = Arguments::from_interaction(interaction);
+        let subject_name = match &interaction.member {
+            Some(member) => member.display_name(),
+            None => interaction.user.display_name(),
+        };
+        let mut context = ActionContext {
+            discord,
+            services,
+            guild_config,
+            guild_id,
+            subject: interaction.user.id,
+            subject_name: subject_name.to_owned(),
+            channel_id: interaction.channel_id,
+            source: EventSource {
+                channel_id: interaction.channel_id,
+                message_id: None,
+                origin: None,
+            },
+            rotation_key: String::new(),
+            indexed: vec![],
+            named: arguments.displayed(),
+            arguments: &arguments,
         };
+        let mut output = ActionOutput::default();
 
-        named_counts.push((nick, count));
-    }
+        responder
+            .respond(async {
+                for (i, action) in std::iter::once(&self.action)
+                    .chain(&self.actions)
+                    .enumerate()
+                {
+                    // the first actions keep the key commands have always
+                    // had, so their rotation carries on where it was
+                    context.rotation_key = match i {
+                        0 => format!("/{}", self.alias),
+                        i => format!("/{}/actions[{}]", self.alias, i - 1),
+                    };
 
-    let mut embed = CreateEmbed::new();
+                    action.run(&context, &mut output).await;
+                }
 
-    for (name, count) in named_counts {
-        embed = embed.field(name, count.to_string(), false);
+                match (output.replies.is_empty(), output.failed) {
+                    (false, _) => Outcome::Reply(output.replies.remove(0)),
+                    (true, false) => Outcome::Done,
+                    (true, true) => Outcome::Failed,
+                }
+            })
+            .await;
+
+        for reply in output.replies {
+            responder.respond(async { Some(reply) }).await;
+        }
+
+        if !output.twemojis.is_empty() {
+            if let Some(message) = responder.message().await {
+                for twemoji in &output.twemojis {
+                    react(&services.emoji_cache, discord, &guild_id, &message, twemoji).await;
+                }
+            }
+        }
     }
+}
 
+/// Tells whoever used a command, and only them, why it didn't run.
+async fn refuse(interaction: &CommandInteraction, discord: &dyn Discord, content: &str) {
     let interaction_response = CreateInteractionResponse::Message(
-        CreateInteractionResponseMessage::new().add_embed(embed),
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true),
     );
 
-    let response_status = interaction
-        .create_response(ctx, interaction_response)
+    if let Err(e) = discord
+        .create_response(interaction.id, &interaction.token, interaction_response)
         .await
-        .err();
-
-    if let Some(e) = response_status {
-        log::error!("Failed to publish leaderboard response with error {:#?}", e);
+    {
+        log::error!("Failed to refuse command with error {:#?}", e);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{num::NonZeroU32, sync::Arc, time::Duration};
+
+    use serenity::model::{
+        id::{ChannelId, RoleId},
+        permissions::Permissions,
+    };
+
     use super::Command;
+    use crate::cooldown::CooldownScope;
 
     #[test]
     fn command_singlereplymessage_deserialization() {
@@ -145,7 +634,7 @@ mod tests {
         description: does stuff
         reply_messages: hello, world!"#;
         let command: Command = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(1, command.reply_messages.len());
+        assert_eq!(1, command.action.reply.unwrap().reply_messages.len());
     }
 
     #[test]
@@ -157,7 +646,19 @@ mod tests {
           - hello, world!
           - ¡hola, mundo!"#;
         let command: Command = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(2, command.reply_messages.len());
+        assert!(!command.ephemeral);
+        assert_eq!(2, command.action.reply.unwrap().reply_messages.len());
+    }
+
+    #[test]
+    fn command_ephemeral_deserialization() {
+        let yaml = r#"---
+        alias: a_command
+        description: does stuff quietly
+        reply_messages: psst
+        ephemeral: true"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert!(command.ephemeral);
     }
 
     #[test]
@@ -167,4 +668,256 @@ mod tests {
         description: does stuff"#;
         assert!(serde_yaml::from_str::<Command>(yaml).is_ok());
     }
+
+    #[test]
+    fn command_options_deserialization() {
+        let yaml = r#"---
+        alias: curseboard
+        description: do you kiss your mother with that mouth?
+        counter_leaderboard: verbal_morality_statute
+        options:
+          - name: user
+            description: whose mouth
+            type: user
+          - name: limit
+            description: how many to show
+            type: integer"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(2, command.options.len());
+        assert!(command.option_problems().is_empty());
+    }
+
+    #[test]
+    fn command_option_problems() {
+        let yaml = r#"---
+        alias: curseboard
+        description: do you kiss your mother with that mouth?
+        counter_leaderboard: verbal_morality_statute
+        options:
+          - name: user
+            description: whose mouth
+            type: string
+          - name: period
+            description: when
+            type: string
+          - name: mouth
+            description: which mouth
+            type: string
+            required: true"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(3, command.option_problems().len());
+    }
+
+    #[test]
+    fn leaderboard_page_size_problems() {
+        let leaderboard = |page_size| {
+            let yaml = format!(
+                "{{alias: curseboard, description: curses, counter_leaderboard: curses, \
+                    leaderboard_page_size: {}}}",
+                page_size
+            );
+            serde_yaml::from_str::<Command>(&yaml).unwrap()
+        };
+
+        assert!(leaderboard(1).action_problems().is_empty());
+        assert!(leaderboard(25).action_problems().is_empty());
+        assert_eq!(
+            vec!["leaderboard_page_size must be 1-25"],
+            leaderboard(0).action_problems()
+        );
+        assert_eq!(
+            vec!["leaderboard_page_size must be 1-25"],
+            leaderboard(26).action_problems()
+        );
+    }
+
+    #[test]
+    fn leaderboard_options_follow_period() {
+        let yaml = r#"---
+        alias: curseboard
+        description: do you kiss your mother with that mouth?
+        counter_leaderboard: verbal_morality_statute
+        options:
+          - name: mouth
+            description: which mouth
+            type: string
+            required: true"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            vec!["options[0]: required options must come before optional ones"],
+            command.option_problems()
+        );
+
+        let option = "{name: o, description: an option, type: string}";
+        let options = (0..25)
+            .map(|i| option.replace("o,", &format!("o{},", i)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let yaml = format!(
+            "{{alias: curseboard, description: curses, counter_leaderboard: curses, \
+                options: [{}]}}",
+            options
+        );
+        let command: Command = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            vec!["can have at most 25 options"],
+            command.option_problems()
+        );
+    }
+
+    #[test]
+    fn command_permissions() {
+        let yaml = r#"---
+        alias: freedom
+        description: for the faithful only
+        reply_messages: FREEDOM
+        allowed_roles: [1, 2]
+        denied_roles: 3
+        allowed_channels: 10
+        default_member_permissions: [send_messages, Embed_Links]"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        let permissions = Permissions::SEND_MESSAGES | Permissions::EMBED_LINKS;
+        assert_eq!(Some(permissions), command.default_member_permissions);
+        assert!(command.permission_problems().is_empty());
+
+        let (faithful, heretic) = (RoleId::new(2), RoleId::new(3));
+        let (channel, thread) = (ChannelId::new(10), ChannelId::new(11));
+        let everything = Some(Permissions::all());
+        assert!(command.allows(&[faithful], everything, channel, None));
+        assert!(command.allows(&[faithful], everything, thread, Some(channel)));
+        assert!(!command.allows(&[faithful], everything, thread, None));
+        assert!(!command.allows(&[], everything, channel, None));
+        assert!(!command.allows(&[faithful, heretic], everything, channel, None));
+        assert!(!command.allows(&[faithful], Some(Permissions::SEND_MESSAGES), channel, None));
+        assert!(!command.allows(&[faithful], None, channel, None));
+
+        let yaml = r#"---
+        alias: freedom
+        description: for anyone
+        reply_messages: FREEDOM
+        allowed_roles: 1
+        denied_roles: 1"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert!(!command.allows(&[RoleId::new(1)], None, ChannelId::new(10), None));
+        assert_eq!(
+            vec!["role 1 is both allowed and denied"],
+            command.permission_problems()
+        );
+
+        let yaml = r#"---
+        alias: freedom
+        description: for nobody
+        default_member_permissions: fly"#;
+        let e = serde_yaml::from_str::<Command>(yaml).unwrap_err();
+        assert!(e.to_string().contains("unknown permission fly"));
+    }
+
+    #[test]
+    fn command_cooldowns() {
+        let yaml = r#"---
+        alias: wednesday
+        description: it is wednesday, my dudes
+        reply_messages: AAAAAAAAAAAAA
+        cooldown: 600
+        cooldown_scope: per_user
+        cooldown_burst: 3"#;
+        let mut command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(Duration::from_secs(600), command.cooldown);
+        assert_eq!(CooldownScope::PerUser, command.cooldown_scope);
+        assert_eq!(NonZeroU32::new(3), command.cooldown_burst);
+        assert!(command.cooldown_problems().is_empty());
+
+        let previous: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(previous, command);
+        command.adopt_state(&previous);
+        assert!(Arc::ptr_eq(&previous.cooldowns, &command.cooldowns));
+
+        let yaml = r#"---
+        alias: wednesday
+        description: it is wednesday, my dudes
+        reply_messages: AAAAAAAAAAAAA
+        cooldown_scope: per_channel
+        cooldown_burst: 3"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(2, command.cooldown_problems().len());
+
+        let yaml = r#"---
+        alias: wednesday
+        description: it is wednesday, my dudes
+        cooldown: 600
+        cooldown_burst: 0"#;
+        assert!(serde_yaml::from_str::<Command>(yaml).is_err());
+    }
+
+    #[test]
+    fn command_actions_run_in_order() {
+        let yaml = r#"---
+        alias: clarus
+        description: counts clarus
+        counter: clarus
+        actions:
+          - reply_messages: "{author} has {counter:clarus} clarus"
+            twemojis: clarus
+          - counter_leaderboard: clarus
+            leaderboard_page_size: 5"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(vec!["clarus"], command.incremented().collect::<Vec<_>>());
+        assert_eq!(
+            Some(5),
+            command
+                .leaderboard()
+                .and_then(|leaderboard| leaderboard.page_size)
+        );
+        assert!(command.action_problems().is_empty());
+        assert!(command.reply_problems().is_empty());
+
+        let yaml = r#"---
+        alias: clarus
+        description: counts clarus
+        counter_leaderboard: clarus
+        twemojis: clarus
+        actions:
+          - reply_messages: "{1}"
+          - counter_leaderboard: clarus
+          - {}"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            vec![
+                "actions[2]: does nothing",
+                "can only have one counter_leaderboard",
+            ],
+            command.action_problems()
+        );
+        assert_eq!(
+            vec!["actions[0]: reply_messages: {1} can only be used by autoresponders"],
+            command.reply_problems()
+        );
+
+        let yaml = r#"---
+        alias: clarus
+        description: counts clarus
+        actions:
+          - reply_messages: clarus
+            alias: clarus"#;
+        let e = serde_yaml::from_str::<Command>(yaml).unwrap_err();
+        assert!(e.to_string().contains("unknown field `alias`"));
+    }
+
+    #[test]
+    fn command_template_options() {
+        let yaml = r#"---
+        alias: hug
+        description: hugs someone
+        reply_messages:
+          - "{author} hugs {target}"
+          - "{author} hugs {nobody}"
+          - "{author} hugs {1}"
+        options:
+          - name: target
+            description: who to hug
+            type: user
+            required: true"#;
+        let command: Command = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(2, command.reply_problems().len());
+    }
 }
@@ -1,25 +1,522 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::read_to_string,
+    io::Error as IoError,
+};
 
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_yaml::{Error as YamlError, Mapping, Value};
+use serenity::{all::CreateCommand, model::id::GuildId};
+use snafu::{ResultExt, Snafu};
 
-use crate::{autoresponder::Autoresponder, command::Command};
+use crate::{
+    autoresponder::Autoresponder,
+    command::{is_valid_name, Command},
+    countercommand::{self, CounterCommand},
+    reminder::{self, ReminderCommands},
+    schedule::Schedule,
+};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Config file at {path} could not be read: {source}"))]
+    Read { path: String, source: IoError },
+    #[snafu(display("Config file at {path} could not be parsed: {source}"))]
+    Parse { path: String, source: YamlError },
+    #[snafu(display("Config file at {path} is invalid: {}", problems.join("; ")))]
+    Invalid { path: String, problems: Vec<String> },
+}
+
+/// Where each entry of a section of a guild's config was in the config
+/// file, for a config missing the entries which failed to deserialize.
+type Positions = HashMap<(u64, &'static str), Vec<usize>>;
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// The guild which counters from before counters were scoped per
+    /// guild belong to. Only needed to migrate such a database.
+    pub default_counter_guild: Option<u64>,
     pub guilds: HashMap<u64, GuildConfig>,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct GuildConfig {
     #[serde(default)]
     pub commands: Vec<Command>,
     #[serde(default)]
     pub autoresponders: Vec<Autoresponder>,
+    /// Counters this guild shares with another guild, by counter name
+    /// and the id of the guild which owns the counter.
+    #[serde(default)]
+    pub shared_counters: HashMap<String, u64>,
+    /// Enables the built-in `/counter` command for moderators.
+    pub counter_command: Option<CounterCommand>,
+    /// Enables the built-in `/remindme` and `/reminders` commands.
+    pub reminders: Option<ReminderCommands>,
+    /// Posts made on a schedule.
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+}
+
+impl Config {
+    /// Reads, parses and validates the config file at the given path.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let contents = read_to_string(path).context(ReadSnafu { path })?;
+        let config: Self = match serde_yaml::from_str(&contents) {
+            Ok(config) => config,
+            Err(source) => {
+                // serde stops at the first problem, so go looking for
+                // the rest of them to report everything at once
+                let problems = structural_problems(&contents);
+
+                if problems.is_empty() {
+                    return Err(source).context(ParseSnafu { path });
+                }
+
+                return InvalidSnafu { path, problems }.fail();
+            }
+        };
+
+        let problems = config.validate();
+
+        if !problems.is_empty() {
+            return InvalidSnafu { path, problems }.fail();
+        }
+
+        Ok(config)
+    }
+
+    /// Loads the config file at the given path, returning every
+    /// problem found with it.
+    pub fn check(path: &str) -> Vec<String> {
+        match Self::load(path) {
+            Ok(_) => vec![],
+            Err(Error::Invalid { problems, .. }) => problems,
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    /// Checks the config for problems serde can't catch on its own,
+    /// returning a description of each one found.
+    pub fn validate(&self) -> Vec<String> {
+        self.problems(&Positions::new())
+    }
+
+    /// Checks the config as [`Config::validate`] does, naming entries
+    /// by where they are in the config file rather than in the config.
+    fn problems(&self, positions: &Positions) -> Vec<String> {
+        let mut problems = vec![];
+        let incremented_counters = self
+            .guilds
+            .iter()
+            .flat_map(|(guild_id, guild_config)| {
+                guild_config
+                    .autoresponders
+                    .iter()
+                    .flat_map(|autoresponder| autoresponder.incremented())
+                    .chain(
+                        guild_config
+                            .commands
+                            .iter()
+                            .flat_map(|command| command.incremented()),
+                    )
+                    .map(|counter| {
+                        (
+                            guild_config.counter_guild(GuildId::new(*guild_id), counter),
+                            counter,
+                        )
+                    })
+            })
+            .collect::<HashSet<_>>();
+        let mut guild_ids = self.guilds.keys().collect::<Vec<_>>();
+
+        guild_ids.sort();
+
+        for guild_id in guild_ids {
+            let guild_config = &self.guilds[guild_id];
+            let counters = guild_config.counters();
+            let mut aliases = HashSet::new();
+
+            let position = |section: &'static str, i: usize| {
+                positions
+                    .get(&(*guild_id, section))
+                    .map_or(i, |positions| positions[i])
+            };
+
+            for (i, command) in guild_config.commands.iter().enumerate() {
+                let i = position("commands", i);
+                let mut problem = |problem: String| {
+                    problems.push(format!("guild {}: commands[{}]: {}", guild_id, i, problem))
+                };
+
+                if !aliases.insert(command.alias.as_str()) {
+                    problem(format!("alias {} is used more than once", command.alias));
+                }
+
+                if guild_config.counter_command.is_some() && command.alias == countercommand::NAME {
+                    problem(format!(
+                        "alias {} is taken by counter_command",
+                        command.alias
+                    ));
+                }
+
+                if guild_config.reminders.is_some()
+                    && (command.alias == reminder::REMIND_ME
+                        || command.alias == reminder::REMINDERS)
+                {
+                    problem(format!("alias {} is taken by reminders", command.alias));
+                }
+
+                // discord's own rules for command names, which it
+                // enforces by rejecting every command in the guild
+                if !is_valid_name(&command.alias) {
+                    problem(format!(
+                        "alias {} must be 1-32 lowercase letters, numbers, - or _",
+                        command.alias
+                    ));
+                }
+
+                if command.description.is_empty() || command.description.chars().count() > 100 {
+                    problem("description must be 1-100 characters".to_owned());
+                }
+
+                for option_problem in command.option_problems() {
+                    problem(option_problem);
+                }
+
+                for permission_problem in command.permission_problems() {
+                    problem(permission_problem);
+                }
+
+                for cooldown_problem in command.cooldown_problems() {
+                    problem(cooldown_problem);
+                }
+
+                for action_problem in command.action_problems() {
+                    problem(action_problem);
+                }
+
+                for reply_problem in command.reply_problems() {
+                    problem(reply_problem);
+                }
+
+                for counter_problem in command.counter_problems(&counters) {
+                    problem(counter_problem);
+                }
+
+                if let Some(counter) = command
+                    .leaderboard()
+                    .map(|leaderboard| &leaderboard.counter)
+                {
+                    let counter_guild =
+                        guild_config.counter_guild(GuildId::new(*guild_id), counter);
+
+                    if !incremented_counters.contains(&(counter_guild, counter.as_str())) {
+                        problem(format!(
+                            "counter_leaderboard {} is never incremented by an autoresponder or \
+                                command",
+                            counter
+                        ));
+                    }
+                }
+            }
+
+            if let Some(counter_command) = &guild_config.counter_command {
+                for counter_command_problem in counter_command.problems() {
+                    problems.push(format!(
+                        "guild {}: counter_command: {}",
+                        guild_id, counter_command_problem
+                    ));
+                }
+            }
+
+            if let Some(reminders) = &guild_config.reminders {
+                for reminders_problem in reminders.problems() {
+                    problems.push(format!(
+                        "guild {}: reminders: {}",
+                        guild_id, reminders_problem
+                    ));
+                }
+            }
+
+            for (counter, owner) in &guild_config.shared_counters {
+                if !self.guilds.contains_key(owner) {
+                    problems.push(format!(
+                        "guild {}: shared_counters: {} is shared with guild {} which has no config",
+                        guild_id, counter, owner
+                    ));
+                }
+            }
+
+            let mut ids = HashSet::new();
+
+            for (i, autoresponder) in guild_config.autoresponders.iter().enumerate() {
+                let i = position("autoresponders", i);
+                let mut problem = |problem: String| {
+                    problems.push(format!(
+                        "guild {}: autoresponders[{}]: {}",
+                        guild_id, i, problem
+                    ))
+                };
+
+                if !ids.insert(autoresponder.id()) {
+                    problem(format!(
+                        "id {} is shared with another autoresponder, give each its own id",
+                        autoresponder.id()
+                    ));
+                }
+
+                if autoresponder.is_noop() {
+                    problem("does nothing".to_owned());
+                }
+
+                for action_problem in autoresponder.action_problems() {
+                    problem(action_problem);
+                }
+
+                for reply_problem in autoresponder.reply_problems() {
+                    problem(reply_problem);
+                }
+
+                for counter_problem in autoresponder.counter_problems(&counters) {
+                    problem(counter_problem);
+                }
+
+                for filter_problem in autoresponder.filter_problems() {
+                    problem(filter_problem);
+                }
+
+                for trigger_problem in autoresponder.trigger_problems() {
+                    problem(trigger_problem);
+                }
+            }
+
+            let mut schedule_ids = HashSet::new();
+
+            for (i, schedule) in guild_config.schedules.iter().enumerate() {
+                let i = position("schedules", i);
+                let mut problem = |problem: String| {
+                    problems.push(format!("guild {}: schedules[{}]: {}", guild_id, i, problem))
+                };
+
+                if !schedule_ids.insert(schedule.id.as_str()) {
+                    problem(format!(
+                        "id {} is shared with another schedule, give each its own id",
+                        schedule.id
+                    ));
+                }
+
+                for action_problem in schedule.action_problems() {
+                    problem(action_problem);
+                }
+
+                for counter_problem in schedule.counter_problems(&counters) {
+                    problem(counter_problem);
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Carries runtime state, such as autoresponder cooldowns, over
+    /// from a previous config for everything which did not change.
+    pub fn adopt_state(&mut self, previous: &Config) {
+        for (guild_id, guild_config) in self.guilds.iter_mut() {
+            if let Some(previous_guild_config) = previous.guilds.get(guild_id) {
+                guild_config.adopt_state(previous_guild_config);
+            }
+        }
+    }
+}
+
+impl GuildConfig {
+    /// The guild whose copy of a counter this guild uses, which is its
+    /// own unless the config explicitly shares it with another guild.
+    pub fn counter_guild(&self, guild_id: GuildId, counter: &str) -> GuildId {
+        match self.shared_counters.get(counter) {
+            Some(owner) => GuildId::new(*owner),
+            None => guild_id,
+        }
+    }
+
+    /// Every counter this guild's config names, sorted.
+    pub fn counters(&self) -> Vec<&str> {
+        let mut counters = self
+            .autoresponders
+            .iter()
+            .flat_map(|autoresponder| autoresponder.counters())
+            .chain(self.commands.iter().flat_map(|command| command.counters()))
+            .chain(self.shared_counters.keys().map(String::as_str))
+            .collect::<Vec<_>>();
+
+        counters.sort_unstable();
+        counters.dedup();
+        counters
+    }
+
+    /// Every application command to register for this guild.
+    pub fn create_commands(&self) -> Vec<CreateCommand> {
+        self.commands
+            .iter()
+            .map(|command| command.create_command())
+            .chain(
+                self.counter_command
+                    .iter()
+                    .map(|counter_command| counter_command.create_command()),
+            )
+            .chain(
+                self.reminders
+                    .iter()
+                    .flat_map(|reminders| reminders.create_commands()),
+            )
+            .collect()
+    }
+
+    /// Whether this guild registers the same application commands as
+    /// another config of it.
+    pub fn same_commands(&self, other: &GuildConfig) -> bool {
+        self.commands == other.commands
+            && self.counter_command == other.counter_command
+            && self.reminders == other.reminders
+    }
+
+    fn adopt_state(&mut self, previous: &GuildConfig) {
+        for autoresponder in self.autoresponders.iter_mut() {
+            if let Some(previous_autoresponder) =
+                previous
+                    .autoresponders
+                    .iter()
+                    .find(|previous_autoresponder| {
+                        previous_autoresponder.same_definition(autoresponder)
+                    })
+            {
+                autoresponder.adopt_state(previous_autoresponder);
+            }
+        }
+
+        for command in self.commands.iter_mut() {
+            if let Some(previous_command) = previous
+                .commands
+                .iter()
+                .find(|previous_command| *previous_command == command)
+            {
+                command.adopt_state(previous_command);
+            }
+        }
+    }
+}
+
+/// Deserializes each guild, command, autoresponder and schedule on its
+/// own so that every malformed one can be reported, named by where it
+/// lives.
+///
+/// Whatever did deserialize is then validated as well, so that fixing a
+/// config doesn't take a round for each kind of problem.
+fn structural_problems(contents: &str) -> Vec<String> {
+    let mut root: Value = match serde_yaml::from_str(contents) {
+        Ok(root) => root,
+        Err(e) => return vec![e.to_string()],
+    };
+    let mut problems = vec![];
+    let guilds = match root.get_mut("guilds") {
+        Some(guilds) => std::mem::replace(guilds, Value::Mapping(Mapping::new())),
+        None => Value::Null,
+    };
+    let mut partial = match Config::deserialize(root) {
+        Ok(config) => config,
+        Err(e) => {
+            problems.push(e.to_string());
+            Config {
+                default_counter_guild: None,
+                guilds: HashMap::new(),
+            }
+        }
+    };
+    let mut positions = Positions::new();
+
+    if let Value::Mapping(guilds) = guilds {
+        for (guild_id, mut guild) in guilds {
+            let id = guild_id.as_u64();
+            let guild_id = match id {
+                Some(guild_id) => guild_id.to_string(),
+                None => format!("{:?}", guild_id),
+            };
+            let commands = take_sequence(&mut guild, "commands");
+            let autoresponders = take_sequence(&mut guild, "autoresponders");
+            let schedules = take_sequence(&mut guild, "schedules");
+            let mut guild_config = match GuildConfig::deserialize(guild) {
+                Ok(guild_config) => Some(guild_config),
+                Err(e) => {
+                    problems.push(format!("guild {}: {}", guild_id, e));
+                    None
+                }
+            };
+            let (command_positions, commands) = deserialize_entries(commands, |i, e| {
+                problems.push(format!("guild {}: commands[{}]: {}", guild_id, i, e))
+            });
+            let (autoresponder_positions, autoresponders) =
+                deserialize_entries(autoresponders, |i, e| {
+                    problems.push(format!("guild {}: autoresponders[{}]: {}", guild_id, i, e))
+                });
+            let (schedule_positions, schedules) = deserialize_entries(schedules, |i, e| {
+                problems.push(format!("guild {}: schedules[{}]: {}", guild_id, i, e))
+            });
+
+            if let Some(id) = id {
+                positions.insert((id, "commands"), command_positions);
+                positions.insert((id, "autoresponders"), autoresponder_positions);
+                positions.insert((id, "schedules"), schedule_positions);
+            }
+
+            if let Some(guild_config) = &mut guild_config {
+                guild_config.commands = commands;
+                guild_config.autoresponders = autoresponders;
+                guild_config.schedules = schedules;
+            }
+
+            if let (Some(id), Some(guild_config)) = (id, guild_config) {
+                partial.guilds.insert(id, guild_config);
+            }
+        }
+    }
+
+    problems.extend(partial.problems(&positions));
+    problems
+}
+
+/// Deserializes each of a section's entries, returning those which
+/// deserialized along with where each was in the section.
+fn deserialize_entries<T: DeserializeOwned>(
+    entries: Vec<Value>,
+    mut problem: impl FnMut(usize, YamlError),
+) -> (Vec<usize>, Vec<T>) {
+    entries
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, entry)| match T::deserialize(entry) {
+            Ok(entry) => Some((i, entry)),
+            Err(e) => {
+                problem(i, e);
+                None
+            }
+        })
+        .unzip()
+}
+
+fn take_sequence(value: &mut Value, key: &str) -> Vec<Value> {
+    match value.get_mut(key) {
+        Some(Value::Sequence(sequence)) => std::mem::take(sequence),
+        _ => vec![],
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Config;
+    use super::{structural_problems, Config};
+    use serenity::model::id::GuildId;
     use std::fs::read_to_string;
 
     #[test]
@@ -29,4 +526,223 @@ mod tests {
         ).expect("Config is not well-formed");
         eprintln!("{:#?}", config);
     }
+
+    #[test]
+    fn duplicate_aliases_are_invalid() {
+        let yaml = r#"---
+        guilds:
+          1:
+            commands:
+              - alias: a_command
+                description: does stuff
+              - alias: a_command
+                description: does other stuff"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(1, config.validate().len());
+    }
+
+    #[test]
+    fn unknown_keys_are_named() {
+        let yaml = r#"---
+        guilds:
+          1:
+            commands:
+              - alias: a_command
+                description: does stuff
+                reply_message: hello
+            autoresponders:
+              - message_matches: foo
+                reply_messages: bar
+              - message_matches: foo
+                reply_message: bar"#;
+        assert!(serde_yaml::from_str::<Config>(yaml).is_err());
+        assert_eq!(
+            vec![
+                "guild 1: commands[0]: unknown field `reply_message`, expected one of \
+                    `alias`, `description`, `ephemeral`, `allowed_roles`, `denied_roles`, \
+                    `allowed_channels`, `default_member_permissions`, `cooldown`, \
+                    `cooldown_scope`, `cooldown_burst`, `actions`, `options`, `counter`, \
+                    `decrement_counter`, `add_roles`, `remove_roles`, `reply_messages`, \
+                    `selection`, `counter_leaderboard`, `leaderboard_page_size`, \
+                    `send_to_channel`, `dm_user`, `twemojis`",
+                "guild 1: autoresponders[1]: unknown field `reply_message`, expected one of \
+                    `id`, `message_matches`, `user_message`, `user_mentioned`, `in_channels`, \
+                    `reaction_added`, `all`, `any`, `not`, `only_in_channels`, `chance`, \
+                    `cooldown`, `cooldown_scope`, `on_cooldown`, `counter`, `decrement_counter`, \
+                    `add_roles`, `remove_roles`, `reply_messages`, `selection`, \
+                    `counter_leaderboard`, `leaderboard_page_size`, `send_to_channel`, `dm_user`, \
+                    `twemojis`",
+            ],
+            structural_problems(yaml)
+        );
+    }
+
+    #[test]
+    fn semantic_problems_are_reported_alongside_malformed_entries() {
+        let yaml = r#"---
+        guilds:
+          1:
+            commands:
+              - alias: a_command
+                description: does stuff
+                reply_message: hello
+              - alias: Bad Alias
+                description: does stuff
+                reply_messages: hello
+            autoresponders:
+              - message_matches: foo
+                reply_messages: bar
+                chance: lots
+              - message_matches: foo
+          2:
+            nonsense: true"#;
+        let problems = structural_problems(yaml);
+        assert_eq!(5, problems.len(), "{:#?}", problems);
+        assert!(problems[0].starts_with("guild 1: commands[0]: unknown field `reply_message`"));
+        assert!(problems[1].starts_with("guild 1: autoresponders[0]: "));
+        assert!(problems[2].starts_with("guild 2: unknown field `nonsense`"));
+        assert_eq!(
+            vec![
+                "guild 1: commands[1]: alias Bad Alias must be 1-32 lowercase letters, numbers, \
+                    - or _",
+                "guild 1: autoresponders[1]: does nothing",
+            ],
+            problems[3..]
+        );
+    }
+
+    #[test]
+    fn semantic_problems_are_reported() {
+        let yaml = r#"---
+        guilds:
+          1:
+            commands:
+              - alias: Bad Alias
+                description: does stuff
+              - alias: leaderboard
+                description: prints a leaderboard
+                counter_leaderboard: nobody_counts_this
+            autoresponders:
+              - message_matches: foo
+                cooldown: 300"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(3, config.validate().len());
+    }
+
+    #[test]
+    fn counter_placeholders_name_counters() {
+        let yaml = r#"---
+        guilds:
+          1:
+            shared_counters:
+              clarus: 1
+            commands:
+              - alias: heresy
+                description: confess heresy
+                counter: heresy
+                reply_messages: "{author} has confessed {counter:heresey} times"
+                actions:
+                  - reply_messages: "and had {counter:clarus} clarus"
+            autoresponders:
+              - message_matches: heresy
+                reply_messages: "that's your {counter:heresy:ordinal} heresy"
+              - message_matches: clarus
+                reply_messages: "{counter:claurs} clarus"
+            schedules:
+              - id: monday
+                cron: 0 9 * * MON
+                channel: 100
+                send_to_channel:
+                  channel: 100
+                  reply_messages: "{counter:monday} Mondays""#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            vec![
+                "guild 1: commands[0]: reply_messages: {counter:heresey} is not a counter",
+                "guild 1: autoresponders[1]: reply_messages: {counter:claurs} is not a counter",
+                "guild 1: schedules[0]: send_to_channel: reply_messages: {counter:monday} is not \
+                    a counter",
+            ],
+            config.validate()
+        );
+    }
+
+    #[test]
+    fn shared_counters_are_explicit() {
+        let yaml = r#"---
+        guilds:
+          1:
+            autoresponders:
+              - message_matches: foo
+                counter: foo_count
+          2:
+            commands:
+              - alias: fooboard
+                description: who said foo
+                counter_leaderboard: foo_count
+          3:
+            shared_counters:
+              foo_count: 1
+            commands:
+              - alias: fooboard
+                description: who said foo
+                counter_leaderboard: foo_count"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let problems = config.validate();
+        assert_eq!(1, problems.len());
+        assert!(problems[0].starts_with("guild 2: "));
+        assert_eq!(
+            GuildId::new(1),
+            config.guilds[&3].counter_guild(GuildId::new(3), "foo_count")
+        );
+        assert_eq!(
+            GuildId::new(3),
+            config.guilds[&3].counter_guild(GuildId::new(3), "bar_count")
+        );
+    }
+
+    #[test]
+    fn counter_command_is_registered() {
+        let yaml = r#"---
+        guilds:
+          1:
+            counter_command:
+              permissions: manage_messages
+            commands:
+              - alias: counter
+                description: counts stuff
+            autoresponders:
+              - message_matches: foo
+                counter: foo_count"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(1, config.validate().len());
+        assert_eq!(2, config.guilds[&1].create_commands().len());
+        assert_eq!(vec!["foo_count"], config.guilds[&1].counters());
+    }
+
+    #[test]
+    fn reminder_commands_are_registered() {
+        let yaml = r#"---
+        guilds:
+          1:
+            reminders:
+              max_per_user: 0
+            commands:
+              - alias: remindme
+                description: reminds stuff"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            vec![
+                "guild 1: commands[0]: alias remindme is taken by reminders",
+                "guild 1: reminders: max_per_user must be at least 1",
+            ],
+            config.validate()
+        );
+        assert_eq!(3, config.guilds[&1].create_commands().len());
+    }
+
+    #[test]
+    fn shipped_config_is_valid() {
+        assert!(Config::check("config/mysteriousbot.yml").is_empty());
+    }
 }
@@ -0,0 +1,406 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+use serde::Deserialize;
+use serenity::model::id::GuildId;
+use snafu::ResultExt;
+use tokio::sync::Mutex;
+
+use crate::store::{DbSnafu, PoolSnafu, Result};
+
+/// Who shares a cooldown. A global cooldown is still per guild, as
+/// each guild gets its own copy of everything it configures.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CooldownScope {
+    #[default]
+    Global,
+    PerUser,
+    PerChannel,
+    PerUserPerChannel,
+}
+
+/// Identifies one running cooldown within a [`Cooldowns`] store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CooldownKey {
+    pub user_id: Option<u64>,
+    pub channel_id: Option<u64>,
+}
+
+impl CooldownScope {
+    /// The key of the cooldown a user posting in a channel falls under.
+    pub fn key(self, user_id: u64, channel_id: u64) -> CooldownKey {
+        let (user_id, channel_id) = match self {
+            Self::Global => (None, None),
+            Self::PerUser => (Some(user_id), None),
+            Self::PerChannel => (None, Some(channel_id)),
+            Self::PerUserPerChannel => (Some(user_id), Some(channel_id)),
+        };
+
+        CooldownKey {
+            user_id,
+            channel_id,
+        }
+    }
+}
+
+/// Where a set of cooldowns is kept in the database: the guild and a
+/// stable identifier of what they belong to.
+#[derive(Debug, Clone, Copy)]
+pub struct CooldownOwner<'a> {
+    pub guild_id: GuildId,
+    pub id: &'a str,
+}
+
+/// How often something can be triggered: once per `cooldown`, though
+/// up to `burst` times in quick succession after going untriggered for
+/// a while. Each trigger uses up one of the burst, and one comes back
+/// every `cooldown`, like tokens in a bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CooldownLimit {
+    pub cooldown: Duration,
+    pub burst: NonZeroU32,
+}
+
+impl CooldownLimit {
+    /// A plain cooldown, with no burst.
+    pub const fn new(cooldown: Duration) -> Self {
+        Self {
+            cooldown,
+            burst: NonZeroU32::MIN,
+        }
+    }
+}
+
+/// When each cooldown was last triggered. For a limit with a burst,
+/// that's pushed a cooldown's length into the future by each trigger
+/// made before the last one's cooldown ran out, so it says how much of
+/// the burst has been used. Cooldowns which have run out are evicted
+/// whenever another one is triggered, so the store only ever holds
+/// those which are still running. They are loaded from the database
+/// the first time they're needed.
+#[derive(Debug, Default)]
+pub struct Cooldowns {
+    last_triggered: Mutex<Option<HashMap<CooldownKey, SystemTime>>>,
+}
+
+/// Cooldowns are runtime state rather than config, so they never tell
+/// two otherwise identical definitions apart.
+impl PartialEq for Cooldowns {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Cooldowns {
+    /// Triggers the cooldown for a key unless it is still running with
+    /// none of its burst left, returning how long until it can be
+    /// triggered if not. A database which can't be read or written is
+    /// logged, and the cooldown kept in memory.
+    pub async fn try_trigger(
+        &self,
+        store: &CooldownStore,
+        owner: CooldownOwner<'_>,
+        key: CooldownKey,
+        limit: CooldownLimit,
+        now: SystemTime,
+    ) -> Result<(), Duration> {
+        let cooldown = limit.cooldown;
+        let mut last_triggered = self.last_triggered.lock().await;
+        let last_triggered = last_triggered.get_or_insert_with(|| {
+            store.load(owner).unwrap_or_else(|e| {
+                log::error!(
+                    "Failed to load cooldowns for {} in guild {} with error {:#?}",
+                    owner.id,
+                    owner.guild_id,
+                    e
+                );
+                HashMap::new()
+            })
+        });
+        let running = |t: &SystemTime| now.duration_since(*t).unwrap_or_default() < cooldown;
+        let triggered = match last_triggered.get(&key).filter(|t| running(t)) {
+            Some(previous) => {
+                let triggered = *previous + cooldown;
+                // how far ahead the rest of the burst lets it get
+                let burst_until = now.checked_add(cooldown.saturating_mul(limit.burst.get() - 1));
+
+                match burst_until.map(|until| triggered.duration_since(until)) {
+                    Some(Ok(wait)) if !wait.is_zero() => return Err(wait),
+                    _ => triggered,
+                }
+            }
+            None => now,
+        };
+
+        last_triggered.retain(|_, t| running(t));
+        last_triggered.insert(key, triggered);
+
+        if let Err(e) = store.save(owner, key, cooldown, triggered, now) {
+            log::error!(
+                "Failed to save cooldown for {} in guild {} with error {:#?}",
+                owner.id,
+                owner.guild_id,
+                e
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps cooldowns in the database, so that restarting doesn't re-arm
+/// every one of them at once.
+pub struct CooldownStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl CooldownStore {
+    /// Keeps cooldowns in the given pool's `cooldowns` table.
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    fn load(&self, owner: CooldownOwner) -> Result<HashMap<CooldownKey, SystemTime>> {
+        let connection = self.get_connection()?;
+        let mut statement = connection
+            .prepare(
+                "SELECT user_id, channel_id, last_triggered FROM cooldowns \
+                    WHERE guild_id = ? AND owner = ?;",
+            )
+            .context(DbSnafu)?;
+        let rows = statement
+            .query_map(params![owner.guild_id.get(), owner.id], |row| {
+                let key = CooldownKey {
+                    user_id: unscoped(row.get(0)?),
+                    channel_id: unscoped(row.get(1)?),
+                };
+                let last_triggered: DateTime<Utc> = row.get(2)?;
+
+                Ok((key, last_triggered.into()))
+            })
+            .context(DbSnafu)?;
+
+        rows.collect::<Result<_, _>>().context(DbSnafu)
+    }
+
+    /// Records a cooldown being triggered and forgets the owner's
+    /// cooldowns which have run out.
+    fn save(
+        &self,
+        owner: CooldownOwner,
+        key: CooldownKey,
+        cooldown: Duration,
+        triggered: SystemTime,
+        now: SystemTime,
+    ) -> Result<()> {
+        let mut connection = self.get_connection()?;
+        let tx = connection.transaction().context(DbSnafu)?;
+        let now = DateTime::<Utc>::from(now);
+        let triggered = DateTime::<Utc>::from(triggered);
+        let expired = chrono::Duration::from_std(cooldown)
+            .ok()
+            .and_then(|cooldown| now.checked_sub_signed(cooldown));
+
+        if let Some(expired) = expired {
+            tx.execute(
+                "DELETE FROM cooldowns \
+                    WHERE guild_id = ? AND owner = ? AND last_triggered <= ?;",
+                params![owner.guild_id.get(), owner.id, expired],
+            )
+            .context(DbSnafu)?;
+        }
+
+        tx.execute(
+            "INSERT OR REPLACE INTO cooldowns \
+                (guild_id, owner, user_id, channel_id, last_triggered) \
+                VALUES(?, ?, ?, ?, ?);",
+            params![
+                owner.guild_id.get(),
+                owner.id,
+                key.user_id.unwrap_or_default(),
+                key.channel_id.unwrap_or_default(),
+                triggered,
+            ],
+        )
+        .context(DbSnafu)?;
+        tx.commit().context(DbSnafu)
+    }
+
+    fn get_connection(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        self.pool.get().context(PoolSnafu)
+    }
+}
+
+/// Ids are stored as 0 for cooldowns which aren't scoped by them, as
+/// SQLite never considers NULLs in a primary key equal.
+fn unscoped(id: u64) -> Option<u64> {
+    match id {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroU32,
+        time::{Duration, SystemTime},
+    };
+
+    use serenity::model::id::GuildId;
+
+    use super::{CooldownLimit, CooldownOwner, CooldownScope, CooldownStore, Cooldowns};
+    use crate::{
+        counter::tests::memory_pool,
+        migrations::{self, Settings},
+    };
+
+    const OWNER: CooldownOwner = CooldownOwner {
+        guild_id: GuildId::new(1),
+        id: "wank",
+    };
+
+    fn store() -> CooldownStore {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        CooldownStore::new(pool)
+    }
+
+    #[tokio::test]
+    async fn cooldowns_are_scoped() {
+        let store = store();
+        let cooldowns = Cooldowns::default();
+        let cooldown = CooldownLimit::new(Duration::from_secs(60));
+        let now = SystemTime::now();
+        let scope = CooldownScope::PerUser;
+        let try_trigger = |user_id, channel_id, now| {
+            cooldowns.try_trigger(&store, OWNER, scope.key(user_id, channel_id), cooldown, now)
+        };
+
+        assert!(try_trigger(1, 1, now).await.is_ok());
+        assert_eq!(Err(cooldown.cooldown), try_trigger(1, 2, now).await);
+        assert!(try_trigger(2, 1, now).await.is_ok());
+        assert!(try_trigger(1, 1, now + cooldown.cooldown).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn expired_cooldowns_are_evicted() {
+        let store = store();
+        let cooldowns = Cooldowns::default();
+        let cooldown = CooldownLimit::new(Duration::from_secs(60));
+        let now = SystemTime::now();
+        let scope = CooldownScope::PerUserPerChannel;
+
+        for user_id in 1..=100 {
+            assert!(cooldowns
+                .try_trigger(&store, OWNER, scope.key(user_id, 1), cooldown, now)
+                .await
+                .is_ok());
+        }
+
+        assert!(cooldowns
+            .try_trigger(
+                &store,
+                OWNER,
+                scope.key(1, 2),
+                cooldown,
+                now + cooldown.cooldown
+            )
+            .await
+            .is_ok());
+        assert_eq!(
+            1,
+            cooldowns
+                .last_triggered
+                .lock()
+                .await
+                .as_ref()
+                .unwrap()
+                .len()
+        );
+        assert_eq!(1, store.load(OWNER).unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn cooldowns_survive_restarts() {
+        let store = store();
+        let cooldown = CooldownLimit::new(Duration::from_secs(60));
+        let now = SystemTime::now();
+        let key = CooldownScope::Global.key(1, 1);
+
+        assert!(Cooldowns::default()
+            .try_trigger(&store, OWNER, key, cooldown, now)
+            .await
+            .is_ok());
+
+        // a fresh set of cooldowns, as after a restart
+        let cooldowns = Cooldowns::default();
+        let later = now + Duration::from_secs(1);
+        assert!(cooldowns
+            .try_trigger(&store, OWNER, key, cooldown, later)
+            .await
+            .is_err());
+
+        let elsewhere = CooldownOwner {
+            guild_id: GuildId::new(2),
+            ..OWNER
+        };
+        assert!(Cooldowns::default()
+            .try_trigger(&store, elsewhere, key, cooldown, later)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn cooldowns_allow_bursts() {
+        let store = store();
+        let cooldowns = Cooldowns::default();
+        let limit = CooldownLimit {
+            cooldown: Duration::from_secs(60),
+            burst: NonZeroU32::new(3).unwrap(),
+        };
+        let now = SystemTime::now();
+        let key = CooldownScope::Global.key(1, 1);
+        let try_trigger = |seconds| {
+            cooldowns.try_trigger(
+                &store,
+                OWNER,
+                key,
+                limit,
+                now + Duration::from_secs(seconds),
+            )
+        };
+
+        assert!(try_trigger(0).await.is_ok());
+        assert!(try_trigger(1).await.is_ok());
+        assert!(try_trigger(2).await.is_ok());
+        // the first of the burst comes back a minute after it was used
+        assert_eq!(Err(Duration::from_secs(57)), try_trigger(3).await);
+        assert!(try_trigger(60).await.is_ok());
+        assert_eq!(Err(Duration::from_secs(60)), try_trigger(60).await);
+
+        // the burst is remembered across restarts too
+        let restarted = Cooldowns::default();
+        assert_eq!(
+            Err(Duration::from_secs(50)),
+            restarted
+                .try_trigger(&store, OWNER, key, limit, now + Duration::from_secs(70))
+                .await
+        );
+        // and comes back in full once it's gone unused long enough
+        for _ in 0..3 {
+            assert!(restarted
+                .try_trigger(&store, OWNER, key, limit, now + Duration::from_secs(300))
+                .await
+                .is_ok());
+        }
+    }
+}
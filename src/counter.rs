@@ -1,50 +1,120 @@
-use r2d2::{Error as R2d2Error, Pool, PooledConnection};
+use chrono::{DateTime, Duration, Utc};
+use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Connection, Error as RusqliteError, OptionalExtension};
-use serenity::model::id::UserId;
-use snafu::{ResultExt, Snafu};
-use std::{borrow::Cow, ops::Deref};
-
-#[derive(Debug, Snafu)]
-pub enum Error {
-    Pool { source: R2d2Error },
-    Db { source: RusqliteError },
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use snafu::ResultExt;
+use std::{borrow::Cow, ops::Deref, str::FromStr};
+
+use crate::store::{DbSnafu, PoolSnafu, Result};
+
+/// Where a change to a counter came from, recorded alongside it.
+#[derive(Debug, Clone)]
+pub struct EventSource {
+    pub channel_id: ChannelId,
+    pub message_id: Option<MessageId>,
+    /// The id of the autoresponder which made the change, so that it
+    /// only ever takes back its own counts.
+    pub origin: Option<String>,
 }
 
-pub type Result<T, E = Error> = std::result::Result<T, E>;
+/// A change a moderator makes to a count by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjustment {
+    /// Overrides the count, which isn't recorded as an event.
+    Set(u64),
+    /// Adds to the count, or takes from it though never below zero.
+    Add(i64),
+}
+
+/// Where a subject stands in a counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Standing {
+    /// One more than the number of subjects with a higher count.
+    pub rank: u64,
+    pub user_id: UserId,
+    pub count: u64,
+}
+
+impl Standing {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            rank: row.get(0)?,
+            user_id: UserId::new(row.get(1)?),
+            count: row.get(2)?,
+        })
+    }
+}
+
+/// A rolling window of time to tally counter events over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    #[default]
+    AllTime,
+}
+
+impl Period {
+    /// Every period, with the name it goes by in commands.
+    pub const ALL: [(&'static str, Period); 4] = [
+        ("day", Period::Day),
+        ("week", Period::Week),
+        ("month", Period::Month),
+        ("all", Period::AllTime),
+    ];
+
+    /// The name this period goes by in commands.
+    pub fn name(self) -> &'static str {
+        Period::ALL
+            .iter()
+            .find(|(_, period)| *period == self)
+            .map_or("all", |(name, _)| name)
+    }
+
+    /// When this period started, if it isn't all of time.
+    fn since(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Period::Day => Some(now - Duration::days(1)),
+            Period::Week => Some(now - Duration::weeks(1)),
+            Period::Month => Some(now - Duration::days(30)),
+            Period::AllTime => None,
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Period::ALL
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, period)| *period)
+            .ok_or_else(|| format!("unknown period {}", s))
+    }
+}
 
 pub struct CounterFactory {
     pool: Pool<SqliteConnectionManager>,
 }
 
 impl CounterFactory {
-    pub fn new(pool: Pool<SqliteConnectionManager>) -> Result<Self> {
-        let connection = pool.get().context(PoolSnafu)?;
-
-        // create the tracking table if it doesn't exist already;
-        // figuring out how to version and migrate this is a problem
-        // for future mysteriouspants. screw that guy.
-        connection
-            .execute(
-                "CREATE TABLE IF NOT EXISTS \
-                    counters ( \
-                        id INTEGER PRIMARY KEY AUTOINCREMENT, \
-                        counter TEXT NOT NULL, \
-                        user_id INTEGER(64) NOT NULL, \
-                        count INTEGER NOT NULL DEFAULT 0, \
-                        CONSTRAINT \
-                            one_count_per_user UNIQUE (counter, user_id) \
-                                ON CONFLICT ROLLBACK);",
-                [],
-            )
-            .context(DbSnafu)?;
-
-        Ok(Self { pool })
+    /// Creates a factory for counters kept in the given pool, whose
+    /// database must already have been brought up to date by
+    /// [`crate::migrations::run`].
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
     }
 
-    pub fn make_counter(&self, counter_id: &str) -> Counter {
+    /// Makes the counter with the given name in the given guild. Guilds
+    /// never see each other's counters, see
+    /// [`crate::config::GuildConfig::counter_guild`] for sharing one.
+    pub fn make_counter(&self, guild_id: GuildId, counter_id: &str) -> Counter {
         Counter {
             pool: self.pool.clone(),
+            guild_id,
             counter_id: Cow::Owned(counter_id.to_owned()),
         }
     }
@@ -53,96 +123,192 @@ impl CounterFactory {
 #[derive(Debug)]
 pub struct Counter {
     pool: Pool<SqliteConnectionManager>,
+    guild_id: GuildId,
     counter_id: Cow<'static, String>,
 }
 
 impl Counter {
     /// Gets the counter for a given subject.
-    #[allow(dead_code)]
     pub fn get(&self, subject: UserId) -> Result<u64> {
         self.get_count(self.get_connection()?.deref(), subject)
     }
 
     /// Increments the counter for a given subject, returning the new
     /// value in the counter.
-    pub fn increment(&self, subject: UserId) -> Result<u64> {
+    pub fn increment(&self, subject: UserId, source: &EventSource) -> Result<u64> {
         let mut connection = self.get_connection()?;
         let tx = connection.transaction().context(DbSnafu)?;
         let mut count = self.get_count(&tx, subject)?;
 
         count += 1;
         self.set_count(&tx, subject, count)?;
+        self.record_event(&tx, subject, 1, source)?;
         tx.commit().context(DbSnafu)?;
 
         Ok(count)
     }
 
-    /// Decrements the counter for a given subject, returning the new
-    /// value in the counter.
-    #[allow(dead_code)]
-    pub fn decrement(&self, subject: UserId) -> Result<u64> {
+    /// Decrements the counter for a given subject, though never below
+    /// zero, returning the new value in the counter.
+    pub fn decrement(&self, subject: UserId, source: &EventSource) -> Result<u64> {
+        self.add(subject, -1, source)
+    }
+
+    /// Adds to the counter for a given subject, or takes from it for a
+    /// negative delta though never below zero, returning the new value
+    /// in the counter.
+    pub fn add(&self, subject: UserId, delta: i64, source: &EventSource) -> Result<u64> {
         let mut connection = self.get_connection()?;
         let tx = connection.transaction().context(DbSnafu)?;
-        let mut count = self.get_count(&tx, subject)?;
+        let previous = self.get_count(&tx, subject)?;
+        let count = self.add_count(&tx, subject, previous, delta, source)?;
 
-        #[cfg(test)]
-        assert_ne!(0, count);
-
-        count -= 1;
-        self.set_count(&tx, subject, count)?;
         tx.commit().context(DbSnafu)?;
 
         Ok(count)
     }
 
-    /// Sets the counter for a given subject.
-    #[allow(dead_code)]
-    pub fn set(&self, subject: UserId, count: u64) -> Result<()> {
-        let connection = self.get_connection()?;
-        self.set_count(&connection, subject, count)?;
-        Ok(())
-    }
-
-    /// The top counts for this counter.
-    pub fn top_counts(&self, subject: UserId) -> Result<Vec<(UserId, u64)>> {
+    /// Changes the count for a subject by hand on a moderator's behalf,
+    /// recording the change in the audit log as the given action, and
+    /// returns the count from before and after the change.
+    pub fn adjust(
+        &self,
+        subject: UserId,
+        moderator: UserId,
+        action: &str,
+        adjustment: Adjustment,
+        source: &EventSource,
+    ) -> Result<(u64, u64)> {
         let mut connection = self.get_connection()?;
         let tx = connection.transaction().context(DbSnafu)?;
+        let previous = self.get_count(&tx, subject)?;
+        let count = match adjustment {
+            // recorded as the change it makes, so that a reset takes
+            // someone off the leaderboards for each period too
+            Adjustment::Set(count) => self.add_count(
+                &tx,
+                subject,
+                previous,
+                count as i64 - previous as i64,
+                source,
+            )?,
+            Adjustment::Add(delta) => self.add_count(&tx, subject, previous, delta, source)?,
+        };
 
-        // get the top ten
-        let mut top_counts = {
-            let mut select = tx
-                .prepare(
-                    "SELECT user_id, count FROM counters \
-                WHERE counter = ? \
-                ORDER BY count DESC \
-                LIMIT 10;",
-                )
-                .context(DbSnafu)?;
-            let rows = select
-                .query_map([&self.counter_id], |row| {
-                    Ok((UserId::new(row.get(0)?), row.get::<_, u64>(1)?))
-                })
-                .context(DbSnafu)?;
+        self.record_audit(&tx, subject, moderator, action, previous, count)?;
+        tx.commit().context(DbSnafu)?;
 
-            let mut top_counts = vec![];
+        Ok((previous, count))
+    }
 
-            for row in rows {
-                top_counts.push(row.context(DbSnafu)?);
-            }
+    /// The net change a source made to the count for a subject. Only
+    /// sources with a message are told apart, and changes made before
+    /// events were recorded aren't included.
+    pub fn delta_from(&self, subject: UserId, source: &EventSource) -> Result<i64> {
+        self.get_connection()?
+            .query_row(
+                "SELECT COALESCE(SUM(delta), 0) FROM counter_events \
+                    WHERE guild_id = ? AND counter = ? AND user_id = ? \
+                    AND channel_id = ? AND message_id IS ? AND origin IS ?;",
+                params![
+                    self.guild_id.get(),
+                    &self.counter_id,
+                    subject.get(),
+                    source.channel_id.get(),
+                    source.message_id.map(MessageId::get),
+                    source.origin,
+                ],
+                |row| row.get(0),
+            )
+            .context(DbSnafu)
+    }
+
+    /// A page of this counter's standings over a period of time, best
+    /// first. Subjects with the same count share a rank.
+    pub fn standings(&self, period: Period, offset: u64, limit: u32) -> Result<Vec<Standing>> {
+        self.query_standings(
+            period,
+            "SELECT rank, user_id, count FROM standings \
+                ORDER BY rank, user_id LIMIT :limit OFFSET :offset;",
+            &[(":limit", &limit), (":offset", &offset)],
+            Standing::from_row,
+        )
+    }
+
+    /// Where a subject stands over a period of time, if they've been
+    /// counted at all.
+    pub fn standing(&self, subject: UserId, period: Period) -> Result<Option<Standing>> {
+        Ok(self
+            .query_standings(
+                period,
+                "SELECT rank, user_id, count FROM standings WHERE user_id = :subject;",
+                &[(":subject", &subject.get())],
+                Standing::from_row,
+            )?
+            .pop())
+    }
+
+    /// How many subjects have been counted over a period of time.
+    pub fn standings_len(&self, period: Period) -> Result<u64> {
+        Ok(self
+            .query_standings(period, "SELECT COUNT(*) FROM standings;", &[], |row| {
+                row.get(0)
+            })?
+            .pop()
+            .unwrap_or_default())
+    }
 
-            top_counts
+    /// Runs a query over a `standings` table of everyone counted over a
+    /// period of time, with their ranks.
+    fn query_standings<T>(
+        &self,
+        period: Period,
+        query: &str,
+        params: &[(&str, &dyn ToSql)],
+        map_row: impl FnMut(&Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>> {
+        let connection = self.get_connection()?;
+        let since = period.since(Utc::now());
+        // all time comes from the running totals, which predate events
+        // being recorded at all
+        let counts = match since {
+            None => {
+                "SELECT user_id, count FROM counters \
+                    WHERE guild_id = :guild_id AND counter = :counter AND count > 0"
+            }
+            Some(_) => {
+                "SELECT user_id, SUM(delta) AS count FROM counter_events \
+                    WHERE guild_id = :guild_id AND counter = :counter AND created_at >= :since \
+                    GROUP BY user_id \
+                    HAVING count > 0"
+            }
         };
+        // RANK() is a window function, which SQLite has had since 3.25
+        let mut statement = connection
+            .prepare(&format!(
+                "WITH counts AS ({}), \
+                    standings AS (\
+                        SELECT RANK() OVER (ORDER BY count DESC) AS rank, user_id, count \
+                        FROM counts) \
+                    {}",
+                counts, query
+            ))
+            .context(DbSnafu)?;
+        let guild_id = self.guild_id.get();
+        let mut all_params: Vec<(&str, &dyn ToSql)> =
+            vec![(":guild_id", &guild_id), (":counter", &self.counter_id)];
 
-        // if the subject isn't in the top ten, at least let them know
-        // where they stand
-        if !top_counts.iter().any(|tuple| tuple.0 == subject) {
-            top_counts.pop();
-            top_counts.push((subject, self.get_count(&tx, subject)?));
+        if let Some(since) = &since {
+            all_params.push((":since", since));
         }
 
-        tx.commit().context(DbSnafu)?;
+        all_params.extend_from_slice(params);
 
-        Ok(top_counts)
+        let rows = statement
+            .query_map(all_params.as_slice(), map_row)
+            .context(DbSnafu)?;
+
+        rows.collect::<Result<_, _>>().context(DbSnafu)
     }
 
     /// Gets the count on a given connection for a subject.
@@ -151,8 +317,8 @@ impl Counter {
             match connection
                 .query_row(
                     "SELECT count FROM counters \
-                        WHERE counter = ? AND user_id = ? LIMIT 1;",
-                    params![&self.counter_id, subject.get()],
+                        WHERE guild_id = ? AND counter = ? AND user_id = ? LIMIT 1;",
+                    params![self.guild_id.get(), &self.counter_id, subject.get()],
                     |row| row.get(0),
                 )
                 .optional()
@@ -164,15 +330,99 @@ impl Counter {
         )
     }
 
+    /// Adds to the count on a given connection for a subject whose
+    /// count was `previous`, returning the new count.
+    fn add_count(
+        &self,
+        connection: &Connection,
+        subject: UserId,
+        previous: u64,
+        delta: i64,
+        source: &EventSource,
+    ) -> Result<u64> {
+        let count = previous.saturating_add_signed(delta);
+
+        if count != previous {
+            self.set_count(connection, subject, count)?;
+            self.record_event(connection, subject, count as i64 - previous as i64, source)?;
+        }
+
+        Ok(count)
+    }
+
+    /// Records a moderator changing the count for a subject by hand in
+    /// the audit log on a given connection.
+    fn record_audit(
+        &self,
+        connection: &Connection,
+        subject: UserId,
+        moderator: UserId,
+        action: &str,
+        previous: u64,
+        count: u64,
+    ) -> Result<()> {
+        connection
+            .execute(
+                "INSERT INTO counter_audit_log \
+                    (guild_id, counter, user_id, moderator_id, action, previous, count, \
+                    created_at) \
+                    VALUES(?, ?, ?, ?, ?, ?, ?, ?);",
+                params![
+                    self.guild_id.get(),
+                    &self.counter_id,
+                    subject.get(),
+                    moderator.get(),
+                    action,
+                    previous,
+                    count,
+                    Utc::now(),
+                ],
+            )
+            .context(DbSnafu)?;
+
+        Ok(())
+    }
+
+    /// Records a change to the count on a given connection for a
+    /// subject.
+    fn record_event(
+        &self,
+        connection: &Connection,
+        subject: UserId,
+        delta: i64,
+        source: &EventSource,
+    ) -> Result<()> {
+        connection
+            .execute(
+                "INSERT INTO counter_events \
+                    (guild_id, counter, user_id, delta, channel_id, message_id, origin, \
+                    created_at) \
+                    VALUES(?, ?, ?, ?, ?, ?, ?, ?);",
+                params![
+                    self.guild_id.get(),
+                    &self.counter_id,
+                    subject.get(),
+                    delta,
+                    source.channel_id.get(),
+                    source.message_id.map(MessageId::get),
+                    source.origin,
+                    Utc::now(),
+                ],
+            )
+            .context(DbSnafu)?;
+
+        Ok(())
+    }
+
     /// Sets the count on a given connection for a subject.
     fn set_count(&self, connection: &Connection, subject: UserId, count: u64) -> Result<usize> {
         let rows_affected = connection
             .execute(
-                "INSERT INTO counters (counter, user_id, count) \
-                    VALUES(?, ?, ?) \
-                    ON CONFLICT(counter, user_id) \
+                "INSERT INTO counters (guild_id, counter, user_id, count) \
+                    VALUES(?, ?, ?, ?) \
+                    ON CONFLICT(guild_id, counter, user_id) \
                     DO UPDATE SET count = excluded.count;",
-                params![&self.counter_id, subject.get(), count],
+                params![self.guild_id.get(), &self.counter_id, subject.get(), count],
             )
             .context(DbSnafu)?;
 
@@ -188,35 +438,228 @@ impl Counter {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use chrono::{Duration, Utc};
     use r2d2::Pool;
     use r2d2_sqlite::SqliteConnectionManager;
-    use serenity::model::id::UserId;
-
-    use super::CounterFactory;
-
-    fn memory_pool() -> Pool<SqliteConnectionManager> {
-        Pool::new(SqliteConnectionManager::memory()).unwrap()
+    use rusqlite::params;
+    use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+
+    use super::{Adjustment, CounterFactory, EventSource, Period, Standing};
+    use crate::migrations::{self, Settings};
+
+    /// A pool over an in-memory database. Every in-memory connection is
+    /// its own database, so the pool holds exactly one connection.
+    pub(crate) fn memory_pool() -> Pool<SqliteConnectionManager> {
+        Pool::builder()
+            .max_size(1)
+            .build(SqliteConnectionManager::memory())
+            .unwrap()
     }
 
+    const SOURCE: EventSource = EventSource {
+        channel_id: ChannelId::new(1),
+        message_id: None,
+        origin: None,
+    };
+
     #[test]
     fn counting() {
         let pool = memory_pool();
-        let counter_factory = CounterFactory::new(pool).unwrap();
-        let counter = counter_factory.make_counter("my_counter");
+        migrations::run(&pool, &Settings::default()).unwrap();
+        let counter_factory = CounterFactory::new(pool);
+        let counter = counter_factory.make_counter(GuildId::new(1), "my_counter");
         let joe = UserId::new(1);
 
         assert!(matches!(counter.get(joe), Ok(0)));
-        assert!(matches!(counter.increment(joe), Ok(1)));
-        assert!(matches!(counter.decrement(joe), Ok(0)));
-        assert!(matches!(counter.increment(joe), Ok(1)));
+        assert!(matches!(counter.increment(joe, &SOURCE), Ok(1)));
+        assert!(matches!(counter.decrement(joe, &SOURCE), Ok(0)));
+        assert!(matches!(counter.increment(joe, &SOURCE), Ok(1)));
 
         let bob = UserId::new(2);
 
         assert!(matches!(counter.get(bob), Ok(0)));
-        assert!(matches!(counter.increment(bob), Ok(1)));
-        assert!(matches!(counter.decrement(bob), Ok(0)));
+        assert!(matches!(counter.increment(bob, &SOURCE), Ok(1)));
+        assert!(matches!(counter.decrement(bob, &SOURCE), Ok(0)));
 
         assert!(matches!(counter.get(joe), Ok(1)));
     }
+
+    #[test]
+    fn counters_are_per_guild() {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        let counter_factory = CounterFactory::new(pool);
+        let here = counter_factory.make_counter(GuildId::new(1), "my_counter");
+        let there = counter_factory.make_counter(GuildId::new(2), "my_counter");
+        let joe = UserId::new(1);
+
+        assert!(matches!(here.increment(joe, &SOURCE), Ok(1)));
+        assert!(matches!(here.increment(joe, &SOURCE), Ok(2)));
+        assert!(matches!(there.get(joe), Ok(0)));
+        assert!(matches!(there.increment(joe, &SOURCE), Ok(1)));
+        assert!(matches!(here.get(joe), Ok(2)));
+        assert_eq!(
+            vec![Standing {
+                rank: 1,
+                user_id: joe,
+                count: 1
+            }],
+            there.standings(Period::AllTime, 0, 10).unwrap()
+        );
+    }
+
+    #[test]
+    fn counts_over_periods() {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        let counter_factory = CounterFactory::new(pool.clone());
+        let counter = counter_factory.make_counter(GuildId::new(1), "my_counter");
+        let joe = UserId::new(1);
+        let bob = UserId::new(2);
+        let source = EventSource {
+            channel_id: ChannelId::new(1),
+            message_id: Some(MessageId::new(1)),
+            origin: None,
+        };
+
+        counter.increment(joe, &source).unwrap();
+        counter.increment(bob, &source).unwrap();
+        counter.increment(bob, &source).unwrap();
+        counter.increment(bob, &source).unwrap();
+        counter.decrement(bob, &source).unwrap();
+
+        // joe was also very busy a couple of weeks ago
+        counter.set_count(&pool.get().unwrap(), joe, 4).unwrap();
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO counter_events \
+                    (guild_id, counter, user_id, delta, channel_id, message_id, created_at) \
+                    VALUES(1, 'my_counter', 1, 3, 1, NULL, ?);",
+                params![Utc::now() - Duration::weeks(2)],
+            )
+            .unwrap();
+
+        let counts = |period| {
+            counter
+                .standings(period, 0, 10)
+                .unwrap()
+                .into_iter()
+                .map(|standing| (standing.user_id, standing.count))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![(bob, 2), (joe, 1)], counts(Period::Week));
+        assert_eq!(vec![(joe, 4), (bob, 2)], counts(Period::Month));
+        assert_eq!(vec![(joe, 4), (bob, 2)], counts(Period::AllTime));
+    }
+
+    #[test]
+    fn adjustments_count_over_periods() {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        let counter = CounterFactory::new(pool).make_counter(GuildId::new(1), "my_counter");
+        let (joe, bob, moderator) = (UserId::new(1), UserId::new(2), UserId::new(3));
+
+        counter.add(joe, 3, &SOURCE).unwrap();
+        counter.add(bob, 1, &SOURCE).unwrap();
+
+        assert_eq!(
+            (3, 0),
+            counter
+                .adjust(joe, moderator, "reset", Adjustment::Set(0), &SOURCE)
+                .unwrap()
+        );
+        assert_eq!(
+            (1, 5),
+            counter
+                .adjust(bob, moderator, "set", Adjustment::Set(5), &SOURCE)
+                .unwrap()
+        );
+
+        for period in [Period::Week, Period::AllTime] {
+            assert_eq!(
+                vec![(bob, 5)],
+                counter
+                    .standings(period, 0, 10)
+                    .unwrap()
+                    .into_iter()
+                    .map(|standing| (standing.user_id, standing.count))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn deltas_by_source() {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        let counter = CounterFactory::new(pool).make_counter(GuildId::new(1), "my_counter");
+        let joe = UserId::new(1);
+        let message = |message_id| EventSource {
+            channel_id: ChannelId::new(1),
+            message_id: Some(MessageId::new(message_id)),
+            origin: None,
+        };
+
+        counter.increment(joe, &message(1)).unwrap();
+        counter.increment(joe, &message(1)).unwrap();
+        counter.decrement(joe, &message(1)).unwrap();
+        counter.increment(joe, &message(2)).unwrap();
+        counter.increment(joe, &SOURCE).unwrap();
+
+        assert_eq!(1, counter.delta_from(joe, &message(1)).unwrap());
+        assert_eq!(0, counter.delta_from(joe, &message(3)).unwrap());
+        assert_eq!(1, counter.delta_from(joe, &SOURCE).unwrap());
+        assert_eq!(0, counter.delta_from(UserId::new(2), &message(2)).unwrap());
+
+        // autoresponders counting on the same message keep to their own
+        let autoresponder = |id: &str| EventSource {
+            origin: Some(id.to_owned()),
+            ..message(4)
+        };
+        counter.increment(joe, &autoresponder("fish")).unwrap();
+        assert_eq!(1, counter.delta_from(joe, &autoresponder("fish")).unwrap());
+        assert_eq!(0, counter.delta_from(joe, &autoresponder("carp")).unwrap());
+        assert_eq!(0, counter.delta_from(joe, &message(4)).unwrap());
+    }
+
+    #[test]
+    fn standings_share_ranks() {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        let counter = CounterFactory::new(pool.clone()).make_counter(GuildId::new(1), "my_counter");
+
+        for (user_id, count) in [(1, 5), (2, 3), (3, 5), (4, 1), (5, 3), (6, 0)] {
+            counter
+                .set_count(&pool.get().unwrap(), UserId::new(user_id), count)
+                .unwrap();
+        }
+
+        let ranks = |offset, limit| {
+            counter
+                .standings(Period::AllTime, offset, limit)
+                .unwrap()
+                .into_iter()
+                .map(|standing| (standing.rank, standing.user_id.get()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![(1, 1), (1, 3), (3, 2)], ranks(0, 3));
+        assert_eq!(vec![(3, 5), (5, 4)], ranks(3, 3));
+        assert_eq!(5, counter.standings_len(Period::AllTime).unwrap());
+        assert_eq!(
+            Some(Standing {
+                rank: 3,
+                user_id: UserId::new(5),
+                count: 3
+            }),
+            counter.standing(UserId::new(5), Period::AllTime).unwrap()
+        );
+        assert_eq!(
+            None,
+            counter.standing(UserId::new(6), Period::AllTime).unwrap()
+        );
+    }
 }
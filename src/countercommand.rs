@@ -0,0 +1,428 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde_with::{formats::PreferOne, serde_as, DisplayFromStr, OneOrMany};
+use serenity::{
+    all::{
+        CommandDataOptionValue, CommandOptionType, CreateAllowedMentions,
+        CreateAutocompleteResponse, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    model::{
+        application::CommandInteraction,
+        id::{RoleId, UserId},
+        mention::Mentionable,
+        permissions::Permissions,
+    },
+};
+
+use crate::{
+    config::GuildConfig,
+    counter::{Adjustment, Counter, EventSource},
+    discord::Discord,
+    handler::Services,
+    options::Arguments,
+    store,
+};
+
+/// What the built-in command is called, which no configured command
+/// can be called as well.
+pub const NAME: &str = "counter";
+
+/// Discord's limit on the number of autocomplete choices.
+const MAX_CHOICES: usize = 25;
+
+/// The built-in `/counter` command, which lets moderators look at and
+/// fix counts without going anywhere near the database.
+#[serde_as]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CounterCommand {
+    /// Roles whose members may use the command.
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    roles: Vec<u64>,
+    /// Discord permissions, such as `manage_messages`, which let a
+    /// member use the command. Discord only shows the command to
+    /// members with all of them.
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<DisplayFromStr, PreferOne>")]
+    permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Permission(Permissions);
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Permissions::from_name(&name.to_uppercase())
+            .map(Self)
+            .ok_or_else(|| format!("unknown permission {}", name))
+    }
+}
+
+/// One of the command's subcommands, with its arguments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Subcommand {
+    Get,
+    Set(u64),
+    Add(i64),
+    Reset,
+}
+
+impl CounterCommand {
+    /// The application command to register alongside the configured
+    /// ones.
+    pub fn create_command(&self) -> CreateCommand {
+        let subcommand = |name: &str, description: &str| {
+            CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "counter", "which counter")
+                        .required(true)
+                        .set_autocomplete(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::User, "user", "whose count")
+                        .required(true),
+                )
+        };
+        let create_command = CreateCommand::new(NAME)
+            .description("look at or fix someone's count")
+            .add_option(subcommand("get", "shows someone's count"))
+            .add_option(
+                subcommand("set", "sets someone's count").add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "count", "the new count")
+                        .required(true)
+                        .min_int_value(0),
+                ),
+            )
+            .add_option(
+                subcommand("add", "adds to someone's count, or takes from it").add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "amount",
+                        "how much to add, negative to take away",
+                    )
+                    .required(true),
+                ),
+            )
+            .add_option(subcommand("reset", "sets someone's count back to zero"));
+
+        match self.permissions.is_empty() {
+            true => create_command,
+            false => create_command.default_member_permissions(self.required_permissions()),
+        }
+    }
+
+    /// Checks the command can only be used by someone, returning a
+    /// description of each problem found.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.roles.is_empty() && self.permissions.is_empty() {
+            problems.push("needs roles or permissions, or anyone could change counts".to_owned());
+        }
+
+        problems
+    }
+
+    fn required_permissions(&self) -> Permissions {
+        self.permissions
+            .iter()
+            .fold(Permissions::empty(), |permissions, permission| {
+                permissions | permission.0
+            })
+    }
+
+    /// Whether a member with the given roles and permissions may use the
+    /// command. Administrators always may, just as Discord always shows
+    /// them the command.
+    fn allows(&self, roles: &[RoleId], permissions: Permissions) -> bool {
+        roles
+            .iter()
+            .any(|role_id| self.roles.contains(&role_id.get()))
+            || permissions.administrator()
+            || (!self.permissions.is_empty() && permissions.contains(self.required_permissions()))
+    }
+
+    pub async fn handle(
+        &self,
+        interaction: &CommandInteraction,
+        discord: &dyn Discord,
+        services: &Services,
+        guild_config: &GuildConfig,
+    ) {
+        let content = match self.respond(interaction, services, guild_config) {
+            Ok(content) => content,
+            Err(e) => {
+                log::error!(
+                    "Failed to run /{} for user {} with error {:#?}",
+                    NAME,
+                    interaction.user.id,
+                    e
+                );
+                "Something went wrong, the count may not have changed.".to_owned()
+            }
+        };
+        let interaction_response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(content)
+                .allowed_mentions(CreateAllowedMentions::new())
+                .ephemeral(true),
+        );
+
+        if let Err(e) = discord
+            .create_response(interaction.id, &interaction.token, interaction_response)
+            .await
+        {
+            log::error!("Failed to respond to interaction with error {:?}", e);
+        }
+    }
+
+    /// Suggests the guild's counters matching what's been typed so far.
+    pub async fn autocomplete(
+        &self,
+        interaction: &CommandInteraction,
+        discord: &dyn Discord,
+        guild_config: &GuildConfig,
+    ) {
+        let typed = interaction
+            .data
+            .autocomplete()
+            .map_or(String::new(), |option| option.value.to_lowercase());
+        let choices = guild_config
+            .counters()
+            .into_iter()
+            .filter(|counter| counter.to_lowercase().contains(&typed))
+            .take(MAX_CHOICES)
+            .fold(CreateAutocompleteResponse::new(), |response, counter| {
+                response.add_string_choice(counter, counter)
+            });
+
+        let r = discord
+            .create_response(
+                interaction.id,
+                &interaction.token,
+                CreateInteractionResponse::Autocomplete(choices),
+            )
+            .await;
+
+        if let Err(e) = r {
+            log::error!("Failed to autocomplete counters with error {:?}", e);
+        }
+    }
+
+    /// What to tell whoever used the command, after doing what they
+    /// asked if they may.
+    fn respond(
+        &self,
+        interaction: &CommandInteraction,
+        services: &Services,
+        guild_config: &GuildConfig,
+    ) -> store::Result<String> {
+        let (guild_id, member) = match (interaction.guild_id, &interaction.member) {
+            (Some(guild_id), Some(member)) => (guild_id, member),
+            _ => return Ok("This command only works in a server.".to_owned()),
+        };
+
+        if !self.allows(&member.roles, member.permissions.unwrap_or_default()) {
+            return Ok("You're not allowed to change counts.".to_owned());
+        }
+
+        let (name, options) = match interaction.data.options.first() {
+            Some(option) => match &option.value {
+                CommandDataOptionValue::SubCommand(options) => (option.name.as_str(), options),
+                _ => return Ok("Pick one of get, set, add or reset.".to_owned()),
+            },
+            None => return Ok("Pick one of get, set, add or reset.".to_owned()),
+        };
+        let arguments = Arguments::from_options(options);
+        let (counter_name, subject) = match (arguments.string("counter"), arguments.user("user")) {
+            (Some(counter_name), Some(subject)) => (counter_name, subject),
+            _ => return Ok("Give a counter and a user.".to_owned()),
+        };
+        let subcommand = match Subcommand::parse(name, &arguments) {
+            Some(subcommand) => subcommand,
+            None => return Ok(format!("There's no {} subcommand.", name)),
+        };
+
+        if !guild_config.counters().contains(&counter_name) {
+            return Ok(format!("There's no counter called {}.", counter_name));
+        }
+
+        let counter = services.counter_factory.make_counter(
+            guild_config.counter_guild(guild_id, counter_name),
+            counter_name,
+        );
+        let source = EventSource {
+            channel_id: interaction.channel_id,
+            message_id: None,
+            origin: None,
+        };
+
+        run(
+            &counter,
+            counter_name,
+            subcommand,
+            subject,
+            interaction.user.id,
+            &source,
+        )
+    }
+}
+
+impl Subcommand {
+    fn parse(name: &str, arguments: &Arguments) -> Option<Self> {
+        Some(match name {
+            "get" => Self::Get,
+            "set" => Self::Set(u64::try_from(arguments.integer("count")?).ok()?),
+            "add" => Self::Add(arguments.integer("amount")?),
+            "reset" => Self::Reset,
+            _ => return None,
+        })
+    }
+}
+
+/// Carries out a subcommand on a subject's count, recording any change
+/// in the audit log, and returns what to tell the moderator.
+fn run(
+    counter: &Counter,
+    counter_name: &str,
+    subcommand: Subcommand,
+    subject: UserId,
+    moderator: UserId,
+    source: &EventSource,
+) -> store::Result<String> {
+    let (action, adjustment) = match subcommand {
+        Subcommand::Get => {
+            return Ok(format!(
+                "{} has {} in {}.",
+                subject.mention(),
+                counter.get(subject)?,
+                counter_name
+            ))
+        }
+        Subcommand::Set(count) => ("set", Adjustment::Set(count)),
+        Subcommand::Add(amount) => ("add", Adjustment::Add(amount)),
+        Subcommand::Reset => ("reset", Adjustment::Set(0)),
+    };
+    let (previous, count) = counter.adjust(subject, moderator, action, adjustment, source)?;
+
+    Ok(format!(
+        "{}'s {} went from {} to {}.",
+        subject.mention(),
+        counter_name,
+        previous,
+        count
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::{
+        id::{ChannelId, GuildId, RoleId, UserId},
+        permissions::Permissions,
+    };
+
+    use super::{run, CounterCommand, Subcommand};
+    use crate::{
+        counter::{tests::memory_pool, CounterFactory, EventSource},
+        migrations::{self, Settings},
+    };
+
+    #[test]
+    fn countercommand_deserialization() {
+        let yaml = r#"---
+        roles: 1
+        permissions: [manage_messages, MODERATE_MEMBERS]"#;
+        let counter_command: CounterCommand = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            Permissions::MANAGE_MESSAGES | Permissions::MODERATE_MEMBERS,
+            counter_command.required_permissions()
+        );
+        assert!(counter_command.problems().is_empty());
+
+        assert!(serde_yaml::from_str::<CounterCommand>("permissions: be_cool").is_err());
+        assert_eq!(
+            1,
+            serde_yaml::from_str::<CounterCommand>("{}")
+                .unwrap()
+                .problems()
+                .len()
+        );
+    }
+
+    #[test]
+    fn countercommand_allows() {
+        let counter_command: CounterCommand =
+            serde_yaml::from_str("{ roles: 1, permissions: manage_messages }").unwrap();
+        let allows = |roles: &[u64], permissions| {
+            let roles = roles.iter().copied().map(RoleId::new).collect::<Vec<_>>();
+            counter_command.allows(&roles, permissions)
+        };
+
+        assert!(allows(&[2, 1], Permissions::empty()));
+        assert!(allows(&[], Permissions::MANAGE_MESSAGES));
+        assert!(allows(&[], Permissions::ADMINISTRATOR));
+        assert!(!allows(&[2], Permissions::SEND_MESSAGES));
+
+        let roles_only: CounterCommand = serde_yaml::from_str("roles: 1").unwrap();
+        assert!(!roles_only.allows(&[], Permissions::empty()));
+    }
+
+    #[test]
+    fn countercommand_run() {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        let counter = CounterFactory::new(pool.clone()).make_counter(GuildId::new(1), "clarus");
+        let (joe, moderator) = (UserId::new(1), UserId::new(2));
+        let source = EventSource {
+            channel_id: ChannelId::new(1),
+            message_id: None,
+            origin: None,
+        };
+        let run = |subcommand| run(&counter, "clarus", subcommand, joe, moderator, &source);
+
+        assert_eq!("<@1> has 0 in clarus.", run(Subcommand::Get).unwrap());
+        assert_eq!(
+            "<@1>'s clarus went from 0 to 5.",
+            run(Subcommand::Set(5)).unwrap()
+        );
+        assert_eq!(
+            "<@1>'s clarus went from 5 to 0.",
+            run(Subcommand::Add(-7)).unwrap()
+        );
+        assert_eq!(
+            "<@1>'s clarus went from 0 to 2.",
+            run(Subcommand::Add(2)).unwrap()
+        );
+        assert_eq!(
+            "<@1>'s clarus went from 2 to 0.",
+            run(Subcommand::Reset).unwrap()
+        );
+
+        let audit_log: Vec<(String, u64, u64)> = pool
+            .get()
+            .unwrap()
+            .prepare(
+                "SELECT action, previous, count FROM counter_audit_log \
+                    WHERE moderator_id = 2 ORDER BY id;",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                ("set".to_owned(), 0, 5),
+                ("add".to_owned(), 5, 0),
+                ("add".to_owned(), 0, 2),
+                ("reset".to_owned(), 2, 0),
+            ],
+            audit_log
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use serenity::{
+    all::{
+        Builder, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateMessage,
+        EditInteractionResponse,
+    },
+    async_trait,
+    client::Context,
+    gateway::ChunkGuildFilter,
+    model::{
+        channel::{Message, ReactionType},
+        guild::Emoji,
+        id::{ChannelId, GuildId, InteractionId, MessageId, RoleId, UserId},
+        user::User,
+    },
+    Result,
+};
+
+/// Everything the bot asks of Discord while handling events, so that
+/// tests can stand in for Discord and see what was asked of it.
+#[async_trait]
+pub trait Discord: Send + Sync {
+    /// The bot's own user.
+    fn current_user_id(&self) -> UserId;
+
+    /// A message's content with mentions written out as names, so that
+    /// triggers can match them.
+    fn content_safe(&self, message: &Message) -> String;
+
+    /// Asks for members of a guild to be sent over the gateway, which
+    /// arrive as chunks tagged with the nonce.
+    fn chunk_guild(&self, guild_id: GuildId, user_ids: Vec<UserId>, nonce: String);
+
+    async fn guild_name(&self, guild_id: GuildId) -> Result<String>;
+
+    async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<Emoji>>;
+
+    async fn message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<Message>;
+
+    async fn user(&self, user_id: UserId) -> Result<User>;
+
+    async fn send_message(&self, channel_id: ChannelId, message: CreateMessage) -> Result<Message>;
+
+    async fn direct_message(&self, user_id: UserId, message: CreateMessage) -> Result<Message>;
+
+    async fn react(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        reaction: ReactionType,
+    ) -> Result<()>;
+
+    async fn add_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()>;
+
+    async fn remove_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()>;
+
+    async fn create_response(
+        &self,
+        interaction_id: InteractionId,
+        token: &str,
+        response: CreateInteractionResponse,
+    ) -> Result<()>;
+
+    /// The message an interaction was first responded with.
+    async fn get_response(&self, token: &str) -> Result<Message>;
+
+    async fn edit_response(&self, token: &str, edit: EditInteractionResponse) -> Result<Message>;
+
+    async fn create_followup(
+        &self,
+        token: &str,
+        followup: CreateInteractionResponseFollowup,
+    ) -> Result<Message>;
+}
+
+/// The real Discord, through the context of the event being handled.
+pub struct LiveDiscord(pub Context);
+
+#[async_trait]
+impl Discord for LiveDiscord {
+    fn current_user_id(&self) -> UserId {
+        self.0.cache.current_user().id
+    }
+
+    fn content_safe(&self, message: &Message) -> String {
+        message.content_safe(&self.0)
+    }
+
+    fn chunk_guild(&self, guild_id: GuildId, user_ids: Vec<UserId>, nonce: String) {
+        self.0.shard.chunk_guild(
+            guild_id,
+            None,
+            false,
+            ChunkGuildFilter::UserIds(user_ids),
+            Some(nonce),
+        );
+    }
+
+    async fn guild_name(&self, guild_id: GuildId) -> Result<String> {
+        if let Some(name) = guild_id.name(&self.0.cache) {
+            return Ok(name);
+        }
+
+        Ok(guild_id.to_partial_guild(&self.0).await?.name)
+    }
+
+    async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<Emoji>> {
+        guild_id.emojis(&self.0).await
+    }
+
+    async fn message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<Message> {
+        channel_id.message(&self.0, message_id).await
+    }
+
+    async fn user(&self, user_id: UserId) -> Result<User> {
+        user_id.to_user(&self.0).await
+    }
+
+    async fn send_message(&self, channel_id: ChannelId, message: CreateMessage) -> Result<Message> {
+        channel_id.send_message(&self.0, message).await
+    }
+
+    async fn direct_message(&self, user_id: UserId, message: CreateMessage) -> Result<Message> {
+        user_id.direct_message(&self.0, message).await
+    }
+
+    async fn react(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        reaction: ReactionType,
+    ) -> Result<()> {
+        self.0
+            .http
+            .create_reaction(channel_id, message_id, &reaction)
+            .await
+    }
+
+    async fn add_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        self.0
+            .http
+            .add_member_role(guild_id, user_id, role_id, None)
+            .await
+    }
+
+    async fn remove_member_role(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> Result<()> {
+        self.0
+            .http
+            .remove_member_role(guild_id, user_id, role_id, None)
+            .await
+    }
+
+    async fn create_response(
+        &self,
+        interaction_id: InteractionId,
+        token: &str,
+        response: CreateInteractionResponse,
+    ) -> Result<()> {
+        response.execute(&self.0, (interaction_id, token)).await
+    }
+
+    async fn get_response(&self, token: &str) -> Result<Message> {
+        self.0.http.get_original_interaction_response(token).await
+    }
+
+    async fn edit_response(&self, token: &str, edit: EditInteractionResponse) -> Result<Message> {
+        edit.execute(&self.0, token).await
+    }
+
+    async fn create_followup(
+        &self,
+        token: &str,
+        followup: CreateInteractionResponseFollowup,
+    ) -> Result<Message> {
+        followup.execute(&self.0, (None, token)).await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use serde::Serialize;
+    use serde_json::Value;
+    use serenity::{
+        all::{
+            CreateInteractionResponse, CreateInteractionResponseFollowup, CreateMessage,
+            EditInteractionResponse,
+        },
+        async_trait,
+        cache::Cache,
+        model::{
+            channel::{Message, ReactionType},
+            guild::Emoji,
+            id::{ChannelId, GuildId, InteractionId, MessageId, RoleId, UserId},
+            user::User,
+        },
+        Error, Result,
+    };
+
+    use super::Discord;
+
+    /// Something asked of Discord. Whatever it was asked to send is kept
+    /// as the JSON it would have been sent as, as serenity's builders
+    /// can't be looked into.
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) enum Call {
+        ChunkGuild {
+            guild_id: GuildId,
+            user_ids: Vec<UserId>,
+        },
+        SendMessage {
+            channel_id: ChannelId,
+            message: Value,
+        },
+        DirectMessage {
+            user_id: UserId,
+            message: Value,
+        },
+        React {
+            channel_id: ChannelId,
+            message_id: MessageId,
+            reaction: ReactionType,
+        },
+        AddMemberRole {
+            guild_id: GuildId,
+            user_id: UserId,
+            role_id: RoleId,
+        },
+        RemoveMemberRole {
+            guild_id: GuildId,
+            user_id: UserId,
+            role_id: RoleId,
+        },
+        CreateResponse {
+            interaction_id: InteractionId,
+            response: Value,
+        },
+        EditResponse {
+            response: Value,
+        },
+        CreateFollowup {
+            followup: Value,
+        },
+    }
+
+    /// Stands in for Discord, answering from what it has been given and
+    /// recording everything asked of it.
+    #[derive(Default)]
+    pub(crate) struct RecordingDiscord {
+        pub(crate) current_user_id: UserId,
+        pub(crate) guild_names: HashMap<GuildId, String>,
+        pub(crate) emojis: HashMap<GuildId, Vec<Emoji>>,
+        pub(crate) messages: Vec<Message>,
+        pub(crate) users: Vec<User>,
+        /// An empty cache, for making content safe without one.
+        cache: Cache,
+        calls: Mutex<Vec<Call>>,
+    }
+
+    impl RecordingDiscord {
+        /// Everything asked of Discord so far, forgetting it.
+        pub(crate) fn take_calls(&self) -> Vec<Call> {
+            std::mem::take(&mut self.calls.lock().unwrap())
+        }
+
+        fn record(&self, call: Call) {
+            self.calls.lock().unwrap().push(call);
+        }
+
+        /// The message Discord answers a message being sent with.
+        fn sent(&self, channel_id: ChannelId) -> Message {
+            let mut message = Message::default();
+            message.id = MessageId::new(self.calls.lock().unwrap().len() as u64 + 1);
+            message.channel_id = channel_id;
+            message
+        }
+    }
+
+    fn json(built: impl Serialize) -> Value {
+        serde_json::to_value(built).unwrap()
+    }
+
+    fn not_found(what: &'static str) -> Error {
+        Error::Other(what)
+    }
+
+    #[async_trait]
+    impl Discord for RecordingDiscord {
+        fn current_user_id(&self) -> UserId {
+            self.current_user_id
+        }
+
+        fn content_safe(&self, message: &Message) -> String {
+            message.content_safe(&self.cache)
+        }
+
+        fn chunk_guild(&self, guild_id: GuildId, user_ids: Vec<UserId>, _nonce: String) {
+            self.record(Call::ChunkGuild { guild_id, user_ids });
+        }
+
+        async fn guild_name(&self, guild_id: GuildId) -> Result<String> {
+            self.guild_names
+                .get(&guild_id)
+                .cloned()
+                .ok_or_else(|| not_found("unknown guild"))
+        }
+
+        async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<Emoji>> {
+            Ok(self.emojis.get(&guild_id).cloned().unwrap_or_default())
+        }
+
+        async fn message(&self, channel_id: ChannelId, message_id: MessageId) -> Result<Message> {
+            self.messages
+                .iter()
+                .find(|message| message.channel_id == channel_id && message.id == message_id)
+                .cloned()
+                .ok_or_else(|| not_found("unknown message"))
+        }
+
+        async fn user(&self, user_id: UserId) -> Result<User> {
+            self.users
+                .iter()
+                .find(|user| user.id == user_id)
+                .cloned()
+                .ok_or_else(|| not_found("unknown user"))
+        }
+
+        async fn send_message(
+            &self,
+            channel_id: ChannelId,
+            message: CreateMessage,
+        ) -> Result<Message> {
+            let sent = self.sent(channel_id);
+            self.record(Call::SendMessage {
+                channel_id,
+                message: json(message),
+            });
+            Ok(sent)
+        }
+
+        async fn direct_message(&self, user_id: UserId, message: CreateMessage) -> Result<Message> {
+            let sent = self.sent(ChannelId::new(user_id.get()));
+            self.record(Call::DirectMessage {
+                user_id,
+                message: json(message),
+            });
+            Ok(sent)
+        }
+
+        async fn react(
+            &self,
+            channel_id: ChannelId,
+            message_id: MessageId,
+            reaction: ReactionType,
+        ) -> Result<()> {
+            self.record(Call::React {
+                channel_id,
+                message_id,
+                reaction,
+            });
+            Ok(())
+        }
+
+        async fn add_member_role(
+            &self,
+            guild_id: GuildId,
+            user_id: UserId,
+            role_id: RoleId,
+        ) -> Result<()> {
+            self.record(Call::AddMemberRole {
+                guild_id,
+                user_id,
+                role_id,
+            });
+            Ok(())
+        }
+
+        async fn remove_member_role(
+            &self,
+            guild_id: GuildId,
+            user_id: UserId,
+            role_id: RoleId,
+        ) -> Result<()> {
+            self.record(Call::RemoveMemberRole {
+                guild_id,
+                user_id,
+                role_id,
+            });
+            Ok(())
+        }
+
+        async fn create_response(
+            &self,
+            interaction_id: InteractionId,
+            _token: &str,
+            response: CreateInteractionResponse,
+        ) -> Result<()> {
+            self.record(Call::CreateResponse {
+                interaction_id,
+                response: json(response),
+            });
+            Ok(())
+        }
+
+        async fn get_response(&self, _token: &str) -> Result<Message> {
+            let mut response = Message::default();
+            response.id = MessageId::new(1);
+            Ok(response)
+        }
+
+        async fn edit_response(
+            &self,
+            _token: &str,
+            edit: EditInteractionResponse,
+        ) -> Result<Message> {
+            self.record(Call::EditResponse {
+                response: json(edit),
+            });
+            self.get_response("").await
+        }
+
+        async fn create_followup(
+            &self,
+            _token: &str,
+            followup: CreateInteractionResponseFollowup,
+        ) -> Result<Message> {
+            let sent = self.sent(ChannelId::default());
+            self.record(Call::CreateFollowup {
+                followup: json(followup),
+            });
+            Ok(sent)
+        }
+    }
+}
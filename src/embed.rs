@@ -0,0 +1,305 @@
+use std::fmt;
+
+use serde::{
+    de::{Error as DeError, Visitor},
+    Deserialize, Deserializer,
+};
+use serenity::{
+    all::{CreateEmbed, CreateEmbedFooter},
+    model::Timestamp,
+};
+
+use crate::template::{Template, TemplateValues};
+
+/// Discord's limit on the number of fields in an embed.
+const MAX_FIELDS: usize = 25;
+
+/// An embed to send along with a reply. Its title, description, field
+/// names and values and footer are templates, filled in the same way
+/// as the reply's text.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Embed {
+    title: Option<Template>,
+    /// Where the title links to.
+    url: Option<String>,
+    description: Option<Template>,
+    color: Option<EmbedColor>,
+    #[serde(default)]
+    fields: Vec<EmbedField>,
+    /// An image URL, or `attachment://name` for one of the reply's
+    /// attachments.
+    image: Option<String>,
+    thumbnail: Option<String>,
+    /// Discord ignores videos in embeds sent by bots, so the URL goes
+    /// into the message instead where Discord will embed a player.
+    video: Option<String>,
+    footer: Option<Template>,
+    timestamp: Option<EmbedTimestamp>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct EmbedField {
+    name: Template,
+    value: Template,
+    #[serde(default)]
+    inline: bool,
+}
+
+/// The color down the side of an embed, written as a number or as
+/// `"#rrggbb"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EmbedColor(u32);
+
+/// The time shown in an embed's footer, either when the reply is sent
+/// or a fixed RFC 3339 time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbedTimestamp {
+    Now,
+    At(Timestamp),
+}
+
+impl Embed {
+    pub fn templates(&self) -> impl Iterator<Item = &Template> {
+        self.title
+            .iter()
+            .chain(&self.description)
+            .chain(
+                self.fields
+                    .iter()
+                    .flat_map(|field| [&field.name, &field.value]),
+            )
+            .chain(&self.footer)
+    }
+
+    /// The video to put in the message, as the embed can't show it.
+    pub fn video(&self) -> Option<&str> {
+        self.video.as_deref()
+    }
+
+    pub fn render(&self, values: &TemplateValues) -> CreateEmbed {
+        let mut embed = CreateEmbed::new();
+
+        if let Some(title) = &self.title {
+            embed = embed.title(title.render(values));
+        }
+
+        if let Some(url) = &self.url {
+            embed = embed.url(url);
+        }
+
+        if let Some(description) = &self.description {
+            embed = embed.description(description.render(values));
+        }
+
+        if let Some(EmbedColor(color)) = self.color {
+            embed = embed.color(color);
+        }
+
+        embed = embed.fields(self.fields.iter().map(|field| {
+            (
+                field.name.render(values),
+                field.value.render(values),
+                field.inline,
+            )
+        }));
+
+        if let Some(image) = &self.image {
+            embed = embed.image(image);
+        }
+
+        if let Some(thumbnail) = &self.thumbnail {
+            embed = embed.thumbnail(thumbnail);
+        }
+
+        if let Some(footer) = &self.footer {
+            embed = embed.footer(CreateEmbedFooter::new(footer.render(values)));
+        }
+
+        match self.timestamp {
+            Some(EmbedTimestamp::Now) => embed.timestamp(Timestamp::now()),
+            Some(EmbedTimestamp::At(timestamp)) => embed.timestamp(timestamp),
+            None => embed,
+        }
+    }
+
+    /// Checks this embed has something to show and that Discord would
+    /// accept it, returning a description of each problem found.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.title.is_none()
+            && self.description.is_none()
+            && self.fields.is_empty()
+            && self.image.is_none()
+            && self.thumbnail.is_none()
+        {
+            problems.push(
+                "needs a title, description, fields, image or thumbnail to show anything"
+                    .to_owned(),
+            );
+        }
+
+        if self.fields.len() > MAX_FIELDS {
+            problems.push(format!("can have at most {} fields", MAX_FIELDS));
+        }
+
+        for (name, url) in [("url", &self.url), ("video", &self.video)] {
+            if let Some(url) = url {
+                if !is_web_url(url) {
+                    problems.push(format!("{} {} must be an http(s) URL", name, url));
+                }
+            }
+        }
+
+        for (name, url) in [("image", &self.image), ("thumbnail", &self.thumbnail)] {
+            if let Some(url) = url {
+                if !is_web_url(url) && !url.starts_with("attachment://") {
+                    problems.push(format!(
+                        "{} {} must be an http(s) URL or attachment://name",
+                        name, url
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+fn is_web_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+impl<'de> Deserialize<'de> for EmbedColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColorVisitor;
+
+        impl<'de> Visitor<'de> for ColorVisitor {
+            type Value = EmbedColor;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a color as a number or \"#rrggbb\"")
+            }
+
+            fn visit_u64<E: DeError>(self, v: u64) -> Result<EmbedColor, E> {
+                match u32::try_from(v) {
+                    Ok(color) if color <= 0xffffff => Ok(EmbedColor(color)),
+                    _ => Err(E::custom(format!("color {} is more than 0xffffff", v))),
+                }
+            }
+
+            fn visit_i64<E: DeError>(self, v: i64) -> Result<EmbedColor, E> {
+                match u64::try_from(v) {
+                    Ok(v) => self.visit_u64(v),
+                    Err(_) => Err(E::custom(format!("color {} is negative", v))),
+                }
+            }
+
+            fn visit_str<E: DeError>(self, v: &str) -> Result<EmbedColor, E> {
+                match v.strip_prefix('#') {
+                    Some(hex) if hex.len() == 6 => u32::from_str_radix(hex, 16)
+                        .map(EmbedColor)
+                        .map_err(|_| E::custom(format!("color {} is not #rrggbb", v))),
+                    _ => Err(E::custom(format!("color {} is not #rrggbb", v))),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmbedTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let timestamp = String::deserialize(deserializer)?;
+
+        if timestamp == "now" {
+            return Ok(Self::Now);
+        }
+
+        Timestamp::parse(&timestamp).map(Self::At).map_err(|_| {
+            D::Error::custom(format!(
+                "timestamp {} is neither now nor an RFC 3339 time",
+                timestamp
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serenity::{
+        all::{CreateEmbed, CreateEmbedFooter},
+        model::Timestamp,
+    };
+
+    use super::{Embed, EmbedColor, EmbedTimestamp};
+    use crate::template::TemplateValues;
+
+    #[test]
+    fn embed_deserialization() {
+        let yaml = r##"---
+        title: "{author} sends a fax"
+        url: https://example.com/fax
+        color: "#ff8000"
+        fields:
+          - name: from
+            value: "{author}"
+            inline: true
+          - name: to
+            value: "{channel}"
+        image: attachment://fax.png
+        footer: "{counter:faxes:ordinal} fax"
+        timestamp: 2024-01-01T00:00:00Z"##;
+        let embed: Embed = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(Some(EmbedColor(0xff8000)), embed.color);
+        assert_eq!(6, embed.templates().count());
+        assert!(embed.problems().is_empty());
+
+        let values = TemplateValues {
+            author: "Peeky".to_owned(),
+            author_mention: "<@1>".to_owned(),
+            channel: "<#2>".to_owned(),
+            guild: None,
+            indexed: vec![],
+            named: HashMap::new(),
+            counter: &|_| 3,
+        };
+        assert_eq!(
+            CreateEmbed::new()
+                .title("Peeky sends a fax")
+                .url("https://example.com/fax")
+                .color(0xff8000)
+                .field("from", "Peeky", true)
+                .field("to", "<#2>", false)
+                .image("attachment://fax.png")
+                .footer(CreateEmbedFooter::new("3rd fax"))
+                .timestamp(Timestamp::parse("2024-01-01T00:00:00Z").unwrap()),
+            embed.render(&values)
+        );
+
+        let now: Embed = serde_yaml::from_str("{ title: hi, timestamp: now }").unwrap();
+        assert_eq!(Some(EmbedTimestamp::Now), now.timestamp);
+    }
+
+    #[test]
+    fn embed_bad_values() {
+        assert!(serde_yaml::from_str::<Embed>("color: \"#fff\"").is_err());
+        assert!(serde_yaml::from_str::<Embed>("color: 0x1000000").is_err());
+        assert!(serde_yaml::from_str::<Embed>("timestamp: yesterday").is_err());
+        assert!(serde_yaml::from_str::<Embed>("titel: typo").is_err());
+
+        let timestamp: Embed = serde_yaml::from_str("timestamp: 2024-01-01T00:00:00Z").unwrap();
+        assert!(matches!(timestamp.timestamp, Some(EmbedTimestamp::At(_))));
+        // there's nothing to show but the timestamp
+        assert_eq!(1, timestamp.problems().len());
+
+        let urls: Embed =
+            serde_yaml::from_str("{ title: hi, image: fax.png, video: ftp://fax }").unwrap();
+        assert_eq!(2, urls.problems().len());
+    }
+}
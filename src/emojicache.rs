@@ -1,12 +1,11 @@
 use mysterious_cache::{ExpiringCache, SharedCache};
+use serenity::model::{guild::Emoji, id::GuildId};
 use serenity::Error as DiscordError;
-use serenity::{
-    client::Context,
-    model::{guild::Emoji, id::GuildId},
-};
 use snafu::{ResultExt, Snafu};
 use std::time::Duration;
 
+use crate::discord::Discord;
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Failed to call Discord with error {source:?}"))]
@@ -30,22 +29,29 @@ impl EmojiCache {
     }
     pub async fn get_emoji(
         &self,
-        ctx: &Context,
+        discord: &dyn Discord,
         guild_id: &GuildId,
         twemoji: &str,
     ) -> Result<Option<Emoji>, Error> {
-        let emojis = self.get_emojis(ctx, guild_id).await?;
+        let emojis = self.get_emojis(discord, guild_id).await?;
         Ok(emojis
             .iter()
             .filter(|emoji| emoji.name == twemoji)
             .next()
             .map(Emoji::clone))
     }
-    async fn get_emojis(&self, ctx: &Context, guild_id: &GuildId) -> Result<Vec<Emoji>, Error> {
+    async fn get_emojis(
+        &self,
+        discord: &dyn Discord,
+        guild_id: &GuildId,
+    ) -> Result<Vec<Emoji>, Error> {
         if let Some(emojis) = self.cache.get(&guild_id.get()) {
             return Ok(emojis);
         } else {
-            let emojis = guild_id.emojis(ctx).await.context(DiscordSnafu)?;
+            let emojis = discord
+                .guild_emojis(*guild_id)
+                .await
+                .context(DiscordSnafu)?;
             self.cache.insert(guild_id.get(), emojis.clone());
             return Ok(emojis);
         }
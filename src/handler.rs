@@ -1,103 +1,366 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use serenity::{
-    all::CreateCommand,
+    builder::CreateCommand,
     client::{Context, EventHandler},
+    http::Http,
     model::{
         application::{Command, Interaction},
-        channel::Message,
+        channel::{Message, Reaction},
+        event::GuildMembersChunkEvent,
         gateway::Ready,
+        id::GuildId,
     },
 };
 
-use crate::{config::Config, counter::CounterFactory, emojicache::EmojiCache};
+use crate::{
+    config::Config,
+    cooldown::CooldownStore,
+    counter::CounterFactory,
+    countercommand,
+    discord::{Discord, LiveDiscord},
+    emojicache::EmojiCache,
+    leaderboard,
+    membercache::MemberCache,
+    random::Random,
+    reminder::{self, ReminderStore},
+    rotation::RotationStore,
+    schedule::{self, ScheduleStore},
+};
 
 pub struct Handler {
-    pub config: Config,
-    pub emoji_cache: EmojiCache,
+    /// Shared with the scheduler, which picks up reloads through it.
+    config: Arc<RwLock<Arc<Config>>>,
+    /// The guilds we were told about on ready, kept so that commands
+    /// can be re-registered when the config is reloaded.
+    guilds: RwLock<Vec<GuildId>>,
+    /// Whether the scheduler and reminder delivery are running, as
+    /// ready comes again after every reconnect.
+    tasks_started: AtomicBool,
     pub pool: Pool<SqliteConnectionManager>,
+    pub services: Arc<Services>,
+}
+
+/// Everything commands and autoresponders use to do their work besides
+/// their config and the Discord context.
+pub struct Services {
+    pub emoji_cache: EmojiCache,
+    pub member_cache: MemberCache,
     pub counter_factory: CounterFactory,
+    pub cooldown_store: CooldownStore,
+    pub rotation_store: RotationStore,
+    pub schedule_store: ScheduleStore,
+    pub reminder_store: ReminderStore,
+    pub random: Box<dyn Random>,
+    /// Where reply attachments are read from.
+    pub media_dir: PathBuf,
 }
 
-#[serenity::async_trait]
-impl EventHandler for Handler {
-    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        let command = match interaction {
-            Interaction::Command(command) => command,
+impl Handler {
+    pub fn new(config: Config, pool: Pool<SqliteConnectionManager>, services: Services) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            guilds: RwLock::new(vec![]),
+            tasks_started: AtomicBool::new(false),
+            pool,
+            services: Arc::new(services),
+        }
+    }
+
+    /// The config currently in effect.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Loads the config at the given path and, if it is valid, swaps it
+    /// in for the current one. Guilds whose commands changed get their
+    /// application commands registered again, and guilds taken out of
+    /// the config have theirs taken away. A bad config is logged and the
+    /// current one stays in effect.
+    pub async fn reload_config(&self, http: &Http, path: &str) {
+        let mut config = match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Not reloading config: {}", e);
+                return;
+            }
+        };
+
+        let (config, previous) = {
+            let mut current = self.config.write().unwrap();
+            config.adopt_state(&current);
+            let config = Arc::new(config);
+            (config.clone(), std::mem::replace(&mut *current, config))
+        };
+
+        log::info!("Reloaded config from {}", path);
+
+        let guilds = self.guilds.read().unwrap().clone();
+
+        for guild_id in guilds {
+            let guild_config = match config.guilds.get(&guild_id.get()) {
+                Some(guild_config) => guild_config,
+                None => {
+                    // its commands would otherwise linger, failing on
+                    // every use
+                    if previous.guilds.contains_key(&guild_id.get()) {
+                        register_commands(http, guild_id, vec![]).await;
+                    }
+                    continue;
+                }
+            };
+            let unchanged =
+                previous
+                    .guilds
+                    .get(&guild_id.get())
+                    .is_some_and(|previous_guild_config| {
+                        previous_guild_config.same_commands(guild_config)
+                    });
+
+            if !unchanged {
+                register_commands(http, guild_id, guild_config.create_commands()).await;
+            }
+        }
+    }
+
+    /// Runs the autoresponders with reaction triggers for a reaction
+    /// which was added or removed.
+    pub async fn handle_reaction(&self, discord: &dyn Discord, reaction: Reaction, added: bool) {
+        let guild_id = match reaction.guild_id {
+            Some(guild_id) => guild_id,
+            None => return, // bail from the whole thing
+        };
+
+        // the twemojis we react with ourselves don't count
+        if reaction.user_id == Some(discord.current_user_id()) {
+            return;
+        }
+
+        let config = self.config();
+        let guild_config = match config.guilds.get(&guild_id.get()) {
+            Some(guild_config) => guild_config,
+            None => return, // not a guild we have config for, skip
+        };
+        let autoresponders = guild_config
+            .autoresponders
+            .iter()
+            .filter(|autoresponder| autoresponder.on_reactions())
+            .collect::<Vec<_>>();
+
+        if autoresponders.is_empty() {
+            return;
+        }
+
+        let message = match discord
+            .message(reaction.channel_id, reaction.message_id)
+            .await
+        {
+            Ok(message) => message,
+            Err(e) => {
+                log::error!(
+                    "Failed to get message {} reacted to with error {:#?}",
+                    reaction.message_id,
+                    e
+                );
+                return;
+            }
+        };
+
+        for autoresponder in autoresponders {
+            match added {
+                true => {
+                    autoresponder
+                        .handle_reaction(
+                            &self.services,
+                            discord,
+                            &reaction,
+                            &message,
+                            &guild_id,
+                            guild_config,
+                        )
+                        .await
+                }
+                false => {
+                    autoresponder
+                        .handle_reaction_removed(
+                            &self.services,
+                            discord,
+                            &reaction,
+                            &message,
+                            &guild_id,
+                            guild_config,
+                        )
+                        .await
+                }
+            }
+        }
+    }
+
+    /// Runs whichever command an interaction is for, or pages the
+    /// leaderboard one of its buttons is on.
+    pub async fn handle_interaction(&self, discord: &dyn Discord, interaction: Interaction) {
+        let (command, autocomplete) = match interaction {
+            Interaction::Command(command) => (command, false),
+            Interaction::Autocomplete(command) => (command, true),
+            Interaction::Component(component) => {
+                let config = self.config();
+                let guild_config = component
+                    .guild_id
+                    .and_then(|guild_id| config.guilds.get(&guild_id.get()));
+
+                if let Some(guild_config) = guild_config {
+                    leaderboard::handle_component(
+                        &component,
+                        discord,
+                        &self.services,
+                        guild_config,
+                    )
+                    .await;
+                }
+
+                return;
+            }
             _ => return, // not a something we know how to handle
         };
         let guild_id = match command.guild_id {
             Some(guild_id) => guild_id,
             None => return, // bail from the whole interaction
         };
-        let guild_config = match self.config.guilds.get(&guild_id.get()) {
+        let config = self.config();
+        let guild_config = match config.guilds.get(&guild_id.get()) {
             Some(guild_config) => guild_config,
             None => return, // not a guild we have config for, skip
         };
 
+        if command.data.name == countercommand::NAME {
+            if let Some(counter_command) = &guild_config.counter_command {
+                match autocomplete {
+                    true => {
+                        counter_command
+                            .autocomplete(&command, discord, guild_config)
+                            .await
+                    }
+                    false => {
+                        counter_command
+                            .handle(&command, discord, &self.services, guild_config)
+                            .await
+                    }
+                }
+
+                return;
+            }
+        }
+
+        if command.data.name == reminder::REMIND_ME || command.data.name == reminder::REMINDERS {
+            if let Some(reminders) = &guild_config.reminders {
+                if !autocomplete {
+                    reminders.handle(&command, discord, &self.services).await;
+                }
+
+                return;
+            }
+        }
+
+        if autocomplete {
+            return; // configured commands have nothing to autocomplete
+        }
+
         if let Some(c) = guild_config
             .commands
             .iter()
             .find(|c| c.alias == command.data.name.as_str())
         {
-            c.handle(&command, ctx, &self.counter_factory).await;
+            if c.permits(&command, discord).await {
+                c.handle(&command, discord, &self.services, guild_config)
+                    .await;
+            }
         }
     }
 
-    async fn message(&self, context: Context, message: Message) {
+    /// Runs the guild's autoresponders over a message.
+    pub async fn handle_message(&self, discord: &dyn Discord, message: Message) {
         let guild_id = match message.guild_id {
             Some(guild_id) => guild_id,
             None => return, // bail from the whole thing
         };
-        let guild_config = match self.config.guilds.get(&guild_id.get()) {
+        let config = self.config();
+        let guild_config = match config.guilds.get(&guild_id.get()) {
             Some(guild_config) => guild_config,
             None => return, // not a guild we have config for, skip
         };
 
         for autoresponder in &guild_config.autoresponders {
             autoresponder
-                .handle(
-                    &self.emoji_cache,
-                    &self.counter_factory,
-                    &context,
-                    &message,
-                    &guild_id,
-                )
+                .handle(&self.services, discord, &message, &guild_id, guild_config)
                 .await;
         }
     }
+}
+
+#[serenity::async_trait]
+impl EventHandler for Handler {
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        self.handle_interaction(&LiveDiscord(ctx), interaction)
+            .await;
+    }
+
+    async fn message(&self, ctx: Context, message: Message) {
+        self.handle_message(&LiveDiscord(ctx), message).await;
+    }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        self.handle_reaction(&LiveDiscord(ctx), reaction, true)
+            .await;
+    }
+
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        self.handle_reaction(&LiveDiscord(ctx), reaction, false)
+            .await;
+    }
+
+    async fn guild_members_chunk(&self, _ctx: Context, chunk: GuildMembersChunkEvent) {
+        self.services.member_cache.receive_chunk(&chunk);
+    }
 
     async fn ready(&self, ctx: Context, ready: Ready) {
         log::info!("{} is connected!", ready.user.name);
 
         let _ = Command::set_global_commands(&ctx.http, vec![]).await;
 
-        let guilds = ready.guilds.iter().map(|offline_guild| offline_guild.id);
+        if !self.tasks_started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(schedule::run(
+                LiveDiscord(ctx.clone()),
+                self.config.clone(),
+                self.services.clone(),
+            ));
+            tokio::spawn(reminder::run(
+                LiveDiscord(ctx.clone()),
+                self.services.clone(),
+            ));
+        }
+
+        let guilds = ready
+            .guilds
+            .iter()
+            .map(|offline_guild| offline_guild.id)
+            .collect::<Vec<_>>();
+
+        *self.guilds.write().unwrap() = guilds.clone();
+
+        let config = self.config();
 
         for guild in guilds {
             let guild_id = guild.get();
-            if let Some(guild_config) = self.config.guilds.get(&guild_id) {
-                log::info!("Setting application commands on Guild ID {}", guild_id);
-
-                let commands = guild_config
-                    .commands
-                    .iter()
-                    .map(|command_config| {
-                        CreateCommand::new(&command_config.alias)
-                            .description(&command_config.description)
-                    })
-                    .collect::<Vec<_>>();
-
-                let r = guild.set_commands(&ctx.http, commands).await;
-
-                match r {
-                    Ok(_) => log::info!("Application commands for guild {} set", guild_id),
-                    Err(e) => log::error!(
-                        "Failed setting commands for guild {} with error {:?}",
-                        guild_id,
-                        e
-                    ),
-                }
+            if let Some(guild_config) = config.guilds.get(&guild_id) {
+                register_commands(&ctx.http, guild, guild_config.create_commands()).await;
             } else {
                 log::info!(
                     "Connected to guild {} which has no associated config",
@@ -107,3 +370,389 @@ impl EventHandler for Handler {
         }
     }
 }
+
+async fn register_commands(http: &Http, guild: GuildId, commands: Vec<CreateCommand>) {
+    let guild_id = guild.get();
+
+    log::info!("Setting application commands on Guild ID {}", guild_id);
+
+    let r = guild.set_commands(http, commands).await;
+
+    match r {
+        Ok(_) => log::info!("Application commands for guild {} set", guild_id),
+        Err(e) => log::error!(
+            "Failed setting commands for guild {} with error {:?}",
+            guild_id,
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use r2d2::Pool;
+    use r2d2_sqlite::SqliteConnectionManager;
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+    use serenity::model::{
+        application::Interaction,
+        channel::{Message, Reaction, ReactionType},
+        guild::Emoji,
+        id::{ChannelId, EmojiId, GuildId, InteractionId, MessageId, UserId},
+    };
+
+    use super::{Handler, Services};
+    use crate::{
+        config::Config,
+        cooldown::CooldownStore,
+        counter::{tests::memory_pool, Adjustment, CounterFactory, EventSource},
+        discord::tests::{Call, RecordingDiscord},
+        emojicache::EmojiCache,
+        membercache::MemberCache,
+        migrations::{self, Settings},
+        random::tests::FixedRandom,
+        reminder::ReminderStore,
+        rotation::RotationStore,
+        schedule::ScheduleStore,
+    };
+
+    const GUILD_ID: GuildId = GuildId::new(1);
+    const AUTHOR_ID: UserId = UserId::new(10);
+
+    fn fixture<T: DeserializeOwned>(json: &str) -> T {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn message() -> Message {
+        fixture(include_str!("fixtures/message.json"))
+    }
+
+    fn command() -> Interaction {
+        fixture(include_str!("fixtures/command.json"))
+    }
+
+    /// The command fixture, but using the named command with the given
+    /// options.
+    pub(crate) fn command_with(name: &str, options: Value) -> Interaction {
+        let mut command: Value = fixture(include_str!("fixtures/command.json"));
+        command["data"]["name"] = name.into();
+        command["data"]["options"] = options;
+        serde_json::from_value(command).unwrap()
+    }
+
+    /// A press of the button with the given custom id, on the message
+    /// fixture.
+    pub(crate) fn component(custom_id: &str) -> Interaction {
+        let mut component: Value = fixture(include_str!("fixtures/command.json"));
+        component["type"] = 3.into();
+        component["data"] = json!({"custom_id": custom_id, "component_type": 2});
+        component["message"] = fixture(include_str!("fixtures/message.json"));
+        serde_json::from_value(component).unwrap()
+    }
+
+    fn reaction() -> Reaction {
+        fixture(include_str!("fixtures/reaction.json"))
+    }
+
+    /// A valid config with the given config for guild 1.
+    pub(crate) fn config(guild_config: &str) -> Config {
+        let config: Config =
+            serde_yaml::from_str(&format!("guilds:\n  1:\n{}", guild_config)).unwrap();
+        assert_eq!(Vec::<String>::new(), config.validate());
+        config
+    }
+
+    /// Services over the given pool, which is brought up to date, and
+    /// which always pick the first of anything picked at random.
+    pub(crate) fn services(pool: Pool<SqliteConnectionManager>) -> Services {
+        migrations::run(&pool, &Settings::default()).unwrap();
+
+        Services {
+            emoji_cache: EmojiCache::new(),
+            member_cache: MemberCache::new(),
+            counter_factory: CounterFactory::new(pool.clone()),
+            cooldown_store: CooldownStore::new(pool.clone()),
+            rotation_store: RotationStore::new(pool.clone()),
+            schedule_store: ScheduleStore::new(pool.clone()),
+            reminder_store: ReminderStore::new(pool),
+            random: Box::new(FixedRandom(0.0)),
+            media_dir: "media".into(),
+        }
+    }
+
+    /// A handler for the given config of guild 1, over an empty
+    /// in-memory database.
+    pub(crate) fn handler(guild_config: &str) -> Handler {
+        let pool = memory_pool();
+
+        Handler::new(config(guild_config), pool.clone(), services(pool))
+    }
+
+    /// Discord as seen by the bot with user id 3, with the reacted to
+    /// message and guild 1's twemojis.
+    pub(crate) fn discord() -> RecordingDiscord {
+        let mut discord = RecordingDiscord::default();
+        discord.current_user_id = UserId::new(3);
+        discord.emojis.insert(
+            GUILD_ID,
+            vec![fixture::<Emoji>(include_str!("fixtures/emoji.json"))],
+        );
+        discord.messages.push(message());
+        discord
+    }
+
+    fn fish(handler: &Handler) -> u64 {
+        handler
+            .services
+            .counter_factory
+            .make_counter(GUILD_ID, "fish")
+            .get(AUTHOR_ID)
+            .unwrap()
+    }
+
+    /// The content of what was responded to an interaction with.
+    pub(crate) fn response_content(call: &Call) -> &str {
+        match call {
+            Call::CreateResponse { response, .. } => response["data"]["content"].as_str().unwrap(),
+            call => panic!("expected a response, got {:?}", call),
+        }
+    }
+
+    #[tokio::test]
+    async fn autoresponders_react_reply_and_count() {
+        let handler = handler(
+            r#"
+    autoresponders:
+      - message_matches: fish
+        counter: fish
+        reply_messages: "{author} has {counter:fish} fish"
+        twemojis: swedishfish
+      - message_matches: shark
+        reply_messages: nope"#,
+        );
+        let discord = discord();
+
+        handler.handle_message(&discord, message()).await;
+
+        let calls = discord.take_calls();
+        assert_eq!(2, calls.len(), "{:?}", calls);
+        assert_eq!(
+            Call::React {
+                channel_id: ChannelId::new(100),
+                message_id: MessageId::new(1000),
+                reaction: ReactionType::Custom {
+                    animated: false,
+                    id: EmojiId::new(30),
+                    name: Some("swedishfish".to_owned()),
+                },
+            },
+            calls[0]
+        );
+        match &calls[1] {
+            Call::SendMessage {
+                channel_id,
+                message,
+            } => {
+                assert_eq!(ChannelId::new(100), *channel_id);
+                assert_eq!(json!("Skorpion Medion has 1 fish"), message["content"]);
+                assert_eq!(json!("1000"), message["message_reference"]["message_id"]);
+            }
+            call => panic!("expected a reply, got {:?}", call),
+        }
+        assert_eq!(1, fish(&handler));
+    }
+
+    #[tokio::test]
+    async fn messages_in_other_guilds_are_ignored() {
+        let handler = handler(
+            r#"
+    autoresponders:
+      - message_matches: fish
+        counter: fish"#,
+        );
+        let discord = discord();
+        let mut message = message();
+        message.guild_id = Some(GuildId::new(2));
+
+        handler.handle_message(&discord, message).await;
+
+        assert_eq!(Vec::<Call>::new(), discord.take_calls());
+        assert_eq!(0, fish(&handler));
+    }
+
+    #[tokio::test]
+    async fn commands_respond_and_count() {
+        let handler = handler(
+            r#"
+    commands:
+      - alias: fish
+        description: Catch a fish
+        counter: fish
+        reply_messages: "{author} has {counter:fish} fish""#,
+        );
+        let discord = discord();
+
+        handler.handle_interaction(&discord, command()).await;
+
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        assert!(matches!(
+            calls[0],
+            Call::CreateResponse { interaction_id, .. } if interaction_id == InteractionId::new(2000)
+        ));
+        assert_eq!("Skorpion Medion has 1 fish", response_content(&calls[0]));
+        assert_eq!(1, fish(&handler));
+    }
+
+    #[tokio::test]
+    async fn commands_without_replies_say_they_are_done() {
+        let handler = handler(
+            r#"
+    commands:
+      - alias: fish
+        description: Catch a fish
+        counter: fish"#,
+        );
+        let discord = discord();
+
+        handler.handle_interaction(&discord, command()).await;
+
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        assert_eq!("Done.", response_content(&calls[0]));
+        match &calls[0] {
+            Call::CreateResponse { response, .. } => {
+                assert_eq!(json!(64), response["data"]["flags"])
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(1, fish(&handler));
+    }
+
+    #[tokio::test]
+    async fn commands_refuse_denied_roles() {
+        let handler = handler(
+            r#"
+    commands:
+      - alias: fish
+        description: Catch a fish
+        denied_roles: 20
+        counter: fish
+        reply_messages: caught one"#,
+        );
+        let discord = discord();
+
+        handler.handle_interaction(&discord, command()).await;
+
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        assert_eq!(
+            "You're not allowed to use this command here.",
+            response_content(&calls[0])
+        );
+        match &calls[0] {
+            Call::CreateResponse { response, .. } => {
+                assert_eq!(json!(64), response["data"]["flags"])
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(0, fish(&handler));
+    }
+
+    #[tokio::test]
+    async fn commands_refuse_during_cooldowns() {
+        let handler = handler(
+            r#"
+    commands:
+      - alias: fish
+        description: Catch a fish
+        cooldown: 60
+        counter: fish
+        reply_messages: caught one"#,
+        );
+        let discord = discord();
+
+        handler.handle_interaction(&discord, command()).await;
+        handler.handle_interaction(&discord, command()).await;
+
+        let calls = discord.take_calls();
+        assert_eq!(2, calls.len(), "{:?}", calls);
+        assert_eq!("caught one", response_content(&calls[0]));
+        assert!(response_content(&calls[1]).starts_with("Slow down!"));
+        assert_eq!(1, fish(&handler));
+    }
+
+    #[tokio::test]
+    async fn reactions_count_for_the_author() {
+        let handler = handler(
+            r#"
+    autoresponders:
+      - reaction_added:
+          emoji: 🐟
+          decrement_on_remove: true
+        counter: fish"#,
+        );
+        let discord = discord();
+
+        handler.handle_reaction(&discord, reaction(), true).await;
+        assert_eq!(1, fish(&handler));
+
+        handler.handle_reaction(&discord, reaction(), false).await;
+        assert_eq!(0, fish(&handler));
+
+        assert_eq!(Vec::<Call>::new(), discord.take_calls());
+    }
+
+    #[tokio::test]
+    async fn reactions_removed_after_a_reset_count_nothing() {
+        let handler = handler(
+            r#"
+    autoresponders:
+      - reaction_added:
+          emoji: 🐟
+          decrement_on_remove: true
+        counter: fish"#,
+        );
+        let discord = discord();
+
+        handler.handle_reaction(&discord, reaction(), true).await;
+        handler
+            .services
+            .counter_factory
+            .make_counter(GUILD_ID, "fish")
+            .adjust(
+                AUTHOR_ID,
+                UserId::new(99),
+                "reset",
+                Adjustment::Set(0),
+                &EventSource {
+                    channel_id: ChannelId::new(2),
+                    message_id: None,
+                    origin: None,
+                },
+            )
+            .unwrap();
+
+        // the reaction's count is still on record, but there's nothing
+        // left to take it back from
+        handler.handle_reaction(&discord, reaction(), false).await;
+        assert_eq!(0, fish(&handler));
+    }
+
+    #[tokio::test]
+    async fn own_reactions_are_ignored() {
+        let handler = handler(
+            r#"
+    autoresponders:
+      - reaction_added:
+          emoji: 🐟
+        counter: fish"#,
+        );
+        let mut discord = discord();
+        discord.current_user_id = UserId::new(11);
+
+        handler.handle_reaction(&discord, reaction(), true).await;
+
+        assert_eq!(0, fish(&handler));
+    }
+}
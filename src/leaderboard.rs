@@ -0,0 +1,262 @@
+use serenity::{
+    all::{
+        CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    model::{
+        application::ComponentInteraction,
+        id::{GuildId, UserId},
+    },
+};
+
+use crate::{
+    config::GuildConfig,
+    counter::{Counter, Period},
+    discord::Discord,
+    handler::Services,
+    membercache::MemberCache,
+    reply::RenderedReply,
+    responder::{Responder, Responding},
+    store,
+};
+
+/// How many rows a page shows unless told otherwise.
+pub const DEFAULT_PAGE_SIZE: u32 = 10;
+
+/// Discord's limit on the number of fields in an embed.
+pub const MAX_PAGE_SIZE: u32 = 25;
+
+/// The longest counter name which leaves its leaderboard's buttons'
+/// custom ids within Discord's limit of 100 characters.
+pub const MAX_COUNTER_CHARS: usize = 40;
+
+/// Starts the custom id of every leaderboard button.
+const CUSTOM_ID_PREFIX: &str = "leaderboard";
+
+/// A page of a counter's leaderboard, with everything needed to show
+/// another page of it when one of its buttons is pressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaderboardPage {
+    pub counter: String,
+    pub period: Period,
+    /// Whose standing the footer shows.
+    pub subject: UserId,
+    pub page_size: u32,
+    /// Counting from zero.
+    pub page: u64,
+}
+
+impl LeaderboardPage {
+    /// The custom id of a button leading to another page. The counter
+    /// goes last, as it's the only part which could have a `:` in it.
+    fn custom_id(&self, page: u64) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            CUSTOM_ID_PREFIX,
+            self.period.name(),
+            self.subject,
+            self.page_size,
+            page,
+            self.counter
+        )
+    }
+
+    /// The page a button leads to, if it's a leaderboard button.
+    pub fn from_custom_id(custom_id: &str) -> Option<Self> {
+        match custom_id.splitn(6, ':').collect::<Vec<_>>().as_slice() {
+            [CUSTOM_ID_PREFIX, period, subject, page_size, page, counter] => Some(Self {
+                counter: counter.to_string(),
+                period: period.parse().ok()?,
+                subject: subject.parse().ok()?,
+                page_size: page_size
+                    .parse()
+                    .ok()
+                    .filter(|page_size| (1..=MAX_PAGE_SIZE).contains(page_size))?,
+                page: page.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The embed showing this page of a counter's standings, with the
+    /// buttons for the pages either side of it if there are any.
+    pub async fn render(
+        &self,
+        discord: &dyn Discord,
+        guild_id: GuildId,
+        counter: &Counter,
+        member_cache: &MemberCache,
+    ) -> store::Result<RenderedReply> {
+        let page_size = u64::from(self.page_size);
+        let pages = counter
+            .standings_len(self.period)?
+            .div_ceil(page_size)
+            .max(1);
+        // the leaderboard may have shrunk since the button was made
+        let page = self.page.min(pages - 1);
+        let standings = counter.standings(self.period, page * page_size, self.page_size)?;
+        let subject_standing = counter.standing(self.subject, self.period)?;
+        let user_ids = standings
+            .iter()
+            .map(|standing| standing.user_id)
+            .chain([self.subject])
+            .collect::<Vec<_>>();
+        let names = member_cache
+            .display_names(discord, guild_id, &user_ids)
+            .await;
+        let mut embed = CreateEmbed::new();
+
+        if standings.is_empty() {
+            embed = embed.description("Nobody has been counted yet.");
+        }
+
+        for standing in standings {
+            embed = embed.field(
+                format!("#{} {}", standing.rank, names[&standing.user_id]),
+                standing.count.to_string(),
+                false,
+            );
+        }
+
+        let name = &names[&self.subject];
+        let footer = match subject_standing {
+            Some(standing) => format!(
+                "Page {} of {}\n{} is #{} with {}",
+                page + 1,
+                pages,
+                name,
+                standing.rank,
+                standing.count
+            ),
+            None => format!(
+                "Page {} of {}\n{} hasn't been counted yet",
+                page + 1,
+                pages,
+                name
+            ),
+        };
+
+        let buttons = match pages > 1 {
+            true => vec![CreateActionRow::Buttons(vec![
+                CreateButton::new(self.custom_id(page.saturating_sub(1)))
+                    .label("Previous")
+                    .disabled(page == 0),
+                CreateButton::new(self.custom_id(page + 1))
+                    .label("Next")
+                    .disabled(page + 1 >= pages),
+            ])],
+            false => vec![],
+        };
+
+        Ok(RenderedReply::from_embed(
+            embed.footer(CreateEmbedFooter::new(footer)),
+            buttons,
+        ))
+    }
+
+    /// Renders this page as [`LeaderboardPage::render`] does, logging
+    /// rather than returning any error.
+    pub async fn render_logged(
+        &self,
+        discord: &dyn Discord,
+        guild_id: GuildId,
+        counter: &Counter,
+        member_cache: &MemberCache,
+    ) -> Option<RenderedReply> {
+        match self.render(discord, guild_id, counter, member_cache).await {
+            Ok(rendered) => Some(rendered),
+            Err(e) => {
+                log::error!(
+                    "Failed to retrieve standings for counter {:?} with error {:#?}",
+                    counter,
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Shows another page of a leaderboard when one of its buttons is
+/// pressed. Any other component is ignored.
+pub async fn handle_component(
+    component: &ComponentInteraction,
+    discord: &dyn Discord,
+    services: &Services,
+    guild_config: &GuildConfig,
+) {
+    let (page, guild_id) = match (
+        LeaderboardPage::from_custom_id(&component.data.custom_id),
+        component.guild_id,
+    ) {
+        (Some(page), Some(guild_id)) => (page, guild_id),
+        _ => return,
+    };
+    // only page through counters the config still names, so buttons
+    // can't be made up to read any other counter
+    let counter_name = match guild_config
+        .counters()
+        .into_iter()
+        .find(|counter| *counter == page.counter)
+    {
+        Some(counter_name) => counter_name,
+        None => {
+            let interaction_response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("This leaderboard is gone.")
+                    .ephemeral(true),
+            );
+
+            if let Err(e) = discord
+                .create_response(component.id, &component.token, interaction_response)
+                .await
+            {
+                log::error!("Failed to page leaderboard with error {:#?}", e);
+            }
+
+            return;
+        }
+    };
+    let counter = services.counter_factory.make_counter(
+        guild_config.counter_guild(guild_id, counter_name),
+        counter_name,
+    );
+
+    Responder::new(discord, Responding::Component(component), false)
+        .respond(page.render_logged(discord, guild_id, &counter, &services.member_cache))
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::UserId;
+
+    use super::LeaderboardPage;
+    use crate::counter::Period;
+
+    #[test]
+    fn leaderboard_custom_ids() {
+        let page = LeaderboardPage {
+            counter: "verbal:morality".to_owned(),
+            period: Period::Week,
+            subject: UserId::new(139425197118849025),
+            page_size: 10,
+            page: 2,
+        };
+        let custom_id = page.custom_id(3);
+        assert_eq!(
+            "leaderboard:week:139425197118849025:10:3:verbal:morality",
+            custom_id
+        );
+        assert_eq!(
+            Some(LeaderboardPage { page: 3, ..page }),
+            LeaderboardPage::from_custom_id(&custom_id)
+        );
+
+        assert!(LeaderboardPage::from_custom_id("leaderboard:week:1:10:0").is_none());
+        assert!(LeaderboardPage::from_custom_id("leaderboard:year:1:10:0:a").is_none());
+        assert!(LeaderboardPage::from_custom_id("leaderboard:week:0:10:0:a").is_none());
+        assert!(LeaderboardPage::from_custom_id("leaderboard:week:1:26:0:a").is_none());
+        assert!(LeaderboardPage::from_custom_id("poll:week:1:10:0:a").is_none());
+    }
+}
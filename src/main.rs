@@ -1,44 +1,97 @@
-use crate::handler::Handler;
+use crate::{
+    config::Config,
+    handler::{Handler, Services},
+};
+use chrono::Utc;
+use cooldown::CooldownStore;
 use counter::CounterFactory;
 use dotenv::dotenv;
 use emojicache::EmojiCache;
+use membercache::MemberCache;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
+use random::ThreadRandom;
+use reminder::ReminderStore;
+use rotation::RotationStore;
+use schedule::ScheduleStore;
 use serenity::{all::ApplicationId, client::Client, model::gateway::GatewayIntents};
-use std::{env, fs::read_to_string};
+use std::{env, process::exit, sync::Arc};
 
+mod action;
 mod autoresponder;
 mod command;
 mod config;
+mod cooldown;
 mod counter;
+mod countercommand;
+mod discord;
+mod embed;
 mod emojicache;
 mod handler;
+mod leaderboard;
+mod membercache;
+mod migrations;
+mod options;
+mod random;
+mod reload;
+mod reminder;
+mod reply;
+mod responder;
+mod rotation;
+mod schedule;
+mod store;
+mod template;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok(); // enable use of .env files
     env_logger::init();
+    let config_file =
+        &env::var("MYSTERIOUSBOT_CONFIG").unwrap_or("./config/mysteriousbot.yml".to_owned());
+    let args = env::args().collect::<Vec<_>>();
+
+    if args.get(1).map(String::as_str) == Some("check-config") {
+        check_config(args.get(2).unwrap_or(config_file));
+    }
+
+    if args.get(1).map(String::as_str) == Some("schedules") {
+        list_schedules(
+            args.get(2).unwrap_or(config_file),
+            args.get(3).map_or("5", String::as_str),
+        );
+    }
+
     let application_id: u64 = env::var("DISCORD_APPLICATION_ID")
         .expect("DISCORD_APPLICATION_ID environment variable is unset, exiting")
         .parse()
         .expect("DISCORD_APPLICATION_ID is not an integer, exiting");
     let token =
         &env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN environment variable is unset, exiting");
-    let config_file =
-        &env::var("MYSTERIOUSBOT_CONFIG").unwrap_or("./config/mysteriousbot.yml".to_owned());
     let db_file = &env::var("MYSTERIOUSBOT_DB").unwrap_or("./db/mysteriousbot.sqlite3".to_owned());
     let pool = Pool::new(SqliteConnectionManager::file(db_file))
         .expect("Couldn't put database in the pool. Party foul.");
-    let handler = Handler {
-        config: serde_yaml::from_str(
-            &read_to_string(config_file)
-                .expect(&format!("Config file at {} could not be read", config_file)),
-        )
-        .expect("Config could not be parsed"),
+    let config = Config::load(config_file).expect("Config could not be loaded");
+    migrations::run(
+        &pool,
+        &migrations::Settings {
+            default_counter_guild: config.default_counter_guild,
+        },
+    )
+    .expect("Couldn't migrate the database");
+    let services = Services {
         emoji_cache: EmojiCache::new(),
-        counter_factory: CounterFactory::new(pool.clone()).expect("Cannot create counter factory"),
-        pool,
+        member_cache: MemberCache::new(),
+        counter_factory: CounterFactory::new(pool.clone()),
+        cooldown_store: CooldownStore::new(pool.clone()),
+        rotation_store: RotationStore::new(pool.clone()),
+        schedule_store: ScheduleStore::new(pool.clone()),
+        reminder_store: ReminderStore::new(pool.clone()),
+        random: Box::new(ThreadRandom),
+        media_dir: env::var("MYSTERIOUSBOT_MEDIA")
+            .unwrap_or("./media".to_owned())
+            .into(),
     };
+    let handler = Arc::new(Handler::new(config, pool, services));
     let mut client = Client::builder(
         token,
         GatewayIntents::GUILD_MESSAGES
@@ -46,11 +99,77 @@ async fn main() {
             | GatewayIntents::MESSAGE_CONTENT,
     )
     .application_id(ApplicationId::new(application_id))
-    .event_handler(handler)
+    .event_handler_arc(handler.clone())
     .await
     .expect("Error creating client");
 
+    tokio::spawn(reload::watch_config(
+        handler,
+        client.http.clone(),
+        config_file.clone(),
+    ));
+
     if let Err(why) = client.start().await {
         println!("An error occurred while running the client: {:?}", why);
     }
 }
+
+/// Reports every problem with the config file at the given path and
+/// exits, unsuccessfully if there were any.
+fn check_config(path: &str) -> ! {
+    let problems = Config::check(path);
+
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+
+    if problems.is_empty() {
+        println!("{} is valid", path);
+        exit(0);
+    } else {
+        eprintln!("{} has {} problem(s)", path, problems.len());
+        exit(1);
+    }
+}
+
+/// Prints when each schedule in the config file at the given path will
+/// next post, without posting anything, and exits.
+fn list_schedules(path: &str, count: &str) -> ! {
+    let count: usize = match count.parse() {
+        Ok(count) => count,
+        Err(_) => {
+            eprintln!("{} is not a number of runs", count);
+            exit(1);
+        }
+    };
+    let config = match Config::load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+    let now = Utc::now();
+    let mut guild_ids = config.guilds.keys().collect::<Vec<_>>();
+
+    guild_ids.sort();
+
+    for guild_id in guild_ids {
+        for schedule in &config.guilds[guild_id].schedules {
+            println!(
+                "guild {}: {} ({} in {}) posts in channel {}",
+                guild_id,
+                schedule.id,
+                schedule.cron.as_str(),
+                schedule.timezone,
+                schedule.channel
+            );
+
+            for run in schedule.upcoming(now, count) {
+                println!("  {}", run.format("%a %Y-%m-%d %H:%M %Z"));
+            }
+        }
+    }
+
+    exit(0);
+}
@@ -0,0 +1,203 @@
+use mysterious_cache::{ExpiringCache, SharedCache};
+use serenity::model::{
+    event::GuildMembersChunkEvent,
+    id::{GuildId, UserId},
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+use crate::discord::Discord;
+
+/// Discord's limit on the number of members asked for in one request.
+const MAX_CHUNK_USERS: usize = 100;
+
+/// How long to wait for Discord to send the members asked for.
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A cache of members' display names, keyed by guild and user. `None`
+/// means the user isn't in the guild any more, so they aren't asked
+/// for again every time they show up.
+type NameCache = SharedCache<ExpiringCache<(u64, u64), Option<String>>, (u64, u64), Option<String>>;
+
+/// An LRU Cache which holds members' display names - cached because
+/// asking Discord for every row of a leaderboard is slow enough to miss
+/// the interaction deadline. Missing names are asked for all at once
+/// with a guild member chunk request over the gateway, which answers
+/// through [`MemberCache::receive_chunk`].
+pub struct MemberCache {
+    cache: NameCache,
+    /// Requests waiting on their chunks, by nonce.
+    pending: Mutex<HashMap<String, oneshot::Sender<()>>>,
+    next_nonce: AtomicU64,
+}
+
+impl MemberCache {
+    pub fn new() -> Self {
+        Self {
+            cache: SharedCache::with_cache(ExpiringCache::with_capacity_and_timeout(
+                10_000,
+                Duration::from_secs(60 * 60),
+            )),
+            pending: Mutex::new(HashMap::new()),
+            next_nonce: AtomicU64::new(0),
+        }
+    }
+
+    /// The display name of each user in the guild, or a label saying
+    /// they're a former member for those Discord couldn't find in it,
+    /// so every user gets a name. Users whose lookup timed out are only
+    /// labelled with their id, as they may well still be members.
+    pub async fn display_names(
+        &self,
+        discord: &dyn Discord,
+        guild_id: GuildId,
+        user_ids: &[UserId],
+    ) -> HashMap<UserId, String> {
+        let missing = self.missing(guild_id, user_ids);
+
+        for missing in missing.chunks(MAX_CHUNK_USERS) {
+            let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed).to_string();
+            let (sender, receiver) = oneshot::channel();
+            self.pending.lock().unwrap().insert(nonce.clone(), sender);
+            discord.chunk_guild(guild_id, missing.to_vec(), nonce.clone());
+
+            if tokio::time::timeout(CHUNK_TIMEOUT, receiver).await.is_err() {
+                log::error!(
+                    "Timed out waiting for {} members of guild {}",
+                    missing.len(),
+                    guild_id
+                );
+                self.pending.lock().unwrap().remove(&nonce);
+            }
+        }
+
+        self.names(guild_id, user_ids)
+    }
+
+    /// Caches the members in a chunk Discord sent in answer to
+    /// [`MemberCache::display_names`], and those it couldn't find.
+    pub fn receive_chunk(&self, chunk: &GuildMembersChunkEvent) {
+        let names = chunk
+            .members
+            .iter()
+            .map(|(user_id, member)| (*user_id, Some(member.display_name().to_owned())))
+            .chain(
+                chunk
+                    .not_found
+                    .iter()
+                    .map(|id| (UserId::new(id.get()), None)),
+            );
+
+        self.insert(chunk.guild_id, names);
+
+        if chunk.chunk_index + 1 == chunk.chunk_count {
+            if let Some(nonce) = &chunk.nonce {
+                self.finish(nonce);
+            }
+        }
+    }
+
+    /// The users whose names aren't cached, each only once.
+    fn missing(&self, guild_id: GuildId, user_ids: &[UserId]) -> Vec<UserId> {
+        let mut missing = vec![];
+
+        for user_id in user_ids {
+            if !missing.contains(user_id)
+                && self.cache.get(&(guild_id.get(), user_id.get())).is_none()
+            {
+                missing.push(*user_id);
+            }
+        }
+
+        missing
+    }
+
+    fn insert(&self, guild_id: GuildId, names: impl Iterator<Item = (UserId, Option<String>)>) {
+        for (user_id, name) in names {
+            self.cache.insert((guild_id.get(), user_id.get()), name);
+        }
+    }
+
+    fn finish(&self, nonce: &str) {
+        if let Some(sender) = self.pending.lock().unwrap().remove(nonce) {
+            // the request gave up waiting if this fails
+            let _ = sender.send(());
+        }
+    }
+
+    fn names(&self, guild_id: GuildId, user_ids: &[UserId]) -> HashMap<UserId, String> {
+        user_ids
+            .iter()
+            .map(|user_id| {
+                let name = match self.cache.get(&(guild_id.get(), user_id.get())) {
+                    Some(Some(name)) => name,
+                    Some(None) => format!("former member ({})", user_id),
+                    None => format!("member ({})", user_id),
+                };
+
+                (*user_id, name)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serenity::model::id::{GuildId, UserId};
+    use tokio::sync::oneshot;
+
+    use super::MemberCache;
+
+    #[test]
+    fn member_names() {
+        let cache = MemberCache::new();
+        let guild_id = GuildId::new(1);
+        let user_ids = [
+            UserId::new(2),
+            UserId::new(3),
+            UserId::new(2),
+            UserId::new(4),
+        ];
+        assert_eq!(
+            vec![UserId::new(2), UserId::new(3), UserId::new(4)],
+            cache.missing(guild_id, &user_ids)
+        );
+
+        cache.insert(
+            guild_id,
+            [
+                (UserId::new(2), Some("Peeky".to_owned())),
+                (UserId::new(3), None),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(vec![UserId::new(4)], cache.missing(guild_id, &user_ids));
+        assert_eq!(3, cache.missing(GuildId::new(5), &user_ids).len());
+
+        let names = cache.names(guild_id, &user_ids);
+        assert_eq!("Peeky", names[&UserId::new(2)]);
+        assert_eq!("former member (3)", names[&UserId::new(3)]);
+        // never found, as the lookup timed out
+        assert_eq!("member (4)", names[&UserId::new(4)]);
+    }
+
+    #[test]
+    fn member_chunks_finish_requests() {
+        let cache = MemberCache::new();
+        let (sender, mut receiver) = oneshot::channel();
+        cache.pending.lock().unwrap().insert("7".to_owned(), sender);
+
+        cache.finish("8");
+        assert!(receiver.try_recv().is_err());
+        cache.finish("7");
+        assert!(receiver.try_recv().is_ok());
+        assert!(cache.pending.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+use r2d2::{Error as R2d2Error, Pool};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, Error as RusqliteError, Transaction};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Failed to get a database connection with error {source}"))]
+    Pool { source: R2d2Error },
+    #[snafu(display("Database error {source}"))]
+    Db { source: RusqliteError },
+    #[snafu(display(
+        "Database schema version {found} is newer than the newest known version {known}"
+    ))]
+    TooNew { found: u32, known: u32 },
+    #[snafu(display(
+        "{rows} existing counters belong to no guild, set default_counter_guild in the \
+            config to say which guild they came from"
+    ))]
+    NoDefaultCounterGuild { rows: u64 },
+    #[snafu(display("Migration {version} ({description}) failed: {source}"))]
+    Migration {
+        version: u32,
+        description: &'static str,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Things some migrations need to know which the database can't tell
+/// them.
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// The guild which counters from before counters were scoped per
+    /// guild are assigned to.
+    pub default_counter_guild: Option<u64>,
+}
+
+pub struct Migration {
+    description: &'static str,
+    apply: fn(&Transaction, &Settings) -> Result<()>,
+}
+
+/// Every migration, oldest first. A migration's schema version is its
+/// position in this list counting from one, so never reorder or edit a
+/// migration which has shipped - add another one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "create counters",
+        apply: |tx, _| sql(tx, include_str!("migrations/0001_create_counters.sql")),
+    },
+    Migration {
+        description: "scope counters per guild",
+        apply: scope_counters_per_guild,
+    },
+    Migration {
+        description: "create counter events",
+        apply: |tx, _| {
+            sql(
+                tx,
+                include_str!("migrations/0003_create_counter_events.sql"),
+            )
+        },
+    },
+    Migration {
+        description: "create cooldowns",
+        apply: |tx, _| sql(tx, include_str!("migrations/0004_create_cooldowns.sql")),
+    },
+    Migration {
+        description: "create rotations",
+        apply: |tx, _| sql(tx, include_str!("migrations/0005_create_rotations.sql")),
+    },
+    Migration {
+        description: "create counter audit log",
+        apply: |tx, _| {
+            sql(
+                tx,
+                include_str!("migrations/0006_create_counter_audit_log.sql"),
+            )
+        },
+    },
+    Migration {
+        description: "create schedule runs",
+        apply: |tx, _| sql(tx, include_str!("migrations/0007_create_schedule_runs.sql")),
+    },
+    Migration {
+        description: "create reminders",
+        apply: |tx, _| sql(tx, include_str!("migrations/0008_create_reminders.sql")),
+    },
+    Migration {
+        description: "add counter event origins",
+        apply: |tx, _| {
+            sql(
+                tx,
+                include_str!("migrations/0009_add_counter_event_origins.sql"),
+            )
+        },
+    },
+];
+
+fn sql(tx: &Transaction, sql: &str) -> Result<()> {
+    tx.execute_batch(sql).context(DbSnafu)
+}
+
+fn scope_counters_per_guild(tx: &Transaction, settings: &Settings) -> Result<()> {
+    let rows: u64 = tx
+        .query_row("SELECT COUNT(*) FROM counters;", [], |row| row.get(0))
+        .context(DbSnafu)?;
+
+    sql(
+        tx,
+        include_str!("migrations/0002_create_counters_by_guild.sql"),
+    )?;
+
+    if rows > 0 {
+        let guild_id = settings
+            .default_counter_guild
+            .context(NoDefaultCounterGuildSnafu { rows })?;
+
+        tx.execute(
+            "INSERT INTO counters_by_guild (guild_id, counter, user_id, count) \
+                SELECT ?, counter, user_id, count FROM counters;",
+            [guild_id],
+        )
+        .context(DbSnafu)?;
+    }
+
+    sql(
+        tx,
+        "DROP TABLE counters; \
+            ALTER TABLE counters_by_guild RENAME TO counters;",
+    )
+}
+
+/// Brings the database up to the newest schema version, returning that
+/// version.
+pub fn run(pool: &Pool<SqliteConnectionManager>, settings: &Settings) -> Result<u32> {
+    let mut connection = pool.get().context(PoolSnafu)?;
+
+    migrate(&mut connection, MIGRATIONS, settings)
+}
+
+/// Applies every migration newer than the database's `user_version`,
+/// each in its own transaction along with the bump of `user_version`,
+/// so a failed migration leaves the database at the last good version.
+fn migrate(
+    connection: &mut Connection,
+    migrations: &[Migration],
+    settings: &Settings,
+) -> Result<u32> {
+    let known = migrations.len() as u32;
+    let found: u32 = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .context(DbSnafu)?;
+
+    // an older build has no idea what a newer schema means, so it
+    // mustn't go scribbling on it
+    ensure!(found <= known, TooNewSnafu { found, known });
+
+    for (version, migration) in (1u32..).zip(migrations).skip(found as usize) {
+        let tx = connection.transaction().context(DbSnafu)?;
+
+        (migration.apply)(&tx, settings).context(MigrationSnafu {
+            version,
+            description: migration.description,
+        })?;
+        tx.pragma_update(None, "user_version", version)
+            .context(DbSnafu)?;
+        tx.commit().context(DbSnafu)?;
+
+        log::info!(
+            "Migrated database to version {} ({})",
+            version,
+            migration.description
+        );
+    }
+
+    Ok(known)
+}
+
+#[cfg(test)]
+mod tests {
+    use r2d2::Pool;
+    use r2d2_sqlite::SqliteConnectionManager;
+    use rusqlite::Connection;
+
+    use super::{migrate, run, sql, Error, Migration, Settings, MIGRATIONS};
+    use crate::counter::tests::memory_pool;
+
+    fn user_version(connection: &Connection) -> u32 {
+        connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_database() {
+        let pool = memory_pool();
+
+        assert_eq!(
+            MIGRATIONS.len() as u32,
+            run(&pool, &Settings::default()).unwrap()
+        );
+        assert_eq!(MIGRATIONS.len() as u32, user_version(&pool.get().unwrap()));
+    }
+
+    #[test]
+    fn migrating_twice_is_harmless() {
+        let pool = memory_pool();
+
+        run(&pool, &Settings::default()).unwrap();
+        assert_eq!(
+            MIGRATIONS.len() as u32,
+            run(&pool, &Settings::default()).unwrap()
+        );
+    }
+
+    /// A database with the counters table as `CounterFactory` created
+    /// it before there were migrations.
+    fn legacy_pool() -> Pool<SqliteConnectionManager> {
+        let pool = memory_pool();
+
+        pool.get()
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE counters ( \
+                    id INTEGER PRIMARY KEY AUTOINCREMENT, \
+                    counter TEXT NOT NULL, \
+                    user_id INTEGER(64) NOT NULL, \
+                    count INTEGER NOT NULL DEFAULT 0, \
+                    CONSTRAINT \
+                        one_count_per_user UNIQUE (counter, user_id) \
+                            ON CONFLICT ROLLBACK); \
+                INSERT INTO counters (counter, user_id, count) VALUES ('a_counter', 1, 42);",
+            )
+            .unwrap();
+
+        pool
+    }
+
+    #[test]
+    fn legacy_database_keeps_its_counts() {
+        let pool = legacy_pool();
+        let settings = Settings {
+            default_counter_guild: Some(7),
+        };
+
+        run(&pool, &settings).unwrap();
+
+        let count: u64 = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT count FROM counters \
+                    WHERE guild_id = 7 AND counter = 'a_counter' AND user_id = 1;",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(42, count);
+    }
+
+    #[test]
+    fn legacy_database_needs_a_default_counter_guild() {
+        let pool = legacy_pool();
+
+        assert!(matches!(
+            run(&pool, &Settings::default()),
+            Err(Error::Migration { version: 2, .. })
+        ));
+        assert_eq!(1, user_version(&pool.get().unwrap()));
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let pool = memory_pool();
+        let too_new = MIGRATIONS.len() as u32 + 1;
+
+        pool.get()
+            .unwrap()
+            .pragma_update(None, "user_version", too_new)
+            .unwrap();
+
+        assert!(matches!(
+            run(&pool, &Settings::default()),
+            Err(Error::TooNew { found, .. }) if found == too_new
+        ));
+    }
+
+    #[test]
+    fn failed_migration_rolls_back() {
+        let pool = memory_pool();
+        let mut connection = pool.get().unwrap();
+        let migrations = [
+            Migration {
+                description: "good",
+                apply: |tx, _| sql(tx, "CREATE TABLE good (id INTEGER);"),
+            },
+            Migration {
+                description: "bad",
+                apply: |tx, _| sql(tx, "CREATE TABLE bad (id INTEGER); NOT EVEN SQL;"),
+            },
+        ];
+
+        assert!(matches!(
+            migrate(&mut connection, &migrations, &Settings::default()),
+            Err(Error::Migration { version: 2, .. })
+        ));
+        assert_eq!(1, user_version(&connection));
+
+        let bad_tables: u32 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name = 'bad';",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(0, bad_tables);
+    }
+}
@@ -0,0 +1,11 @@
+-- databases from before migrations existed already have this table,
+-- hence IF NOT EXISTS
+CREATE TABLE IF NOT EXISTS
+    counters (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        counter TEXT NOT NULL,
+        user_id INTEGER(64) NOT NULL,
+        count INTEGER NOT NULL DEFAULT 0,
+        CONSTRAINT
+            one_count_per_user UNIQUE (counter, user_id)
+                ON CONFLICT ROLLBACK);
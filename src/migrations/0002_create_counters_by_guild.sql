@@ -0,0 +1,10 @@
+CREATE TABLE
+    counters_by_guild (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER(64) NOT NULL,
+        counter TEXT NOT NULL,
+        user_id INTEGER(64) NOT NULL,
+        count INTEGER NOT NULL DEFAULT 0,
+        CONSTRAINT
+            one_count_per_user UNIQUE (guild_id, counter, user_id)
+                ON CONFLICT ROLLBACK);
@@ -0,0 +1,14 @@
+CREATE TABLE
+    counter_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER(64) NOT NULL,
+        counter TEXT NOT NULL,
+        user_id INTEGER(64) NOT NULL,
+        delta INTEGER NOT NULL,
+        channel_id INTEGER(64),
+        message_id INTEGER(64),
+        created_at TEXT NOT NULL);
+
+CREATE INDEX
+    counter_events_by_time
+        ON counter_events (guild_id, counter, created_at);
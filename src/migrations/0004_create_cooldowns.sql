@@ -0,0 +1,8 @@
+CREATE TABLE
+    cooldowns (
+        guild_id INTEGER(64) NOT NULL,
+        owner TEXT NOT NULL,
+        user_id INTEGER(64) NOT NULL,
+        channel_id INTEGER(64) NOT NULL,
+        last_triggered TEXT NOT NULL,
+        PRIMARY KEY (guild_id, owner, user_id, channel_id));
@@ -0,0 +1,7 @@
+CREATE TABLE
+    rotations (
+        guild_id INTEGER(64) NOT NULL,
+        owner TEXT NOT NULL,
+        sequence TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, owner));
@@ -0,0 +1,11 @@
+CREATE TABLE
+    counter_audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER(64) NOT NULL,
+        counter TEXT NOT NULL,
+        user_id INTEGER(64) NOT NULL,
+        moderator_id INTEGER(64) NOT NULL,
+        action TEXT NOT NULL,
+        previous INTEGER NOT NULL,
+        count INTEGER NOT NULL,
+        created_at TEXT NOT NULL);
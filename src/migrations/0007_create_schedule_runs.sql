@@ -0,0 +1,6 @@
+CREATE TABLE
+    schedule_runs (
+        guild_id INTEGER(64) NOT NULL,
+        schedule TEXT NOT NULL,
+        last_run TEXT NOT NULL,
+        PRIMARY KEY (guild_id, schedule));
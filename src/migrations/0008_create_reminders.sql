@@ -0,0 +1,18 @@
+CREATE TABLE
+    reminders (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER(64) NOT NULL,
+        channel_id INTEGER(64) NOT NULL,
+        user_id INTEGER(64) NOT NULL,
+        message_id INTEGER(64),
+        what TEXT NOT NULL,
+        due TEXT NOT NULL,
+        dm INTEGER NOT NULL);
+
+CREATE INDEX
+    reminders_by_due
+        ON reminders (due);
+
+CREATE INDEX
+    reminders_by_user
+        ON reminders (guild_id, user_id);
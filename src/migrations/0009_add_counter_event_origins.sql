@@ -0,0 +1,3 @@
+ALTER TABLE
+    counter_events
+        ADD COLUMN origin TEXT;
@@ -0,0 +1,297 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Deserialize;
+use serenity::{
+    all::{CommandDataOption, CommandDataOptionValue, CommandOptionType, CreateCommandOption},
+    model::{
+        application::CommandInteraction,
+        id::{ChannelId, RoleId, UserId},
+    },
+};
+
+use crate::command::is_valid_name;
+
+/// An option a configured command takes, registered with Discord as
+/// part of the command.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CommandOption {
+    pub name: String,
+    pub description: String,
+    #[serde(rename = "type")]
+    pub kind: OptionKind,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub choices: Vec<OptionChoice>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionKind {
+    String,
+    Integer,
+    User,
+    Channel,
+    Role,
+    Boolean,
+}
+
+/// A choice for a string or integer option, either as a bare value
+/// which is also its name or with a name of its own.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum OptionChoice {
+    Named { name: String, value: ChoiceValue },
+    Bare(ChoiceValue),
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ChoiceValue {
+    Integer(i32),
+    String(String),
+}
+
+impl CommandOption {
+    /// The option to register as part of an application command.
+    pub fn create_option(&self) -> CreateCommandOption {
+        let kind = match self.kind {
+            OptionKind::String => CommandOptionType::String,
+            OptionKind::Integer => CommandOptionType::Integer,
+            OptionKind::User => CommandOptionType::User,
+            OptionKind::Channel => CommandOptionType::Channel,
+            OptionKind::Role => CommandOptionType::Role,
+            OptionKind::Boolean => CommandOptionType::Boolean,
+        };
+
+        self.choices.iter().fold(
+            CreateCommandOption::new(kind, &self.name, &self.description).required(self.required),
+            |option, choice| match choice.value() {
+                ChoiceValue::Integer(value) => option.add_int_choice(choice.name(), *value),
+                ChoiceValue::String(value) => option.add_string_choice(choice.name(), value),
+            },
+        )
+    }
+
+    /// Checks this option for anything Discord would refuse, returning a
+    /// description of each problem found.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if !is_valid_name(&self.name) {
+            problems.push(format!(
+                "name {} must be 1-32 lowercase letters, numbers, - or _",
+                self.name
+            ));
+        }
+
+        if self.description.is_empty() || self.description.chars().count() > 100 {
+            problems.push("description must be 1-100 characters".to_owned());
+        }
+
+        if !self.choices.is_empty()
+            && !matches!(self.kind, OptionKind::String | OptionKind::Integer)
+        {
+            problems.push("only string and integer options can have choices".to_owned());
+        }
+
+        if self.choices.len() > 25 {
+            problems.push("can have at most 25 choices".to_owned());
+        }
+
+        for choice in &self.choices {
+            let matches_kind = matches!(
+                (self.kind, choice.value()),
+                (OptionKind::String, ChoiceValue::String(_))
+                    | (OptionKind::Integer, ChoiceValue::Integer(_))
+            );
+
+            if !matches_kind && matches!(self.kind, OptionKind::String | OptionKind::Integer) {
+                problems.push(format!(
+                    "choice {} doesn't match the option's type",
+                    choice.name()
+                ));
+            }
+        }
+
+        problems
+    }
+}
+
+impl OptionChoice {
+    fn name(&self) -> String {
+        match self {
+            Self::Named { name, .. } => name.clone(),
+            Self::Bare(value) => value.to_string(),
+        }
+    }
+
+    fn value(&self) -> &ChoiceValue {
+        match self {
+            Self::Named { value, .. } | Self::Bare(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for ChoiceValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// The value someone gave for an option when invoking a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    User(UserId),
+    Channel(ChannelId),
+    Role(RoleId),
+}
+
+impl fmt::Display for Argument {
+    /// Users, channels and roles display as mentions.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(value) => write!(f, "{}", value),
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Boolean(value) => write!(f, "{}", value),
+            Self::User(user_id) => write!(f, "<@{}>", user_id),
+            Self::Channel(channel_id) => write!(f, "<#{}>", channel_id),
+            Self::Role(role_id) => write!(f, "<@&{}>", role_id),
+        }
+    }
+}
+
+/// The arguments a command was invoked with, by option name.
+#[derive(Debug, Default)]
+pub struct Arguments(HashMap<String, Argument>);
+
+impl Arguments {
+    pub fn from_interaction(interaction: &CommandInteraction) -> Self {
+        Self::from_options(&interaction.data.options)
+    }
+
+    /// The arguments among some options, such as a subcommand's.
+    pub fn from_options(options: &[CommandDataOption]) -> Self {
+        Self(
+            options
+                .iter()
+                .filter_map(|option| {
+                    let argument = match &option.value {
+                        CommandDataOptionValue::String(value) => Argument::String(value.clone()),
+                        CommandDataOptionValue::Integer(value) => Argument::Integer(*value),
+                        CommandDataOptionValue::Boolean(value) => Argument::Boolean(*value),
+                        CommandDataOptionValue::User(user_id) => Argument::User(*user_id),
+                        CommandDataOptionValue::Channel(channel_id) => {
+                            Argument::Channel(*channel_id)
+                        }
+                        CommandDataOptionValue::Role(role_id) => Argument::Role(*role_id),
+                        _ => return None, // not a type we register options with
+                    };
+
+                    Some((option.name.clone(), argument))
+                })
+                .collect(),
+        )
+    }
+
+    /// Every argument as it should be displayed, by option name.
+    pub fn displayed(&self) -> HashMap<String, String> {
+        self.0
+            .iter()
+            .map(|(name, argument)| (name.clone(), argument.to_string()))
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Argument> {
+        self.0.get(name)
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(Argument::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.get(name) {
+            Some(Argument::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn boolean(&self, name: &str) -> Option<bool> {
+        match self.get(name) {
+            Some(Argument::Boolean(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn user(&self, name: &str) -> Option<UserId> {
+        match self.get(name) {
+            Some(Argument::User(user_id)) => Some(*user_id),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChoiceValue, CommandOption, OptionChoice, OptionKind};
+
+    #[test]
+    fn commandoption_deserialization() {
+        let yaml = r#"---
+        name: target
+        description: who to hug
+        type: user
+        required: true"#;
+        let option: CommandOption = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(OptionKind::User, option.kind);
+        assert!(option.required);
+        assert!(option.problems().is_empty());
+    }
+
+    #[test]
+    fn commandoption_choices() {
+        let yaml = r#"---
+        name: flavor
+        description: what kind
+        type: string
+        choices:
+          - vanilla
+          - name: Chocolate Chip
+            value: chocolate_chip"#;
+        let option: CommandOption = serde_yaml::from_str(yaml).unwrap();
+        assert!(!option.required);
+        assert_eq!(
+            vec![
+                OptionChoice::Bare(ChoiceValue::String("vanilla".to_owned())),
+                OptionChoice::Named {
+                    name: "Chocolate Chip".to_owned(),
+                    value: ChoiceValue::String("chocolate_chip".to_owned())
+                },
+            ],
+            option.choices
+        );
+        assert!(option.problems().is_empty());
+    }
+
+    #[test]
+    fn commandoption_mismatched_choices() {
+        let yaml = r#"---
+        name: Flavor
+        description: what kind
+        type: integer
+        choices: [1, vanilla]"#;
+        let option: CommandOption = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(2, option.problems().len());
+    }
+}
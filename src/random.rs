@@ -0,0 +1,117 @@
+use rand::Rng;
+
+/// Where the bot gets its randomness from, so that tests can swap in a
+/// predictable source.
+pub trait Random: Send + Sync {
+    /// A number from 0 up to but not including 1.
+    fn fraction(&self) -> f64;
+
+    /// A number from 0 up to but not including `n`, which must not be 0.
+    fn below(&self, n: usize) -> usize {
+        ((self.fraction() * n as f64) as usize).min(n - 1)
+    }
+
+    /// Whether something with the given probability happens.
+    fn chance(&self, probability: f64) -> bool {
+        self.fraction() < probability
+    }
+
+    /// Picks an index into the given weights, each index being as
+    /// likely as its share of the total weight.
+    fn weighted_index(&self, weights: &[u64]) -> Option<usize> {
+        let total: u64 = weights.iter().sum();
+
+        if total == 0 {
+            return None;
+        }
+
+        let mut target = ((self.fraction() * total as f64) as u64).min(total - 1);
+
+        weights.iter().position(|weight| {
+            if target < *weight {
+                return true;
+            }
+
+            target -= weight;
+            false
+        })
+    }
+}
+
+/// Randomness from the thread-local generator.
+pub struct ThreadRandom;
+
+impl Random for ThreadRandom {
+    fn fraction(&self) -> f64 {
+        rand::thread_rng().gen()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Mutex;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Random;
+
+    /// Randomness which is the same every time for a given seed.
+    pub(crate) struct SeededRandom(Mutex<StdRng>);
+
+    impl SeededRandom {
+        pub(crate) fn new(seed: u64) -> Self {
+            Self(Mutex::new(StdRng::seed_from_u64(seed)))
+        }
+    }
+
+    impl Random for SeededRandom {
+        fn fraction(&self) -> f64 {
+            self.0.lock().unwrap().gen()
+        }
+    }
+
+    /// Randomness which always comes out the same.
+    pub(crate) struct FixedRandom(pub f64);
+
+    impl Random for FixedRandom {
+        fn fraction(&self) -> f64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn weighted_index_edges() {
+        let weights = [1, 0, 3];
+        assert_eq!(Some(0), FixedRandom(0.0).weighted_index(&weights));
+        assert_eq!(Some(0), FixedRandom(0.24).weighted_index(&weights));
+        assert_eq!(Some(2), FixedRandom(0.25).weighted_index(&weights));
+        assert_eq!(Some(2), FixedRandom(0.999).weighted_index(&weights));
+        assert_eq!(None, FixedRandom(0.5).weighted_index(&[]));
+        assert_eq!(None, FixedRandom(0.5).weighted_index(&[0, 0]));
+    }
+
+    #[test]
+    fn weighted_index_distribution() {
+        let random = SeededRandom::new(42);
+        let weights = [1, 3];
+        let mut picks = [0; 2];
+
+        for _ in 0..10_000 {
+            picks[random.weighted_index(&weights).unwrap()] += 1;
+        }
+
+        // a quarter and three quarters, give or take
+        assert!((2_250..2_750).contains(&picks[0]), "{:?}", picks);
+        assert!((7_250..7_750).contains(&picks[1]), "{:?}", picks);
+    }
+
+    #[test]
+    fn chance_distribution() {
+        let random = SeededRandom::new(7);
+        let hits = (0..10_000).filter(|_| random.chance(0.25)).count();
+
+        assert!((2_250..2_750).contains(&hits), "{}", hits);
+        assert!(!FixedRandom(0.0).chance(0.0));
+        assert!(FixedRandom(0.999).chance(1.0));
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fs::metadata, sync::Arc, time::Duration, time::SystemTime};
+
+use serenity::http::Http;
+use tokio::time::interval;
+
+use crate::handler::Handler;
+
+/// How often the config file is checked for modifications.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Watches the config file at the given path, reloading it into the
+/// handler whenever it is modified or the process receives a SIGHUP.
+pub async fn watch_config(handler: Arc<Handler>, http: Arc<Http>, path: String) {
+    let mut last_modified = modified_time(&path);
+    let mut poll = interval(POLL_INTERVAL);
+
+    #[cfg(unix)]
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("Cannot listen for SIGHUP");
+
+    loop {
+        #[cfg(unix)]
+        let forced = tokio::select! {
+            _ = poll.tick() => false,
+            _ = hangup.recv() => true,
+        };
+        #[cfg(not(unix))]
+        let forced = {
+            poll.tick().await;
+            false
+        };
+
+        let modified = modified_time(&path);
+
+        if forced {
+            log::info!("Received SIGHUP, reloading config");
+        } else if modified == last_modified {
+            continue;
+        }
+
+        last_modified = modified;
+        handler.reload_config(&http, &path).await;
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
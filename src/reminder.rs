@@ -0,0 +1,929 @@
+use std::sync::{Arc, LazyLock};
+
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use regex::Regex;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Deserialize;
+use serde_with::{serde_as, DisplayFromStr};
+use serenity::{
+    all::{
+        CommandDataOptionValue, CommandOptionType, CreateAllowedMentions, CreateCommand,
+        CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage,
+        CreateMessage,
+    },
+    model::{
+        application::CommandInteraction,
+        channel::MessageReference,
+        id::{ChannelId, GuildId, MessageId, UserId},
+        mention::Mentionable,
+    },
+};
+use snafu::ResultExt;
+use tokio::{sync::Notify, time};
+
+use crate::{
+    discord::Discord,
+    handler::Services,
+    options::Arguments,
+    store::{DbSnafu, PoolSnafu, Result},
+};
+
+/// What the built-in commands are called, which no configured command
+/// can be called as well.
+pub const REMIND_ME: &str = "remindme";
+pub const REMINDERS: &str = "reminders";
+
+/// Discord's limit on the length of a string option is 6000, but a
+/// reminder has to fit in a message with a mention in front of it.
+const MAX_WHAT_CHARS: u16 = 1000;
+
+/// How much of a reminder `/reminders list` shows, so that a full list
+/// fits in a message.
+const MAX_LISTED_CHARS: usize = 60;
+
+/// The longest the delivery task sleeps without looking for reminders
+/// which are due.
+const MAX_SLEEP_SECONDS: i64 = 60;
+
+/// The built-in `/remindme` and `/reminders` commands, which let anyone
+/// in the guild have the bot remind them of something later.
+#[serde_as]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ReminderCommands {
+    /// How many reminders someone can have waiting at once.
+    #[serde(default = "default_max_per_user")]
+    max_per_user: u32,
+    /// The timezone times given without an offset are read in, UTC by
+    /// default.
+    #[serde(default = "default_timezone")]
+    #[serde_as(as = "DisplayFromStr")]
+    timezone: Tz,
+}
+
+/// A reminder waiting to be delivered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reminder {
+    pub id: i64,
+    pub guild_id: GuildId,
+    /// Where the reminder was set, and where it's delivered unless it's
+    /// delivered by DM.
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+    /// The response which confirmed the reminder, which delivering it
+    /// replies to.
+    pub message_id: Option<MessageId>,
+    pub what: String,
+    pub due: DateTime<Utc>,
+    pub dm: bool,
+}
+
+/// What to respond to a command with.
+#[derive(Debug, PartialEq)]
+struct Response {
+    content: String,
+    ephemeral: bool,
+    /// The reminder whose delivery should reply to the response.
+    replied_to_by: Option<i64>,
+}
+
+impl Response {
+    fn private(content: String) -> Self {
+        Self {
+            content,
+            ephemeral: true,
+            replied_to_by: None,
+        }
+    }
+}
+
+impl ReminderCommands {
+    /// The application commands to register alongside the configured
+    /// ones.
+    pub fn create_commands(&self) -> Vec<CreateCommand> {
+        vec![
+            CreateCommand::new(REMIND_ME)
+                .description("reminds you of something later")
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "what",
+                        "what to remind you of",
+                    )
+                    .required(true)
+                    .max_length(MAX_WHAT_CHARS),
+                )
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "in",
+                    "how long from now, like 2h or 1d 30m",
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "at",
+                    format!("when, like 2026-10-20 15:00 ({})", self.timezone),
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "dm",
+                    "remind you in a DM rather than here",
+                )),
+            CreateCommand::new(REMINDERS)
+                .description("your reminders")
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "list",
+                    "shows your reminders",
+                ))
+                .add_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        "cancel",
+                        "cancels one of your reminders",
+                    )
+                    .add_sub_option(
+                        CreateCommandOption::new(
+                            CommandOptionType::Integer,
+                            "id",
+                            "which reminder, as /reminders list shows it",
+                        )
+                        .required(true)
+                        .min_int_value(1),
+                    ),
+                ),
+        ]
+    }
+
+    /// Checks the commands are usable, returning a description of each
+    /// problem found.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.max_per_user == 0 {
+            problems.push("max_per_user must be at least 1".to_owned());
+        }
+
+        problems
+    }
+
+    pub async fn handle(
+        &self,
+        interaction: &CommandInteraction,
+        discord: &dyn Discord,
+        services: &Services,
+    ) {
+        let store = &services.reminder_store;
+        let response = match self.respond(interaction, store, Utc::now()) {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!(
+                    "Failed to run /{} for user {} with error {:#?}",
+                    interaction.data.name,
+                    interaction.user.id,
+                    e
+                );
+                Response::private("Something went wrong, sorry.".to_owned())
+            }
+        };
+        let interaction_response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(response.content)
+                .allowed_mentions(CreateAllowedMentions::new())
+                .ephemeral(response.ephemeral),
+        );
+
+        if let Err(e) = discord
+            .create_response(interaction.id, &interaction.token, interaction_response)
+            .await
+        {
+            log::error!("Failed to respond to interaction with error {:?}", e);
+            return;
+        }
+
+        if let Some(id) = response.replied_to_by {
+            let result = match discord.get_response(&interaction.token).await {
+                Ok(message) => store.set_message(id, message.id),
+                Err(e) => {
+                    log::error!("Failed to get interaction response with error {:#?}", e);
+                    return;
+                }
+            };
+
+            if let Err(e) = result {
+                log::error!(
+                    "Failed to save message of reminder {} with error {:#?}",
+                    id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// What to tell whoever used a command, after doing what they asked.
+    fn respond(
+        &self,
+        interaction: &CommandInteraction,
+        store: &ReminderStore,
+        now: DateTime<Utc>,
+    ) -> Result<Response> {
+        let guild_id = match interaction.guild_id {
+            Some(guild_id) => guild_id,
+            None => {
+                return Ok(Response::private(
+                    "This command only works in a server.".to_owned(),
+                ))
+            }
+        };
+
+        if interaction.data.name == REMIND_ME {
+            return self.remind_me(interaction, guild_id, store, now);
+        }
+
+        let (name, options) = match interaction.data.options.first() {
+            Some(option) => match &option.value {
+                CommandDataOptionValue::SubCommand(options) => (option.name.as_str(), options),
+                _ => return Ok(Response::private("Pick one of list or cancel.".to_owned())),
+            },
+            None => return Ok(Response::private("Pick one of list or cancel.".to_owned())),
+        };
+        let user_id = interaction.user.id;
+        let content = match name {
+            "list" => list(&store.pending(guild_id, user_id)?),
+            "cancel" => match Arguments::from_options(options).integer("id") {
+                Some(id) if store.cancel(guild_id, user_id, id)? => {
+                    format!("Cancelled reminder #{}.", id)
+                }
+                Some(id) => format!("You have no reminder #{}.", id),
+                None => "Say which reminder to cancel.".to_owned(),
+            },
+            name => format!("There's no {} subcommand.", name),
+        };
+
+        Ok(Response::private(content))
+    }
+
+    fn remind_me(
+        &self,
+        interaction: &CommandInteraction,
+        guild_id: GuildId,
+        store: &ReminderStore,
+        now: DateTime<Utc>,
+    ) -> Result<Response> {
+        let arguments = Arguments::from_interaction(interaction);
+        let what = match arguments.string("what").map(str::trim) {
+            Some(what) if !what.is_empty() => what,
+            _ => return Ok(Response::private("Say what to remind you of.".to_owned())),
+        };
+        let due = match (arguments.string("in"), arguments.string("at")) {
+            (Some(after), None) => {
+                match parse_duration(after).and_then(|after| now.checked_add_signed(after)) {
+                    Some(due) => due,
+                    None => {
+                        return Ok(Response::private(format!(
+                            "I don't understand {} as a duration, try something like 2h or 1d 30m.",
+                            after
+                        )))
+                    }
+                }
+            }
+            (None, Some(at)) => match parse_time(at, self.timezone) {
+                Some(due) => due,
+                None => {
+                    return Ok(Response::private(format!(
+                        "I don't understand {} as a time, try something like 2026-10-20 15:00.",
+                        at
+                    )))
+                }
+            },
+            _ => {
+                return Ok(Response::private(
+                    "Say either in or at, but not both.".to_owned(),
+                ))
+            }
+        };
+
+        if due <= now {
+            return Ok(Response::private("That's already happened.".to_owned()));
+        }
+
+        let user_id = interaction.user.id;
+
+        if store.pending(guild_id, user_id)?.len() >= self.max_per_user as usize {
+            return Ok(Response::private(format!(
+                "You already have {} reminders, cancel one first.",
+                self.max_per_user
+            )));
+        }
+
+        let dm = arguments.boolean("dm").unwrap_or(false);
+        let id = store.add(&Reminder {
+            id: 0,
+            guild_id,
+            channel_id: interaction.channel_id,
+            user_id,
+            message_id: None,
+            what: what.to_owned(),
+            due,
+            dm,
+        })?;
+        let content = format!(
+            "Reminder #{} set, I'll remind you{} <t:{}:R>: {}",
+            id,
+            if dm { " by DM" } else { "" },
+            due.timestamp(),
+            what
+        );
+
+        // a reminder in the channel replies to everyone seeing it set
+        Ok(Response {
+            content,
+            ephemeral: dm,
+            replied_to_by: (!dm).then_some(id),
+        })
+    }
+}
+
+/// Describes someone's reminders, soonest first.
+fn list(reminders: &[Reminder]) -> String {
+    if reminders.is_empty() {
+        return "You have no reminders.".to_owned();
+    }
+
+    reminders
+        .iter()
+        .map(|reminder| {
+            let mut what = reminder
+                .what
+                .chars()
+                .take(MAX_LISTED_CHARS)
+                .collect::<String>();
+
+            if what.len() < reminder.what.len() {
+                what.push('…');
+            }
+
+            format!(
+                "#{} <t:{}:R>{}: {}",
+                reminder.id,
+                reminder.due.timestamp(),
+                if reminder.dm { " by DM" } else { "" },
+                what
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reads a duration such as `2h`, `1d 30m` or `90 minutes`.
+fn parse_duration(text: &str) -> Option<Duration> {
+    static PART: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^(\d+)\s*([a-z]+)[\s,]*").unwrap());
+    let text = text.trim().to_lowercase();
+    let mut rest = text.as_str();
+    let mut seconds = 0i64;
+
+    while !rest.is_empty() {
+        let captures = PART.captures(rest)?;
+        let amount: i64 = captures[1].parse().ok()?;
+        let unit = match &captures[2] {
+            "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60,
+            "d" | "day" | "days" => 24 * 60 * 60,
+            "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+
+        seconds = seconds.checked_add(amount.checked_mul(unit)?)?;
+        rest = &rest[captures[0].len()..];
+    }
+
+    Duration::try_seconds(seconds).filter(|duration| *duration > Duration::zero())
+}
+
+/// Reads a time such as `2026-10-20 15:00` in the given timezone, an
+/// RFC 3339 time with its own offset, or a Discord timestamp.
+fn parse_time(text: &str, timezone: Tz) -> Option<DateTime<Utc>> {
+    static TIMESTAMP: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"^<t:(-?\d+)(:[a-zA-Z])?>$").unwrap());
+    let text = text.trim();
+
+    if let Some(captures) = TIMESTAMP.captures(text) {
+        return DateTime::from_timestamp(captures[1].parse().ok()?, 0);
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"]
+        .into_iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .and_then(|time| timezone.from_local_datetime(&time).earliest())
+        .map(|time| time.with_timezone(&Utc))
+}
+
+const fn default_max_per_user() -> u32 {
+    25
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+impl Reminder {
+    /// Reminds whoever set the reminder, in the channel it was set in
+    /// or by DM.
+    async fn deliver(&self, discord: &dyn Discord) {
+        let result = match self.dm {
+            true => {
+                let message = CreateMessage::new().content(format!(
+                    "You asked me to remind you in {}: {}",
+                    self.channel_id.mention(),
+                    self.what
+                ));
+
+                discord.direct_message(self.user_id, message).await
+            }
+            false => {
+                let mut message = CreateMessage::new()
+                    .content(format!(
+                        "{}, you asked me to remind you: {}",
+                        self.user_id.mention(),
+                        self.what
+                    ))
+                    .allowed_mentions(CreateAllowedMentions::new().users([self.user_id]));
+
+                if let Some(message_id) = self.message_id {
+                    let mut reference = MessageReference::from((self.channel_id, message_id));
+                    // still remind them if the response has been deleted
+                    reference.fail_if_not_exists = Some(false);
+                    message = message.reference_message(reference);
+                }
+
+                discord.send_message(self.channel_id, message).await
+            }
+        };
+
+        if let Err(e) = result {
+            log::error!("Failed to deliver reminder {} with error {:#?}", self.id, e);
+        }
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            guild_id: GuildId::new(row.get(1)?),
+            channel_id: ChannelId::new(row.get(2)?),
+            user_id: UserId::new(row.get(3)?),
+            message_id: row.get::<_, Option<u64>>(4)?.map(MessageId::new),
+            what: row.get(5)?,
+            due: row.get(6)?,
+            dm: row.get(7)?,
+        })
+    }
+}
+
+/// Keeps reminders until they're delivered, so that they survive
+/// restarts.
+pub struct ReminderStore {
+    pool: Pool<SqliteConnectionManager>,
+    /// Wakes the delivery task when a reminder is added, as it may be
+    /// due before the task would otherwise wake.
+    added: Notify,
+}
+
+impl ReminderStore {
+    /// Keeps reminders in the given pool's `reminders` table. Nothing is
+    /// delivered until [`run`] is started with services holding it.
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self {
+            pool,
+            added: Notify::new(),
+        }
+    }
+
+    /// Adds a reminder, ignoring its id, and returns the id it was
+    /// given.
+    fn add(&self, reminder: &Reminder) -> Result<i64> {
+        let connection = self.get_connection()?;
+
+        connection
+            .execute(
+                "INSERT INTO reminders \
+                    (guild_id, channel_id, user_id, message_id, what, due, dm) \
+                    VALUES(?, ?, ?, ?, ?, ?, ?);",
+                params![
+                    reminder.guild_id.get(),
+                    reminder.channel_id.get(),
+                    reminder.user_id.get(),
+                    reminder.message_id.map(MessageId::get),
+                    reminder.what,
+                    reminder.due,
+                    reminder.dm,
+                ],
+            )
+            .context(DbSnafu)?;
+        self.added.notify_one();
+
+        Ok(connection.last_insert_rowid())
+    }
+
+    fn set_message(&self, id: i64, message_id: MessageId) -> Result<()> {
+        self.get_connection()?
+            .execute(
+                "UPDATE reminders SET message_id = ? WHERE id = ?;",
+                params![message_id.get(), id],
+            )
+            .context(DbSnafu)
+            .map(|_| ())
+    }
+
+    /// Someone's reminders in a guild, soonest first.
+    fn pending(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<Reminder>> {
+        let connection = self.get_connection()?;
+        let mut statement = connection
+            .prepare(
+                "SELECT id, guild_id, channel_id, user_id, message_id, what, due, dm \
+                    FROM reminders WHERE guild_id = ? AND user_id = ? ORDER BY due, id;",
+            )
+            .context(DbSnafu)?;
+        let rows = statement
+            .query_map(params![guild_id.get(), user_id.get()], Reminder::from_row)
+            .context(DbSnafu)?;
+
+        rows.collect::<Result<_, _>>().context(DbSnafu)
+    }
+
+    /// Cancels one of someone's reminders, returning whether they had
+    /// it.
+    fn cancel(&self, guild_id: GuildId, user_id: UserId, id: i64) -> Result<bool> {
+        self.get_connection()?
+            .execute(
+                "DELETE FROM reminders WHERE id = ? AND guild_id = ? AND user_id = ?;",
+                params![id, guild_id.get(), user_id.get()],
+            )
+            .context(DbSnafu)
+            .map(|deleted| deleted > 0)
+    }
+
+    /// Removes and returns every reminder which is due.
+    fn take_due(&self, now: DateTime<Utc>) -> Result<Vec<Reminder>> {
+        let mut connection = self.get_connection()?;
+        let tx = connection.transaction().context(DbSnafu)?;
+        let due = {
+            let mut statement = tx
+                .prepare(
+                    "SELECT id, guild_id, channel_id, user_id, message_id, what, due, dm \
+                        FROM reminders WHERE due <= ? ORDER BY due, id;",
+                )
+                .context(DbSnafu)?;
+            let rows = statement
+                .query_map([now], Reminder::from_row)
+                .context(DbSnafu)?;
+
+            rows.collect::<Result<Vec<_>, _>>().context(DbSnafu)?
+        };
+
+        tx.execute("DELETE FROM reminders WHERE due <= ?;", [now])
+            .context(DbSnafu)?;
+        tx.commit().context(DbSnafu)?;
+
+        Ok(due)
+    }
+
+    /// When the next reminder is due, if there are any.
+    fn next_due(&self) -> Result<Option<DateTime<Utc>>> {
+        self.get_connection()?
+            .query_row("SELECT MIN(due) FROM reminders;", [], |row| row.get(0))
+            .optional()
+            .context(DbSnafu)
+            .map(Option::flatten)
+    }
+
+    fn get_connection(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        self.pool.get().context(PoolSnafu)
+    }
+}
+
+/// Delivers reminders as they come due, forever.
+pub async fn run(discord: impl Discord, services: Arc<Services>) {
+    log::info!("Delivering reminders");
+
+    loop {
+        let now = Utc::now();
+        let wake_at = deliver(&discord, &services, now)
+            .await
+            .unwrap_or(now + Duration::seconds(MAX_SLEEP_SECONDS))
+            .min(now + Duration::seconds(MAX_SLEEP_SECONDS));
+        let sleep = (wake_at - Utc::now()).to_std().unwrap_or_default();
+
+        tokio::select! {
+            _ = time::sleep(sleep) => {}
+            _ = services.reminder_store.added.notified() => {}
+        }
+    }
+}
+
+/// Delivers every reminder which is due, returning when the next one
+/// is. Reminders are removed before they're delivered, as one which
+/// fails to deliver is better missed than sent over and over.
+pub async fn deliver(
+    discord: &dyn Discord,
+    services: &Services,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let store = &services.reminder_store;
+
+    match store.take_due(now) {
+        Ok(reminders) => {
+            for reminder in reminders {
+                reminder.deliver(discord).await;
+            }
+        }
+        Err(e) => log::error!("Failed to take due reminders with error {:#?}", e),
+    }
+
+    match store.next_due() {
+        Ok(next_due) => next_due,
+        Err(e) => {
+            log::error!("Failed to find the next reminder with error {:#?}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use chrono_tz::{Europe::London, Tz};
+    use serde_json::json;
+    use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+
+    use super::{deliver, parse_duration, parse_time, Reminder, ReminderCommands, ReminderStore};
+    use crate::{
+        counter::tests::memory_pool,
+        discord::tests::Call,
+        handler::tests::{command_with, discord, handler, response_content, services},
+    };
+
+    const REMINDERS: &str = r#"
+    reminders:
+      max_per_user: 2
+      timezone: Europe/London"#;
+
+    fn reminder(user_id: u64, due_in_hours: i64) -> Reminder {
+        Reminder {
+            id: 0,
+            guild_id: GuildId::new(1),
+            channel_id: ChannelId::new(100),
+            user_id: UserId::new(user_id),
+            message_id: None,
+            what: "feed the fish".to_owned(),
+            due: Utc.with_ymd_and_hms(2026, 10, 19, 9, 0, 0).unwrap()
+                + Duration::hours(due_in_hours),
+            dm: false,
+        }
+    }
+
+    #[test]
+    fn reminder_commands_deserialization() {
+        let reminders: ReminderCommands = serde_yaml::from_str("timezone: Europe/London").unwrap();
+        assert_eq!(25, reminders.max_per_user);
+        assert_eq!(London, reminders.timezone);
+        assert!(reminders.problems().is_empty());
+
+        let reminders: ReminderCommands = serde_yaml::from_str("max_per_user: 0").unwrap();
+        assert_eq!(Tz::UTC, reminders.timezone);
+        assert_eq!(
+            vec!["max_per_user must be at least 1"],
+            reminders.problems()
+        );
+
+        assert!(serde_yaml::from_str::<ReminderCommands>("timezone: Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(Some(Duration::hours(2)), parse_duration("2h"));
+        assert_eq!(
+            Some(Duration::minutes(24 * 60 + 30)),
+            parse_duration("1d 30m")
+        );
+        assert_eq!(Some(Duration::minutes(90)), parse_duration(" 90 Minutes "));
+        assert_eq!(Some(Duration::days(15)), parse_duration("2 weeks, 1 day"));
+        assert_eq!(None, parse_duration(""));
+        assert_eq!(None, parse_duration("0m"));
+        assert_eq!(None, parse_duration("2 fortnights"));
+        assert_eq!(None, parse_duration("soon"));
+        assert_eq!(None, parse_duration("99999999999999999w"));
+    }
+
+    #[test]
+    fn times() {
+        // BST until the 25th
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2026, 10, 20, 14, 0, 0).unwrap()),
+            parse_time("2026-10-20 15:00", London)
+        );
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2026, 10, 27, 15, 0, 30).unwrap()),
+            parse_time("2026-10-27 15:00:30", London)
+        );
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2026, 10, 20, 13, 0, 0).unwrap()),
+            parse_time("2026-10-20T15:00:00+02:00", London)
+        );
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2026, 10, 20, 15, 0, 0).unwrap()),
+            parse_time("<t:1792508400:R>", London)
+        );
+        // the first of the twice 01:30 is on the 25th
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2026, 10, 25, 0, 30, 0).unwrap()),
+            parse_time("2026-10-25 01:30", London)
+        );
+        assert_eq!(None, parse_time("tomorrow", London));
+    }
+
+    #[test]
+    fn reminder_store() {
+        let store = ReminderStore::new(services(memory_pool()).reminder_store.pool);
+        let guild_id = GuildId::new(1);
+        let later = store.add(&reminder(10, 2)).unwrap();
+        let sooner = store.add(&reminder(10, 1)).unwrap();
+        let other = store.add(&reminder(11, 3)).unwrap();
+        store.set_message(sooner, MessageId::new(5)).unwrap();
+
+        let pending = store.pending(guild_id, UserId::new(10)).unwrap();
+        assert_eq!(
+            vec![sooner, later],
+            pending.iter().map(|r| r.id).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(MessageId::new(5)), pending[0].message_id);
+        assert_eq!(reminder(10, 1).due, pending[0].due);
+
+        assert!(!store.cancel(guild_id, UserId::new(10), other).unwrap());
+        assert!(store.cancel(guild_id, UserId::new(11), other).unwrap());
+
+        assert_eq!(Some(reminder(10, 1).due), store.next_due().unwrap());
+        let due = store.take_due(reminder(10, 1).due).unwrap();
+        assert_eq!(vec![sooner], due.iter().map(|r| r.id).collect::<Vec<_>>());
+        assert!(store.take_due(reminder(10, 1).due).unwrap().is_empty());
+        assert_eq!(Some(reminder(10, 2).due), store.next_due().unwrap());
+    }
+
+    #[tokio::test]
+    async fn reminders_reply_to_their_confirmation() {
+        let handler = handler(REMINDERS);
+        let discord = discord();
+
+        handler
+            .handle_interaction(
+                &discord,
+                command_with(
+                    "remindme",
+                    json!([
+                        {"name": "what", "type": 3, "value": "feed the fish"},
+                        {"name": "in", "type": 3, "value": "1h"},
+                    ]),
+                ),
+            )
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        assert!(response_content(&calls[0]).starts_with("Reminder #1 set, I'll remind you <t:"));
+
+        // not yet
+        let next_due = deliver(&discord, &handler.services, Utc::now()).await;
+        assert!(next_due.is_some());
+        assert_eq!(Vec::<Call>::new(), discord.take_calls());
+
+        assert_eq!(
+            None,
+            deliver(&discord, &handler.services, next_due.unwrap()).await
+        );
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        match &calls[0] {
+            Call::SendMessage {
+                channel_id,
+                message,
+            } => {
+                assert_eq!(ChannelId::new(100), *channel_id);
+                assert_eq!(
+                    json!("<@10>, you asked me to remind you: feed the fish"),
+                    message["content"]
+                );
+                assert_eq!(json!("1"), message["message_reference"]["message_id"]);
+                assert_eq!(json!(["10"]), message["allowed_mentions"]["users"]);
+            }
+            call => panic!("expected a reminder, got {:?}", call),
+        }
+    }
+
+    #[tokio::test]
+    async fn reminders_by_dm() {
+        let handler = handler(REMINDERS);
+        let discord = discord();
+
+        handler
+            .handle_interaction(
+                &discord,
+                command_with(
+                    "remindme",
+                    json!([
+                        {"name": "what", "type": 3, "value": "feed the fish"},
+                        {"name": "at", "type": 3, "value": "2099-01-01 09:00"},
+                        {"name": "dm", "type": 5, "value": true},
+                    ]),
+                ),
+            )
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        match &calls[0] {
+            Call::CreateResponse { response, .. } => {
+                assert_eq!(json!(64), response["data"]["flags"])
+            }
+            call => panic!("expected a response, got {:?}", call),
+        }
+
+        let due = Utc.with_ymd_and_hms(2099, 1, 1, 9, 0, 0).unwrap();
+        assert_eq!(None, deliver(&discord, &handler.services, due).await);
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        match &calls[0] {
+            Call::DirectMessage { user_id, message } => {
+                assert_eq!(UserId::new(10), *user_id);
+                assert_eq!(
+                    json!("You asked me to remind you in <#100>: feed the fish"),
+                    message["content"]
+                );
+            }
+            call => panic!("expected a DM, got {:?}", call),
+        }
+    }
+
+    #[tokio::test]
+    async fn reminders_are_listed_and_cancelled() {
+        let handler = handler(REMINDERS);
+        let discord = discord();
+        let remind_me = |what: &str, after: &str| {
+            command_with(
+                "remindme",
+                json!([
+                    {"name": "what", "type": 3, "value": what},
+                    {"name": "in", "type": 3, "value": after},
+                ]),
+            )
+        };
+
+        for (what, after) in [
+            ("feed the fish", "2h"),
+            ("buy more fish", "1h"),
+            ("nap", "3h"),
+        ] {
+            handler
+                .handle_interaction(&discord, remind_me(what, after))
+                .await;
+        }
+        let calls = discord.take_calls();
+        assert_eq!(
+            "You already have 2 reminders, cancel one first.",
+            response_content(&calls[2])
+        );
+
+        handler
+            .handle_interaction(
+                &discord,
+                command_with(
+                    "reminders",
+                    json!([{"name": "list", "type": 1, "options": []}]),
+                ),
+            )
+            .await;
+        let calls = discord.take_calls();
+        let listed = response_content(&calls[0]).lines().collect::<Vec<_>>();
+        assert_eq!(2, listed.len(), "{:?}", listed);
+        assert!(listed[0].starts_with("#2 ") && listed[0].ends_with(": buy more fish"));
+        assert!(listed[1].starts_with("#1 ") && listed[1].ends_with(": feed the fish"));
+
+        let cancel = || {
+            command_with(
+                "reminders",
+                json!([{"name": "cancel", "type": 1, "options": [
+                    {"name": "id", "type": 4, "value": 2},
+                ]}]),
+            )
+        };
+        handler.handle_interaction(&discord, cancel()).await;
+        handler.handle_interaction(&discord, cancel()).await;
+        let calls = discord.take_calls();
+        assert_eq!("Cancelled reminder #2.", response_content(&calls[0]));
+        assert_eq!("You have no reminder #2.", response_content(&calls[1]));
+    }
+}
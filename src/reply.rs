@@ -0,0 +1,493 @@
+use std::{
+    fmt,
+    num::NonZeroU32,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{
+    de::{value::MapAccessDeserializer, Error as DeError, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_with::{formats::PreferOne, serde_as, OneOrMany};
+use serenity::all::{
+    CreateActionRow, CreateAttachment, CreateEmbed, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse,
+};
+
+use crate::{
+    embed::Embed,
+    random::Random,
+    template::{Placeholder, Template, TemplateValues},
+};
+
+/// Discord's limit on the number of files in a message.
+const MAX_ATTACHMENTS: usize = 10;
+
+/// Discord's limit on the number of embeds in a message.
+const MAX_EMBEDS: usize = 10;
+
+/// Discord's limit on the length of a message's content.
+const MAX_CONTENT_CHARS: usize = 2000;
+
+/// One of the messages a command or autoresponder can reply with,
+/// written as a bare template or as a map of `text`, an `embed`,
+/// `attachments` from the media directory and a `weight` to make it
+/// more or less likely to be picked than the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    /// Empty for a reply which is only an embed or attachments.
+    pub text: Template,
+    pub embed: Option<Embed>,
+    /// Paths of files within the media directory.
+    pub attachments: Vec<PathBuf>,
+    pub weight: NonZeroU32,
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReplyDefinition {
+    #[serde(default)]
+    text: Template,
+    embed: Option<Embed>,
+    #[serde(default)]
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    attachments: Vec<PathBuf>,
+    #[serde(default = "default_weight")]
+    weight: NonZeroU32,
+}
+
+/// A reply filled in and ready to send, or several combined into one
+/// message.
+pub struct RenderedReply {
+    content: String,
+    embeds: Vec<CreateEmbed>,
+    attachments: Vec<CreateAttachment>,
+    components: Vec<CreateActionRow>,
+}
+
+const fn default_weight() -> NonZeroU32 {
+    NonZeroU32::MIN
+}
+
+/// How a command or autoresponder picks which of its replies to send.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    /// At random by weight, every time.
+    #[default]
+    Random,
+    /// At random, but without repeating any until all have been sent.
+    Shuffle,
+    /// In order, starting over after the last.
+    Sequential,
+}
+
+/// Checks replies only use weights when they are picked by weight,
+/// returning a description of each problem found.
+pub fn selection_problems(replies: &[Reply], selection: Selection) -> Vec<String> {
+    let mut problems = vec![];
+
+    if selection != Selection::Random && replies.iter().any(|reply| reply.weight.get() != 1) {
+        problems.push("reply_messages: weights only matter when selection is random".to_owned());
+    }
+
+    problems
+}
+
+/// Checks replies' embeds and attachments are something Discord would
+/// accept, returning a description of each problem found.
+pub fn content_problems(replies: &[Reply]) -> Vec<String> {
+    let mut problems = vec![];
+
+    for reply in replies {
+        if let Some(embed) = &reply.embed {
+            for embed_problem in embed.problems() {
+                problems.push(format!("reply_messages: embed {}", embed_problem));
+            }
+        }
+
+        if reply.attachments.len() > MAX_ATTACHMENTS {
+            problems.push(format!(
+                "reply_messages: can have at most {} attachments",
+                MAX_ATTACHMENTS
+            ));
+        }
+
+        for attachment in &reply.attachments {
+            // attachments are only ever read from the media directory,
+            // never from wherever else the bot can read
+            if !attachment
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                problems.push(format!(
+                    "reply_messages: attachment {} must be a relative path within the media \
+                        directory",
+                    attachment.display()
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Picks one of the replies, weighted by their weights.
+pub fn choose<'a>(replies: &'a [Reply], random: &dyn Random) -> Option<&'a Reply> {
+    let weights = replies
+        .iter()
+        .map(|reply| u64::from(reply.weight.get()))
+        .collect::<Vec<_>>();
+
+    random.weighted_index(&weights).map(|i| &replies[i])
+}
+
+impl Reply {
+    /// The text and every template in the embed.
+    pub fn templates(&self) -> impl Iterator<Item = &Template> {
+        std::iter::once(&self.text).chain(self.embed.iter().flat_map(Embed::templates))
+    }
+
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.templates().flat_map(Template::placeholders)
+    }
+
+    /// Whether filling in this reply needs the guild's name, see
+    /// [`Template::uses_guild`].
+    pub fn uses_guild(&self) -> bool {
+        self.templates().any(Template::uses_guild)
+    }
+
+    /// Fills in this reply and reads its attachments from the media
+    /// directory. An attachment which can't be read is logged and left
+    /// out.
+    pub async fn render(&self, values: &TemplateValues<'_>, media_dir: &Path) -> RenderedReply {
+        let mut content = self.text.render(values);
+        let embeds = self
+            .embed
+            .iter()
+            .map(|embed| embed.render(values))
+            .collect();
+        let mut attachments = vec![];
+
+        if let Some(video) = self.embed.as_ref().and_then(Embed::video) {
+            if !content.is_empty() {
+                content.push('\n');
+            }
+
+            content.push_str(video);
+        }
+
+        for attachment in &self.attachments {
+            let path = media_dir.join(attachment);
+
+            match CreateAttachment::path(&path).await {
+                Ok(attachment) => attachments.push(attachment),
+                Err(e) => log::error!(
+                    "Failed to read attachment {} with error {:#?}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        RenderedReply {
+            content,
+            embeds,
+            attachments,
+            components: vec![],
+        }
+    }
+}
+
+impl RenderedReply {
+    /// A reply which is just an embed and the components under it.
+    pub fn from_embed(embed: CreateEmbed, components: Vec<CreateActionRow>) -> Self {
+        Self {
+            content: String::new(),
+            embeds: vec![embed],
+            attachments: vec![],
+            components,
+        }
+    }
+
+    /// Adds another reply to the end of this one if the two fit in one
+    /// message, or gives it back to be sent on its own if they don't.
+    /// Replies with components are never combined, as updating their
+    /// message would change the other reply's embeds too.
+    pub fn combine(&mut self, other: RenderedReply) -> Option<RenderedReply> {
+        let separator = match self.content.is_empty() || other.content.is_empty() {
+            true => 0,
+            false => 1,
+        };
+        let fits = self.components.is_empty()
+            && other.components.is_empty()
+            && self.content.chars().count() + separator + other.content.chars().count()
+                <= MAX_CONTENT_CHARS
+            && self.embeds.len() + other.embeds.len() <= MAX_EMBEDS
+            && self.attachments.len() + other.attachments.len() <= MAX_ATTACHMENTS;
+
+        if !fits {
+            return Some(other);
+        }
+
+        if separator > 0 {
+            self.content.push('\n');
+        }
+
+        self.content.push_str(&other.content);
+        self.embeds.extend(other.embeds);
+        self.attachments.extend(other.attachments);
+
+        None
+    }
+
+    pub fn message(self) -> CreateMessage {
+        let mut message = CreateMessage::new()
+            .add_files(self.attachments)
+            .components(self.components);
+
+        if !self.content.is_empty() {
+            message = message.content(self.content);
+        }
+
+        message.embeds(self.embeds)
+    }
+
+    pub fn interaction_response(self) -> CreateInteractionResponseMessage {
+        let mut response = CreateInteractionResponseMessage::new()
+            .add_files(self.attachments)
+            .components(self.components);
+
+        if !self.content.is_empty() {
+            response = response.content(self.content);
+        }
+
+        response.embeds(self.embeds)
+    }
+
+    /// Finishes a deferred response with this reply.
+    pub fn edit_response(self) -> EditInteractionResponse {
+        let mut edit = self.attachments.into_iter().fold(
+            EditInteractionResponse::new().components(self.components),
+            EditInteractionResponse::new_attachment,
+        );
+
+        if !self.content.is_empty() {
+            edit = edit.content(self.content);
+        }
+
+        edit.embeds(self.embeds)
+    }
+
+    pub fn followup(self) -> CreateInteractionResponseFollowup {
+        let mut followup = CreateInteractionResponseFollowup::new()
+            .add_files(self.attachments)
+            .components(self.components);
+
+        if !self.content.is_empty() {
+            followup = followup.content(self.content);
+        }
+
+        followup.embeds(self.embeds)
+    }
+}
+
+impl<'de> Deserialize<'de> for Reply {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ReplyVisitor;
+
+        impl<'de> Visitor<'de> for ReplyVisitor {
+            type Value = Reply;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a reply message or a map with text, embed, attachments and weight")
+            }
+
+            fn visit_str<E: DeError>(self, text: &str) -> Result<Reply, E> {
+                Ok(Reply {
+                    text: text.parse().map_err(E::custom)?,
+                    embed: None,
+                    attachments: vec![],
+                    weight: default_weight(),
+                })
+            }
+
+            // scalars YAML doesn't consider strings are still fine as
+            // replies, just as they were when replies were plain strings
+            fn visit_bool<E: DeError>(self, v: bool) -> Result<Reply, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_i64<E: DeError>(self, v: i64) -> Result<Reply, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_u64<E: DeError>(self, v: u64) -> Result<Reply, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_f64<E: DeError>(self, v: f64) -> Result<Reply, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Reply, A::Error> {
+                let reply = ReplyDefinition::deserialize(MapAccessDeserializer::new(map))?;
+
+                if reply.text.is_empty() && reply.embed.is_none() && reply.attachments.is_empty() {
+                    return Err(A::Error::custom(
+                        "a reply needs text, an embed or attachments",
+                    ));
+                }
+
+                Ok(Reply {
+                    text: reply.text,
+                    embed: reply.embed,
+                    attachments: reply.attachments,
+                    weight: reply.weight,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ReplyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use serenity::all::{CreateActionRow, CreateEmbed};
+
+    use super::{choose, content_problems, selection_problems, RenderedReply, Reply, Selection};
+    use crate::{random::tests::FixedRandom, template::TemplateValues};
+
+    #[test]
+    fn reply_deserialization() {
+        let yaml = r#"---
+        - plain
+        - text: heavy
+          weight: 3
+        - 42"#;
+        let replies: Vec<Reply> = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!("42", replies[2].text.to_string());
+        assert_eq!(1, replies[0].weight.get());
+        assert_eq!(3, replies[1].weight.get());
+        assert_eq!(
+            "heavy",
+            choose(&replies, &FixedRandom(0.2))
+                .unwrap()
+                .text
+                .to_string()
+        );
+        assert_eq!(
+            "plain",
+            choose(&replies, &FixedRandom(0.0))
+                .unwrap()
+                .text
+                .to_string()
+        );
+        assert_eq!(
+            "42",
+            choose(&replies, &FixedRandom(0.9))
+                .unwrap()
+                .text
+                .to_string()
+        );
+        assert!(choose(&[], &FixedRandom(0.0)).is_none());
+    }
+
+    #[test]
+    fn reply_selection_problems() {
+        let replies: Vec<Reply> = serde_yaml::from_str("[foo, { text: bar, weight: 2 }]").unwrap();
+        assert!(selection_problems(&replies, Selection::Random).is_empty());
+        assert_eq!(1, selection_problems(&replies, Selection::Shuffle).len());
+        assert!(selection_problems(&replies[..1], Selection::Sequential).is_empty());
+    }
+
+    #[test]
+    fn reply_bad_weights() {
+        assert!(serde_yaml::from_str::<Reply>("{ text: foo, weight: 0 }").is_err());
+        assert!(serde_yaml::from_str::<Reply>("{ text: foo, wieght: 2 }").is_err());
+        assert!(serde_yaml::from_str::<Reply>("\"{unclosed\"").is_err());
+    }
+
+    #[tokio::test]
+    async fn reply_embeds_and_attachments() {
+        let yaml = r#"---
+        - text: "{guild} fax"
+          attachments: mysteriousbot.yml
+        - embed:
+            description: "{author} sent a fax"
+            video: https://example.com/fax.mp4
+          weight: 2
+        - attachments: [../db/mysteriousbot.sqlite3, /etc/passwd]
+        - attachments: missing.png"#;
+        let replies: Vec<Reply> = serde_yaml::from_str(yaml).unwrap();
+        assert!(replies[0].uses_guild());
+        assert!(!replies[1].uses_guild());
+        assert_eq!(1, replies[1].placeholders().count());
+        assert_eq!(2, content_problems(&replies).len());
+
+        let values = TemplateValues {
+            author: "Peeky".to_owned(),
+            author_mention: "<@1>".to_owned(),
+            channel: "<#2>".to_owned(),
+            guild: Some("A0RA".to_owned()),
+            indexed: vec![],
+            named: HashMap::new(),
+            counter: &|_| 0,
+        };
+        let media_dir = Path::new("config");
+
+        let rendered = replies[0].render(&values, media_dir).await;
+        assert_eq!("A0RA fax", rendered.content);
+        assert!(rendered.embeds.is_empty());
+        assert_eq!("mysteriousbot.yml", rendered.attachments[0].filename);
+
+        // the video can't go in the embed, so it goes in the message
+        let rendered = replies[1].render(&values, media_dir).await;
+        assert_eq!("https://example.com/fax.mp4", rendered.content);
+        assert_eq!(1, rendered.embeds.len());
+
+        // files which can't be read are left out
+        let rendered = replies[3].render(&values, media_dir).await;
+        assert!(rendered.attachments.is_empty());
+    }
+
+    #[test]
+    fn rendered_replies_combine() {
+        let rendered = |content: &str, embeds: usize| RenderedReply {
+            content: content.to_owned(),
+            embeds: vec![CreateEmbed::new(); embeds],
+            attachments: vec![],
+            components: vec![],
+        };
+
+        let mut combined = rendered("fax", 1);
+        assert!(combined.combine(rendered("", 2)).is_none());
+        assert!(combined.combine(rendered("more fax", 0)).is_none());
+        assert_eq!("fax\nmore fax", combined.content);
+        assert_eq!(3, combined.embeds.len());
+
+        // too many embeds for one message
+        let overflow = combined.combine(rendered("", 8)).unwrap();
+        assert_eq!(8, overflow.embeds.len());
+        assert!(combined.combine(rendered(&"x".repeat(1991), 0)).is_some());
+        assert!(combined
+            .combine(RenderedReply::from_embed(CreateEmbed::new(), vec![]))
+            .is_none());
+
+        let leaderboard =
+            RenderedReply::from_embed(CreateEmbed::new(), vec![CreateActionRow::Buttons(vec![])]);
+        assert!(combined.combine(leaderboard).is_some());
+    }
+
+    #[test]
+    fn reply_needs_content() {
+        assert!(serde_yaml::from_str::<Reply>("{ weight: 2 }").is_err());
+        assert!(serde_yaml::from_str::<Reply>("{ embed: { titel: fax } }").is_err());
+        assert!(serde_yaml::from_str::<Reply>("\"\"").is_ok());
+    }
+}
@@ -0,0 +1,428 @@
+use std::future::Future;
+
+use serenity::{
+    all::{
+        CreateInteractionResponse, CreateInteractionResponseFollowup,
+        CreateInteractionResponseMessage, EditInteractionResponse,
+    },
+    model::{
+        application::{CommandInteraction, ComponentInteraction},
+        channel::Message,
+        id::InteractionId,
+    },
+    Error as DiscordError,
+};
+use tokio::time::{self, Duration, Instant};
+
+use crate::{discord::Discord, reply::RenderedReply};
+
+/// How long a response can take before it's deferred, leaving time to
+/// spare before Discord's three second deadline.
+const DEFER_AFTER: Duration = Duration::from_millis(1500);
+
+/// What a response says if something went wrong and nothing came of
+/// it.
+const FAILED_CONTENT: &str = "Sorry, something went wrong.";
+
+/// What a response says if all went well but there's nothing to reply
+/// with.
+const DONE_CONTENT: &str = "Done.";
+
+/// What came of working out a response.
+pub enum Outcome {
+    /// Something to reply with.
+    Reply(RenderedReply),
+    /// Everything was done but there's nothing to reply with.
+    Done,
+    /// Something went wrong, which has already been logged, and there's
+    /// nothing to reply with.
+    Failed,
+}
+
+impl From<Option<RenderedReply>> for Outcome {
+    fn from(reply: Option<RenderedReply>) -> Self {
+        match reply {
+            Some(reply) => Outcome::Reply(reply),
+            None => Outcome::Failed,
+        }
+    }
+}
+
+/// The interaction being responded to.
+#[derive(Clone, Copy)]
+pub enum Responding<'a> {
+    /// Answered with a message of its own.
+    Command(&'a CommandInteraction),
+    /// Answered by updating the message the component is on.
+    Component(&'a ComponentInteraction),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseState {
+    Pending,
+    Deferred,
+    Sent,
+}
+
+/// Responds to an interaction, deferring the response if it isn't
+/// ready in time and then finishing it by editing the deferred
+/// response. Everything sent after the first response is sent as a
+/// follow-up.
+pub struct Responder<'a> {
+    discord: &'a dyn Discord,
+    responding: Responding<'a>,
+    ephemeral: bool,
+    /// When the response gets deferred if it hasn't been sent.
+    defer_at: Instant,
+    state: ResponseState,
+}
+
+impl<'a> Responder<'a> {
+    /// Starts the clock on responding, so it should be made as soon as
+    /// the interaction arrives.
+    pub fn new(discord: &'a dyn Discord, responding: Responding<'a>, ephemeral: bool) -> Self {
+        Self {
+            discord,
+            responding,
+            ephemeral,
+            defer_at: Instant::now() + DEFER_AFTER,
+            state: ResponseState::Pending,
+        }
+    }
+
+    /// Sends the reply `reply` comes up with, deferring the response
+    /// first if it isn't ready in time. Nothing is sent if it comes up
+    /// with nothing, besides saying whether it went well in place of a
+    /// response Discord is still waiting on.
+    pub async fn respond(&mut self, reply: impl Future<Output = impl Into<Outcome>>) {
+        tokio::pin!(reply);
+
+        let outcome = match self.state {
+            ResponseState::Pending => match time::timeout_at(self.defer_at, &mut reply).await {
+                Ok(reply) => reply,
+                Err(_) => {
+                    self.defer().await;
+                    reply.await
+                }
+            },
+            _ => reply.await,
+        };
+        let content = match outcome.into() {
+            Outcome::Reply(reply) => Ok(reply),
+            Outcome::Done => Err(DONE_CONTENT),
+            Outcome::Failed => Err(FAILED_CONTENT),
+        };
+
+        let result = match (self.state, content, self.responding) {
+            (ResponseState::Pending, Ok(reply), _) => {
+                self.create_response(self.initial_response(reply)).await
+            }
+            (ResponseState::Pending, Err(content), _) => {
+                self.create_response(CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(content)
+                        .ephemeral(true),
+                ))
+                .await
+            }
+            (ResponseState::Deferred, Ok(reply), _) => {
+                self.edit_response(reply.edit_response()).await
+            }
+            (ResponseState::Deferred, Err(content), Responding::Command(_)) => {
+                self.edit_response(EditInteractionResponse::new().content(content))
+                    .await
+            }
+            (ResponseState::Sent, Ok(reply), _) => {
+                self.create_followup(reply.followup().ephemeral(self.ephemeral))
+                    .await
+            }
+            _ => return,
+        };
+
+        self.state = ResponseState::Sent;
+
+        if let Err(e) = result {
+            log::error!("Failed to respond to interaction with error {:#?}", e);
+        }
+    }
+
+    /// The message sent in response, once there is one.
+    pub async fn message(&self) -> Option<Message> {
+        if self.state != ResponseState::Sent {
+            return None;
+        }
+
+        match self.responding {
+            Responding::Command(command) => match self.discord.get_response(&command.token).await {
+                Ok(message) => Some(message),
+                Err(e) => {
+                    log::error!("Failed to get interaction response with error {:#?}", e);
+                    None
+                }
+            },
+            Responding::Component(component) => Some(*component.message.clone()),
+        }
+    }
+
+    /// The id and token of the interaction being responded to.
+    fn interaction(&self) -> (InteractionId, &str) {
+        match self.responding {
+            Responding::Command(command) => (command.id, &command.token),
+            Responding::Component(component) => (component.id, &component.token),
+        }
+    }
+
+    async fn defer(&mut self) {
+        let defer = match self.responding {
+            Responding::Command(_) => CreateInteractionResponse::Defer(
+                CreateInteractionResponseMessage::new().ephemeral(self.ephemeral),
+            ),
+            Responding::Component(_) => CreateInteractionResponse::Acknowledge,
+        };
+
+        if let Err(e) = self.create_response(defer).await {
+            log::error!("Failed to defer interaction response with error {:#?}", e);
+        }
+
+        self.state = ResponseState::Deferred;
+    }
+
+    fn initial_response(&self, reply: RenderedReply) -> CreateInteractionResponse {
+        match self.responding {
+            Responding::Command(_) => CreateInteractionResponse::Message(
+                reply.interaction_response().ephemeral(self.ephemeral),
+            ),
+            Responding::Component(_) => {
+                CreateInteractionResponse::UpdateMessage(reply.interaction_response())
+            }
+        }
+    }
+
+    async fn create_response(
+        &self,
+        response: CreateInteractionResponse,
+    ) -> Result<(), DiscordError> {
+        let (interaction_id, token) = self.interaction();
+
+        self.discord
+            .create_response(interaction_id, token, response)
+            .await
+    }
+
+    async fn edit_response(&self, edit: EditInteractionResponse) -> Result<(), DiscordError> {
+        let (_, token) = self.interaction();
+
+        self.discord.edit_response(token, edit).await.map(|_| ())
+    }
+
+    async fn create_followup(
+        &self,
+        followup: CreateInteractionResponseFollowup,
+    ) -> Result<(), DiscordError> {
+        let (_, token) = self.interaction();
+
+        self.discord
+            .create_followup(token, followup)
+            .await
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use serenity::all::CreateEmbed;
+    use tokio::time::{self, Duration, Instant};
+
+    use super::{Outcome, Responder, Responding, DONE_CONTENT, FAILED_CONTENT};
+    use crate::{
+        discord::tests::Call,
+        handler::tests::{command_with, component, discord},
+        reply::RenderedReply,
+    };
+
+    fn reply(description: &str) -> Option<RenderedReply> {
+        Some(RenderedReply::from_embed(
+            CreateEmbed::new().description(description),
+            vec![],
+        ))
+    }
+
+    /// A reply which takes a moment longer than the responder waits
+    /// before deferring, which is made to be no time at all.
+    async fn slow_reply(description: &str) -> Option<RenderedReply> {
+        time::sleep(Duration::from_millis(10)).await;
+        reply(description)
+    }
+
+    /// The type and the data of an interaction response.
+    fn created(call: &Call) -> (u64, &Value) {
+        match call {
+            Call::CreateResponse { response, .. } => {
+                (response["type"].as_u64().unwrap(), &response["data"])
+            }
+            call => panic!("expected a response, got {:?}", call),
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_then_followups() {
+        let discord = discord();
+        let command = command_with("fish", json!([])).command().unwrap();
+        let mut responder = Responder::new(&discord, Responding::Command(&command), true);
+
+        responder.respond(async { reply("first") }).await;
+        responder.respond(async { reply("second") }).await;
+        responder.respond(async { None }).await;
+        assert!(responder.message().await.is_some());
+
+        let calls = discord.take_calls();
+        assert_eq!(2, calls.len(), "{:?}", calls);
+        let (kind, data) = created(&calls[0]);
+        assert_eq!(4, kind);
+        assert_eq!(json!("first"), data["embeds"][0]["description"]);
+        assert_eq!(json!(64), data["flags"]);
+        match &calls[1] {
+            Call::CreateFollowup { followup } => {
+                assert_eq!(json!("second"), followup["embeds"][0]["description"]);
+                assert_eq!(json!(64), followup["flags"]);
+            }
+            call => panic!("expected a followup, got {:?}", call),
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_responses_are_deferred() {
+        let discord = discord();
+        let command = command_with("fish", json!([])).command().unwrap();
+        let mut responder = Responder::new(&discord, Responding::Command(&command), false);
+        responder.defer_at = Instant::now();
+
+        assert!(responder.message().await.is_none());
+        responder.respond(slow_reply("slow")).await;
+        assert!(responder.message().await.is_some());
+
+        let calls = discord.take_calls();
+        assert_eq!(2, calls.len(), "{:?}", calls);
+        assert_eq!(5, created(&calls[0]).0);
+        match &calls[1] {
+            Call::EditResponse { response } => {
+                assert_eq!(json!("slow"), response["embeds"][0]["description"])
+            }
+            call => panic!("expected an edit, got {:?}", call),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_responses_say_so() {
+        // before the deadline
+        let discord = discord();
+        let command = command_with("fish", json!([])).command().unwrap();
+        Responder::new(&discord, Responding::Command(&command), false)
+            .respond(async { None })
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        let (kind, data) = created(&calls[0]);
+        assert_eq!(4, kind);
+        assert_eq!(json!(FAILED_CONTENT), data["content"]);
+        assert_eq!(json!(64), data["flags"]);
+
+        // after it
+        let mut responder = Responder::new(&discord, Responding::Command(&command), false);
+        responder.defer_at = Instant::now();
+        responder
+            .respond(async {
+                time::sleep(Duration::from_millis(10)).await;
+                None
+            })
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(2, calls.len(), "{:?}", calls);
+        assert_eq!(5, created(&calls[0]).0);
+        assert_eq!(
+            Call::EditResponse {
+                response: json!({"content": FAILED_CONTENT})
+            },
+            calls[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn finished_responses_say_so() {
+        // before the deadline
+        let discord = discord();
+        let command = command_with("fish", json!([])).command().unwrap();
+        Responder::new(&discord, Responding::Command(&command), false)
+            .respond(async { Outcome::Done })
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        let (kind, data) = created(&calls[0]);
+        assert_eq!(4, kind);
+        assert_eq!(json!(DONE_CONTENT), data["content"]);
+        assert_eq!(json!(64), data["flags"]);
+
+        // after it
+        let mut responder = Responder::new(&discord, Responding::Command(&command), false);
+        responder.defer_at = Instant::now();
+        responder
+            .respond(async {
+                time::sleep(Duration::from_millis(10)).await;
+                Outcome::Done
+            })
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(2, calls.len(), "{:?}", calls);
+        assert_eq!(5, created(&calls[0]).0);
+        assert_eq!(
+            Call::EditResponse {
+                response: json!({"content": DONE_CONTENT})
+            },
+            calls[1]
+        );
+
+        // and once there's been a response, there's nothing more to say
+        responder.respond(async { Outcome::Done }).await;
+        assert_eq!(Vec::<Call>::new(), discord.take_calls());
+    }
+
+    #[tokio::test]
+    async fn components_update_their_message() {
+        let discord = discord();
+        let component = component("leaderboard:week:10:10:1:fish")
+            .message_component()
+            .unwrap();
+
+        Responder::new(&discord, Responding::Component(&component), false)
+            .respond(async { reply("page 2") })
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        let (kind, data) = created(&calls[0]);
+        assert_eq!(7, kind);
+        assert_eq!(json!("page 2"), data["embeds"][0]["description"]);
+
+        // a deferred update is left as it was if nothing came of it
+        let mut responder = Responder::new(&discord, Responding::Component(&component), false);
+        responder.defer_at = Instant::now();
+        responder
+            .respond(async {
+                time::sleep(Duration::from_millis(10)).await;
+                None
+            })
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        assert_eq!(6, created(&calls[0]).0);
+
+        Responder::new(&discord, Responding::Component(&component), false)
+            .respond(async { None })
+            .await;
+        let calls = discord.take_calls();
+        assert_eq!(1, calls.len(), "{:?}", calls);
+        let (kind, data) = created(&calls[0]);
+        assert_eq!(4, kind);
+        assert_eq!(json!(FAILED_CONTENT), data["content"]);
+    }
+}
@@ -0,0 +1,253 @@
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+use serenity::model::id::GuildId;
+use snafu::ResultExt;
+
+use crate::{
+    random::Random,
+    reply::{self, Reply, Selection},
+    store::{DbSnafu, PoolSnafu, Result},
+};
+
+/// Keeps where each command and autoresponder is in its rotation of
+/// replies, per guild, so that restarting doesn't start them over.
+pub struct RotationStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl RotationStore {
+    /// Keeps rotations in the given pool's `rotations` table.
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    /// Picks the reply to send by the given selection. A rotation which
+    /// can't be read or written is logged, and a reply picked at random.
+    pub fn pick<'a>(
+        &self,
+        guild_id: GuildId,
+        owner: &str,
+        replies: &'a [Reply],
+        selection: Selection,
+        random: &dyn Random,
+    ) -> Option<&'a Reply> {
+        if selection == Selection::Random || replies.is_empty() {
+            return reply::choose(replies, random);
+        }
+
+        match self.next(guild_id, owner, replies.len(), selection, random) {
+            Ok(i) => replies.get(i),
+            Err(e) => {
+                log::error!(
+                    "Failed to advance rotation for {} in guild {} with error {:#?}",
+                    owner,
+                    guild_id,
+                    e
+                );
+                reply::choose(replies, random)
+            }
+        }
+    }
+
+    /// Advances an owner's rotation through `count` replies, returning
+    /// the index of the reply it landed on.
+    fn next(
+        &self,
+        guild_id: GuildId,
+        owner: &str,
+        count: usize,
+        selection: Selection,
+        random: &dyn Random,
+    ) -> Result<usize> {
+        let mut connection = self.get_connection()?;
+        let tx = connection.transaction().context(DbSnafu)?;
+        let (mut sequence, mut position): (Vec<usize>, usize) = tx
+            .query_row(
+                "SELECT sequence, position FROM rotations WHERE guild_id = ? AND owner = ?;",
+                params![guild_id.get(), owner],
+                |row| Ok((parse_sequence(&row.get::<_, String>(0)?), row.get(1)?)),
+            )
+            .optional()
+            .context(DbSnafu)?
+            .unwrap_or_default();
+
+        let index = match selection {
+            Selection::Sequential => {
+                sequence.clear();
+                position %= count;
+                position
+            }
+            _ => {
+                // a new bag once this one is used up, or if the replies
+                // changed underneath it
+                if position >= count || !is_permutation(&sequence, count) {
+                    let last = position
+                        .checked_sub(1)
+                        .and_then(|last| sequence.get(last))
+                        .copied();
+
+                    sequence = shuffled(count, last, random);
+                    position = 0;
+                }
+
+                sequence[position]
+            }
+        };
+
+        tx.execute(
+            "INSERT OR REPLACE INTO rotations (guild_id, owner, sequence, position) \
+                VALUES(?, ?, ?, ?);",
+            params![
+                guild_id.get(),
+                owner,
+                sequence
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                position + 1,
+            ],
+        )
+        .context(DbSnafu)?;
+        tx.commit().context(DbSnafu)?;
+
+        Ok(index)
+    }
+
+    fn get_connection(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        self.pool.get().context(PoolSnafu)
+    }
+}
+
+fn parse_sequence(sequence: &str) -> Vec<usize> {
+    sequence
+        .split(',')
+        .map_while(|index| index.parse().ok())
+        .collect()
+}
+
+fn is_permutation(sequence: &[usize], count: usize) -> bool {
+    let mut sorted = sequence.to_vec();
+
+    sorted.sort_unstable();
+    sorted.into_iter().eq(0..count)
+}
+
+/// Every index below `count` in a random order, which doesn't start
+/// with `last` so the reply a bag ended on isn't sent twice in a row.
+fn shuffled(count: usize, last: Option<usize>, random: &dyn Random) -> Vec<usize> {
+    let mut sequence = (0..count).collect::<Vec<_>>();
+
+    for i in (1..count).rev() {
+        sequence.swap(i, random.below(i + 1));
+    }
+
+    if count > 1 && sequence.first() == last.as_ref() {
+        sequence.swap(0, 1 + random.below(count - 1));
+    }
+
+    sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use serenity::model::id::GuildId;
+
+    use super::RotationStore;
+    use crate::{
+        counter::tests::memory_pool,
+        migrations::{self, Settings},
+        random::tests::SeededRandom,
+        reply::{Reply, Selection},
+    };
+
+    fn replies(count: usize) -> Vec<Reply> {
+        serde_yaml::from_str(&format!("{:?}", (0..count).collect::<Vec<_>>())).unwrap()
+    }
+
+    fn picks(
+        store: &RotationStore,
+        replies: &[Reply],
+        selection: Selection,
+        times: usize,
+    ) -> Vec<String> {
+        let random = SeededRandom::new(3);
+
+        (0..times)
+            .map(|_| {
+                store
+                    .pick(GuildId::new(1), "/fax", replies, selection, &random)
+                    .unwrap()
+                    .text
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn store() -> RotationStore {
+        let pool = memory_pool();
+        migrations::run(&pool, &Settings::default()).unwrap();
+        RotationStore::new(pool)
+    }
+
+    #[test]
+    fn sequential_rotation() {
+        let store = store();
+        let replies = replies(3);
+
+        assert_eq!(
+            vec!["0", "1", "2", "0", "1"],
+            picks(&store, &replies, Selection::Sequential, 5)
+        );
+
+        // a quote added to the end carries on from where it was
+        assert_eq!(
+            vec!["2", "3", "0"],
+            picks(&store, &self::replies(4), Selection::Sequential, 3)
+        );
+    }
+
+    #[test]
+    fn shuffle_rotation() {
+        let store = store();
+        let replies = replies(5);
+        let picks = picks(&store, &replies, Selection::Shuffle, 20);
+
+        for bag in picks.chunks(5) {
+            assert_eq!(5, bag.iter().collect::<HashSet<_>>().len(), "{:?}", picks);
+        }
+
+        for pair in picks.windows(2) {
+            assert_ne!(pair[0], pair[1], "{:?}", picks);
+        }
+    }
+
+    #[test]
+    fn rotations_are_per_guild_and_owner() {
+        let store = store();
+        let replies = replies(3);
+        let random = SeededRandom::new(3);
+        let pick = |guild_id, owner| {
+            store
+                .pick(
+                    GuildId::new(guild_id),
+                    owner,
+                    &replies,
+                    Selection::Sequential,
+                    &random,
+                )
+                .unwrap()
+                .text
+                .to_string()
+        };
+
+        assert_eq!("0", pick(1, "/fax"));
+        assert_eq!("1", pick(1, "/fax"));
+        assert_eq!("0", pick(2, "/fax"));
+        assert_eq!("0", pick(1, "/quote"));
+        assert_eq!("2", pick(1, "/fax"));
+    }
+}
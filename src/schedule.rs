@@ -0,0 +1,572 @@
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension};
+use serde::{de::Error as DeError, Deserialize, Deserializer};
+use serde_with::{serde_as, DisplayFromStr};
+use serde_yaml::Value;
+use serenity::model::id::{ChannelId, GuildId};
+use snafu::ResultExt;
+
+use crate::{
+    action::{check_fields, ActionContext, ActionOutput, Actions, Fields},
+    autoresponder::react,
+    config::{Config, GuildConfig},
+    counter::EventSource,
+    discord::Discord,
+    handler::Services,
+    options::Arguments,
+    store::{DbSnafu, PoolSnafu, Result},
+};
+
+/// How late a post missed while the bot was down can still be made.
+/// Anything later is skipped, as a Monday post on a Tuesday is worse
+/// than none at all.
+const MISSED_GRACE_MINUTES: i64 = 10;
+
+/// The longest the scheduler sleeps, so that schedules added by a
+/// config reload are picked up soon after.
+const MAX_SLEEP_SECONDS: i64 = 60;
+
+/// A post made on a schedule rather than in response to anyone.
+#[derive(Debug)]
+pub struct Schedule {
+    /// Names the schedule, so when it last ran is remembered across
+    /// restarts.
+    pub id: String,
+    /// When to post, as a five field cron expression such as
+    /// `0 9 * * MON`.
+    pub cron: Cron,
+    /// The timezone the cron expression is read in, UTC by default.
+    pub timezone: Tz,
+    /// Where to post.
+    pub channel: u64,
+    pub action: Actions,
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+struct ScheduleParts {
+    id: String,
+    cron: String,
+    #[serde(default = "default_timezone")]
+    #[serde_as(as = "DisplayFromStr")]
+    timezone: Tz,
+    channel: u64,
+    #[serde(flatten)]
+    action: Actions,
+}
+
+/// Every key a schedule understands.
+static FIELDS: Fields = Fields::with_actions(&["id", "cron", "timezone", "channel"]);
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let definition = Value::deserialize(deserializer)?;
+
+        check_fields(&definition, FIELDS.all())?;
+
+        let parts = ScheduleParts::deserialize(definition).map_err(D::Error::custom)?;
+        let cron = Cron::new(&parts.cron)
+            .parse()
+            .map_err(|e| D::Error::custom(format!("cron {}: {}", parts.cron, e)))?;
+
+        Ok(Self {
+            id: parts.id,
+            cron,
+            timezone: parts.timezone,
+            channel: parts.channel,
+            action: parts.action,
+        })
+    }
+}
+
+impl Schedule {
+    /// The first time this schedule fires after the given time.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.cron
+            .find_next_occurrence(&after.with_timezone(&self.timezone), false)
+            .ok()
+            .map(|next| next.with_timezone(&Utc))
+    }
+
+    /// The next `count` times this schedule fires after the given time,
+    /// in its own timezone.
+    pub fn upcoming(&self, after: DateTime<Utc>, count: usize) -> Vec<DateTime<Tz>> {
+        self.cron
+            .iter_after(after.with_timezone(&self.timezone))
+            .take(count)
+            .collect()
+    }
+
+    /// The time this schedule should post for now, if it's due, given
+    /// when it last ran. Only the latest run missed within the grace
+    /// period is made up, so it never posts twice to catch up.
+    pub fn due(&self, last_run: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let after = last_run.max(now - Duration::minutes(MISSED_GRACE_MINUTES));
+        let mut due = None;
+
+        while let Some(next) = self.next_after(due.unwrap_or(after)) {
+            if next > now {
+                break;
+            }
+
+            due = Some(next);
+        }
+
+        due
+    }
+
+    /// Checks this schedule only does what can be done without anyone
+    /// to do it for, returning a description of each problem found.
+    pub fn action_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let action = &self.action;
+        let acts_for_someone = [
+            ("counter", action.increment.is_some()),
+            ("decrement_counter", action.decrement.is_some()),
+            ("add_roles", action.add_roles.is_some()),
+            ("remove_roles", action.remove_roles.is_some()),
+            ("counter_leaderboard", action.leaderboard.is_some()),
+            ("dm_user", action.dm_user.is_some()),
+        ];
+
+        for (key, used) in acts_for_someone {
+            if used {
+                problems.push(format!(
+                    "{} needs someone to act for, which schedules lack",
+                    key
+                ));
+            }
+        }
+
+        if action.reply.is_none() && action.send_to_channel.is_none() {
+            problems.push("posts nothing, give it reply_messages".to_owned());
+        }
+
+        if action.react.is_some() && action.reply.is_none() {
+            problems.push("twemojis need reply_messages to react to".to_owned());
+        }
+
+        problems.extend(action.problems());
+        problems
+    }
+
+    /// Checks the counters this schedule's posts show, the bot's own
+    /// counts, are among the guild's `counters`.
+    pub fn counter_problems(&self, counters: &[&str]) -> Vec<String> {
+        self.action.counter_problems(counters)
+    }
+
+    /// Runs this schedule's actions for the bot itself, going by
+    /// `bot_name`, posting what they reply with in the schedule's channel
+    /// and reacting to the first post.
+    async fn post(
+        &self,
+        discord: &dyn Discord,
+        services: &Services,
+        bot_name: &str,
+        guild_id: GuildId,
+        guild_config: &GuildConfig,
+    ) {
+        let subject = discord.current_user_id();
+        let subject_name = bot_name.to_owned();
+        let channel_id = ChannelId::new(self.channel);
+        let arguments = Arguments::default();
+        let context = ActionContext {
+            discord,
+            services,
+            guild_config,
+            guild_id,
+            subject,
+            subject_name,
+            channel_id,
+            source: EventSource {
+                channel_id,
+                message_id: None,
+                origin: None,
+            },
+            rotation_key: format!("schedule:{}", self.id),
+            indexed: vec![],
+            named: Default::default(),
+            arguments: &arguments,
+        };
+        let mut output = ActionOutput::default();
+
+        self.action.run(&context, &mut output).await;
+
+        let mut posted = vec![];
+
+        for reply in output.replies {
+            match discord.send_message(channel_id, reply.message()).await {
+                Ok(message) => posted.push(message),
+                Err(e) => log::error!(
+                    "Failed to post schedule {} in guild {} with error {:#?}",
+                    self.id,
+                    guild_id,
+                    e
+                ),
+            }
+        }
+
+        if let Some(message) = posted.first() {
+            for twemoji in &output.twemojis {
+                react(&services.emoji_cache, discord, &guild_id, message, twemoji).await;
+            }
+        }
+    }
+}
+
+fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+/// Keeps when each schedule last ran, per guild, so that restarting
+/// doesn't post anything twice.
+pub struct ScheduleStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl ScheduleStore {
+    /// Keeps when each schedule last ran in the given pool's
+    /// `schedule_runs` table.
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    /// When a schedule last ran, or was first seen if it never has.
+    fn last_run(&self, guild_id: GuildId, id: &str) -> Result<Option<DateTime<Utc>>> {
+        self.get_connection()?
+            .query_row(
+                "SELECT last_run FROM schedule_runs WHERE guild_id = ? AND schedule = ?;",
+                params![guild_id.get(), id],
+                |row| row.get(0),
+            )
+            .optional()
+            .context(DbSnafu)
+    }
+
+    fn save(&self, guild_id: GuildId, id: &str, last_run: DateTime<Utc>) -> Result<()> {
+        self.get_connection()?
+            .execute(
+                "INSERT OR REPLACE INTO schedule_runs (guild_id, schedule, last_run) \
+                    VALUES(?, ?, ?);",
+                params![guild_id.get(), id, last_run],
+            )
+            .context(DbSnafu)
+            .map(|_| ())
+    }
+
+    fn get_connection(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        self.pool.get().context(PoolSnafu)
+    }
+}
+
+/// Posts every guild's schedules as they come due, forever. The config
+/// is looked at afresh each time, so reloads take effect, but the bot's
+/// own name is only looked up once.
+pub async fn run(discord: impl Discord, config: Arc<RwLock<Arc<Config>>>, services: Arc<Services>) {
+    log::info!("Running schedules");
+
+    let bot_name = match discord.user(discord.current_user_id()).await {
+        Ok(user) => user.display_name().to_owned(),
+        Err(e) => {
+            log::error!("Failed to get the bot's own user with error {:#?}", e);
+            String::new()
+        }
+    };
+
+    loop {
+        let now = Utc::now();
+        let config = config.read().unwrap().clone();
+        let wake_at = tick(&discord, &config, &services, &bot_name, now).await;
+        let sleep = wake_at
+            .unwrap_or(now + Duration::seconds(MAX_SLEEP_SECONDS))
+            .min(now + Duration::seconds(MAX_SLEEP_SECONDS))
+            - Utc::now();
+
+        tokio::time::sleep(sleep.to_std().unwrap_or_default()).await;
+    }
+}
+
+/// Posts every schedule which is due, returning when the next one is.
+/// A schedule seen for the first time only starts counting from now.
+pub async fn tick(
+    discord: &dyn Discord,
+    config: &Config,
+    services: &Services,
+    bot_name: &str,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let store = &services.schedule_store;
+    let mut wake_at: Option<DateTime<Utc>> = None;
+
+    for (guild_id, guild_config) in &config.guilds {
+        let guild_id = GuildId::new(*guild_id);
+
+        for schedule in &guild_config.schedules {
+            let last_run = match store.last_run(guild_id, &schedule.id) {
+                Ok(last_run) => last_run,
+                Err(e) => {
+                    log::error!(
+                        "Failed to read last run of schedule {} in guild {} with error {:#?}",
+                        schedule.id,
+                        guild_id,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let run = last_run.and_then(|last_run| schedule.due(last_run, now));
+
+            // saved before posting, as a post which fails halfway is
+            // better missed than made twice
+            if last_run.is_none() || run.is_some() {
+                if let Err(e) = store.save(guild_id, &schedule.id, run.unwrap_or(now)) {
+                    log::error!(
+                        "Failed to save last run of schedule {} in guild {} with error {:#?}",
+                        schedule.id,
+                        guild_id,
+                        e
+                    );
+                    continue;
+                }
+            }
+
+            if run.is_some() {
+                schedule
+                    .post(discord, services, bot_name, guild_id, guild_config)
+                    .await;
+            }
+
+            if let Some(next) = schedule.next_after(now) {
+                wake_at = Some(wake_at.map_or(next, |wake_at| wake_at.min(next)));
+            }
+        }
+    }
+
+    wake_at
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+    use chrono_tz::{Europe::London, Tz};
+    use serde_json::json;
+    use serenity::model::{
+        channel::ReactionType,
+        id::{ChannelId, EmojiId, MessageId},
+    };
+
+    use super::{tick, Schedule};
+    use crate::{
+        config::Config,
+        counter::tests::memory_pool,
+        discord::tests::Call,
+        handler::tests::{config, discord, services},
+    };
+
+    const MONDAY: &str = r#"
+    id: monday
+    cron: 0 9 * * MON
+    timezone: Europe/London
+    channel: 100
+    reply_messages: "{author} says it's Monday"
+    twemojis: swedishfish"#;
+
+    /// A time in October 2026, when London goes from BST to GMT on the
+    /// 25th.
+    fn london(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        London
+            .with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn schedule_deserialization() {
+        let schedule: Schedule = serde_yaml::from_str(MONDAY).unwrap();
+        assert_eq!("monday", schedule.id);
+        assert_eq!(London, schedule.timezone);
+        assert_eq!(100, schedule.channel);
+        assert!(schedule.action.reply.is_some());
+
+        let schedule: Schedule =
+            serde_yaml::from_str("{ id: a, cron: '* * * * *', channel: 1, reply_messages: a }")
+                .unwrap();
+        assert_eq!(Tz::UTC, schedule.timezone);
+
+        let e = serde_yaml::from_str::<Schedule>("{ id: a, cron: '* * * * *', chanel: 1 }")
+            .unwrap_err()
+            .to_string();
+        assert!(e.contains("unknown field `chanel`"), "{}", e);
+
+        let e = serde_yaml::from_str::<Schedule>("{ id: a, cron: '* * *', channel: 1 }")
+            .unwrap_err()
+            .to_string();
+        assert!(e.starts_with("cron * * *: "), "{}", e);
+
+        assert!(serde_yaml::from_str::<Schedule>(
+            "{ id: a, cron: '* * * * *', timezone: Mars/Olympus, channel: 1 }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn schedules_fire_in_their_timezone() {
+        let schedule: Schedule = serde_yaml::from_str(MONDAY).unwrap();
+
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2026, 10, 19, 8, 0, 0).unwrap()),
+            schedule.next_after(london(18, 12, 0))
+        );
+        assert_eq!(
+            vec!["Mon 2026-10-26 09:00 GMT", "Mon 2026-11-02 09:00 GMT"],
+            schedule
+                .upcoming(london(19, 9, 0), 2)
+                .into_iter()
+                .map(|run| run.format("%a %Y-%m-%d %H:%M %Z").to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn schedules_come_due() {
+        let schedule: Schedule = serde_yaml::from_str(MONDAY).unwrap();
+        let sunday = london(18, 12, 0);
+        let monday = london(19, 9, 0);
+
+        assert_eq!(None, schedule.due(sunday, london(19, 8, 59)));
+        assert_eq!(Some(monday), schedule.due(sunday, monday));
+        assert_eq!(None, schedule.due(monday, london(19, 9, 1)));
+        // missed by a restart, but not by much
+        assert_eq!(Some(monday), schedule.due(sunday, london(19, 9, 5)));
+        // missed by too much
+        assert_eq!(None, schedule.due(sunday, london(19, 9, 30)));
+        // only the latest of several missed runs
+        assert_eq!(
+            Some(london(26, 9, 0)),
+            schedule.due(london(11, 12, 0), london(26, 9, 2))
+        );
+    }
+
+    #[test]
+    fn schedule_problems() {
+        let yaml = r#"---
+        guilds:
+          1:
+            schedules:
+              - id: a
+                cron: "* * * * *"
+                channel: 1
+                counter: a
+                twemojis: a
+              - id: a
+                cron: "* * * * *"
+                channel: 1
+                reply_messages: a"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+
+        assert_eq!(
+            vec![
+                "guild 1: schedules[0]: counter needs someone to act for, which schedules lack",
+                "guild 1: schedules[0]: posts nothing, give it reply_messages",
+                "guild 1: schedules[0]: twemojis need reply_messages to react to",
+                "guild 1: schedules[1]: id a is shared with another schedule, give each its own id",
+            ],
+            config.validate()
+        );
+    }
+
+    #[tokio::test]
+    async fn schedules_post_once() {
+        let pool = memory_pool();
+        let config = config(
+            r#"
+    schedules:
+      - id: monday
+        cron: 0 9 * * MON
+        timezone: Europe/London
+        channel: 100
+        reply_messages: "{author} says it's Monday"
+        twemojis: swedishfish"#,
+        );
+        let discord = discord();
+        let running = services(pool.clone());
+
+        // a new schedule only counts from when it's first seen
+        assert_eq!(
+            Some(london(19, 9, 0)),
+            tick(
+                &discord,
+                &config,
+                &running,
+                "mysteriousbot",
+                london(19, 8, 0)
+            )
+            .await
+        );
+        assert_eq!(Vec::<Call>::new(), discord.take_calls());
+
+        assert_eq!(
+            Some(london(26, 9, 0)),
+            tick(
+                &discord,
+                &config,
+                &running,
+                "mysteriousbot",
+                london(19, 9, 0)
+            )
+            .await
+        );
+        let calls = discord.take_calls();
+        assert_eq!(2, calls.len(), "{:?}", calls);
+        match &calls[0] {
+            Call::SendMessage {
+                channel_id,
+                message,
+            } => {
+                assert_eq!(ChannelId::new(100), *channel_id);
+                assert_eq!(json!("mysteriousbot says it's Monday"), message["content"]);
+            }
+            call => panic!("expected a post, got {:?}", call),
+        }
+        assert_eq!(
+            Call::React {
+                channel_id: ChannelId::new(100),
+                message_id: MessageId::new(1),
+                reaction: ReactionType::Custom {
+                    animated: false,
+                    id: EmojiId::new(30),
+                    name: Some("swedishfish".to_owned()),
+                },
+            },
+            calls[1]
+        );
+
+        tick(
+            &discord,
+            &config,
+            &running,
+            "mysteriousbot",
+            london(19, 9, 1),
+        )
+        .await;
+        assert_eq!(Vec::<Call>::new(), discord.take_calls());
+
+        // as if restarted
+        tick(
+            &discord,
+            &config,
+            &services(pool),
+            "mysteriousbot",
+            london(19, 9, 2),
+        )
+        .await;
+        assert_eq!(Vec::<Call>::new(), discord.take_calls());
+    }
+}
@@ -0,0 +1,14 @@
+use r2d2::Error as R2d2Error;
+use rusqlite::Error as RusqliteError;
+use snafu::Snafu;
+
+/// Why something kept in the database couldn't be read or written. Every
+/// store fails the same two ways, so they share this.
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    Pool { source: R2d2Error },
+    Db { source: RusqliteError },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,312 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::{de::Error as DeError, Deserialize, Deserializer};
+use serenity::model::id::GuildId;
+
+use crate::discord::Discord;
+
+/// A reply message with placeholders in braces, which are filled in
+/// when the message is sent. `{{` and `}}` are literal braces.
+///
+/// - `{author}` is the display name of whoever triggered the reply,
+///   `{author.mention}` pings them instead
+/// - `{channel}` mentions the channel and `{guild}` names the guild
+/// - `{1}` or `{name}` are regex captures for autoresponders, and
+///   `{name}` is an option's value for commands
+/// - `{counter:name}` is the author's count in a counter, and
+///   `{counter:name:ordinal}` the same as 1st, 2nd, 3rd...
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placeholder {
+    Author,
+    AuthorMention,
+    Channel,
+    Guild,
+    Indexed(usize),
+    Named(String),
+    Counter { counter: String, ordinal: bool },
+}
+
+/// Everything a template can be filled in with.
+pub struct TemplateValues<'a> {
+    pub author: String,
+    pub author_mention: String,
+    pub channel: String,
+    /// Only looked up when a template uses it, see
+    /// [`Template::uses_guild`].
+    pub guild: Option<String>,
+    pub indexed: Vec<String>,
+    pub named: HashMap<String, String>,
+    /// Looks up the author's count in the named counter.
+    pub counter: &'a (dyn Fn(&str) -> u64 + Sync),
+}
+
+impl Template {
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Placeholder(placeholder) => Some(placeholder),
+            Part::Literal(_) => None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.source.is_empty()
+    }
+
+    /// Whether filling in this template needs the guild's name, which
+    /// costs a trip to Discord.
+    pub fn uses_guild(&self) -> bool {
+        self.placeholders()
+            .any(|placeholder| *placeholder == Placeholder::Guild)
+    }
+
+    pub fn render(&self, values: &TemplateValues) -> String {
+        let mut rendered = String::with_capacity(self.source.len());
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => rendered.push_str(literal),
+                Part::Placeholder(Placeholder::Author) => rendered.push_str(&values.author),
+                Part::Placeholder(Placeholder::AuthorMention) => {
+                    rendered.push_str(&values.author_mention)
+                }
+                Part::Placeholder(Placeholder::Channel) => rendered.push_str(&values.channel),
+                Part::Placeholder(Placeholder::Guild) => {
+                    rendered.push_str(values.guild.as_deref().unwrap_or_default())
+                }
+                Part::Placeholder(Placeholder::Indexed(i)) => {
+                    rendered.push_str(values.indexed.get(*i).map_or("", String::as_str))
+                }
+                Part::Placeholder(Placeholder::Named(name)) => {
+                    rendered.push_str(values.named.get(name).map_or("", String::as_str))
+                }
+                Part::Placeholder(Placeholder::Counter { counter, ordinal }) => {
+                    let count = (values.counter)(counter);
+
+                    rendered.push_str(&count.to_string());
+
+                    if *ordinal {
+                        rendered.push_str(ordinal_suffix(count));
+                    }
+                }
+            }
+        }
+
+        rendered
+    }
+}
+
+/// The name of a guild for `{guild}`, or nothing if Discord won't say.
+pub async fn guild_name(discord: &dyn Discord, guild_id: GuildId) -> Option<String> {
+    match discord.guild_name(guild_id).await {
+        Ok(name) => Some(name),
+        Err(e) => {
+            log::error!(
+                "Failed to get guild info for guild {} with error {:#?}",
+                guild_id,
+                e
+            );
+            None
+        }
+    }
+}
+
+fn ordinal_suffix(n: u64) -> &'static str {
+    match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed {{{} in template", name)),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    parts.push(Part::Placeholder(name.parse()?));
+                }
+                '}' => return Err("unmatched } in template, write }} for a literal }".to_owned()),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            parts,
+        })
+    }
+}
+
+impl FromStr for Placeholder {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let is_identifier = |s: &str| {
+            s.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+
+        Ok(match name.split(':').collect::<Vec<_>>().as_slice() {
+            ["author"] => Placeholder::Author,
+            ["author.mention"] => Placeholder::AuthorMention,
+            ["channel"] => Placeholder::Channel,
+            ["guild"] => Placeholder::Guild,
+            [index] if !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()) => {
+                Placeholder::Indexed(
+                    index
+                        .parse()
+                        .map_err(|_| format!("bad index {{{}}}", name))?,
+                )
+            }
+            [named] if is_identifier(named) => Placeholder::Named(named.to_string()),
+            ["counter", counter] if !counter.is_empty() => Placeholder::Counter {
+                counter: counter.to_string(),
+                ordinal: false,
+            },
+            ["counter", counter, "ordinal"] if !counter.is_empty() => Placeholder::Counter {
+                counter: counter.to_string(),
+                ordinal: true,
+            },
+            _ => return Err(format!("unknown placeholder {{{}}} in template", name)),
+        })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Placeholder, Template, TemplateValues};
+
+    fn values(counter: &(dyn Fn(&str) -> u64 + Sync)) -> TemplateValues<'_> {
+        TemplateValues {
+            author: "Peeky".to_owned(),
+            author_mention: "<@1>".to_owned(),
+            channel: "<#2>".to_owned(),
+            guild: Some("A0RA".to_owned()),
+            indexed: vec!["boats".to_owned(), "boat".to_owned()],
+            named: HashMap::from([("noun".to_owned(), "boat".to_owned())]),
+            counter,
+        }
+    }
+
+    #[test]
+    fn template_plain() {
+        let template: Template = "no placeholders here".parse().unwrap();
+        assert_eq!(0, template.placeholders().count());
+        assert_eq!("no placeholders here", template.render(&values(&|_| 0)));
+    }
+
+    #[test]
+    fn template_placeholders() {
+        let template: Template =
+            "{author} ({author.mention}) said {1}/{noun} in {channel} of {guild} {{literally}}"
+                .parse()
+                .unwrap();
+        assert!(template.uses_guild());
+        assert_eq!(
+            "Peeky (<@1>) said boat/boat in <#2> of A0RA {literally}",
+            template.render(&values(&|_| 0))
+        );
+    }
+
+    #[test]
+    fn template_counters() {
+        let template: Template =
+            "that's your {counter:verbal_morality_statute:ordinal} offense, {author}"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            vec![&Placeholder::Counter {
+                counter: "verbal_morality_statute".to_owned(),
+                ordinal: true
+            }],
+            template.placeholders().take(1).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "that's your 42nd offense, Peeky",
+            template.render(&values(&|_| 42))
+        );
+        assert_eq!(
+            "that's your 11th offense, Peeky",
+            template.render(&values(&|_| 11))
+        );
+        assert_eq!(
+            "that's your 101st offense, Peeky",
+            template.render(&values(&|_| 101))
+        );
+    }
+
+    #[test]
+    fn template_bad() {
+        assert!("{author".parse::<Template>().is_err());
+        assert!("author}".parse::<Template>().is_err());
+        assert!("{}".parse::<Template>().is_err());
+        assert!("{author.name}".parse::<Template>().is_err());
+        assert!("{counter:}".parse::<Template>().is_err());
+        assert!("{counter:foo:cardinal}".parse::<Template>().is_err());
+        assert!(serde_yaml::from_str::<Template>("\"{nope nope}\"").is_err());
+    }
+}